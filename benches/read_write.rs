@@ -7,7 +7,8 @@ mod bench {
 
     use std::fs::{OpenOptions, remove_file};
 
-    use adafruit_gps::GpsSentence;
+    use adafruit_gps::{Gps, GpsSentence};
+    use adafruit_gps::wire_trace::{Direction, ReplayPort, TraceEvent};
     use adafruit_gps::gga::{GgaData, SatFix};
 
     const SENTENCE: GpsSentence = GpsSentence::GGA(GgaData {
@@ -67,6 +68,29 @@ mod bench {
         })
     }
 
+    /// A second of output at 10Hz, as read_line would see it at 115200 baud.
+    fn ten_hz_second() -> Vec<TraceEvent> {
+        let lines = [
+            "$GNGGA,131613.000,5132.7314,N,00005.9099,W,1,9,1.17,42.4,M,47.0,M,,*60\r\n",
+            "$GPGSA,A,3,29,02,26,25,31,14,,,,,,,1.42,1.17,0.80*07\r\n",
+            "$GPGSV,4,3,14,12,12,100,,04,11,331,,16,06,282,,05,05,074,22*75\r\n",
+            "$GPGSV,4,4,14,32,01,215,,41,,,*4F\r\n",
+            "$GNVTG,0.00,T,,M,0.00,N,0.00,K,A*23\r\n",
+        ];
+        (0..10).flat_map(|_| lines.iter()).map(|line| TraceEvent::new(Direction::Received, line)).collect()
+    }
+
+    #[bench]
+    fn bench_read_line(b: &mut Bencher) {
+        let events = ten_hz_second();
+        b.iter(|| {
+            let mut gps = Gps::from_port(Box::new(ReplayPort::new(&events)));
+            for _ in 0..events.len() {
+                gps.read_line();
+            }
+        });
+    }
+
     #[bench]
     fn bench_read(b: &mut Bencher) {
         for _ in 0..VECTOR_SIZE {
@@ -0,0 +1,120 @@
+//! # Datum conversion
+//! Converts coordinates given by the gps in another datum (see pmtk_330_api_set_datum) back to WGS84,
+//! which the rest of geodesy expects.
+//!
+//! ## Method
+//! The position is turned into earth centred x, y, z on the datum's ellipsoid, shifted by the
+//! datum's dx, dy, dz and turned back into latitude, longitude and height on the WGS84 ellipsoid.
+//! This is the three parameter shift NIMA gives, good to a few metres.
+//!
+//! Altitude is taken as height above the ellipsoid. If it is None, 0 is used and the result has no
+//! altitude either.
+
+pub use adafruit_gps::{Datum, DatumInfo, Ellipsoid, DATUMS};
+
+use super::Coordinate;
+
+/// Latitude, longitude (degrees) and height (m) to earth centred x, y, z (m).
+pub fn geodetic_to_ecef(ellipsoid: &Ellipsoid, latitude: f64, longitude: f64, height: f64) -> (f64, f64, f64) {
+    let e_sq = ellipsoid.eccentricity_sq();
+    let (sin_lat, cos_lat) = latitude.to_radians().sin_cos();
+    let (sin_long, cos_long) = longitude.to_radians().sin_cos();
+    // Radius of curvature in the prime vertical.
+    let n = ellipsoid.a / (1.0 - e_sq * sin_lat.powi(2)).sqrt();
+    (
+        (n + height) * cos_lat * cos_long,
+        (n + height) * cos_lat * sin_long,
+        (n * (1.0 - e_sq) + height) * sin_lat,
+    )
+}
+
+/// Earth centred x, y, z (m) to latitude, longitude (degrees) and height (m).
+pub fn ecef_to_geodetic(ellipsoid: &Ellipsoid, x: f64, y: f64, z: f64) -> (f64, f64, f64) {
+    let e_sq = ellipsoid.eccentricity_sq();
+    let p = (x.powi(2) + y.powi(2)).sqrt();
+    let longitude = y.atan2(x);
+    let mut latitude = z.atan2(p * (1.0 - e_sq));
+    let mut height = 0.0;
+    // Converges to well under a millimetre in a few iterations.
+    for _ in 0..10 {
+        let sin_lat = latitude.sin();
+        let n = ellipsoid.a / (1.0 - e_sq * sin_lat.powi(2)).sqrt();
+        height = p / latitude.cos() - n;
+        latitude = z.atan2(p * (1.0 - e_sq * n / (n + height)));
+    }
+    (latitude.to_degrees(), longitude.to_degrees(), height)
+}
+
+fn shift(coord: &Coordinate, from: &Ellipsoid, to: &Ellipsoid, sign: f64, info: &DatumInfo) -> Option<Coordinate> {
+    let latitude = coord.latitude? as f64;
+    let longitude = coord.longitude? as f64;
+    let height = coord.altitude.unwrap_or(0.0) as f64;
+    let (x, y, z) = geodetic_to_ecef(from, latitude, longitude, height);
+    let (latitude, longitude, height) =
+        ecef_to_geodetic(to, x + sign * info.dx, y + sign * info.dy, z + sign * info.dz);
+    Some(Coordinate {
+        utc: coord.utc,
+        latitude: Some(latitude as f32),
+        longitude: Some(longitude as f32),
+        altitude: coord.altitude.map(|_| height as f32),
+    })
+}
+
+/// Converts a coordinate in the given datum to WGS84.
+///
+/// None if the coordinate has no latitude or longitude, or the datum isn't in DATUMS (the user
+/// datum, 3).
+pub fn to_wgs84(coord: &Coordinate, datum: Datum) -> Option<Coordinate> {
+    let info = datum.info()?;
+    shift(coord, &info.ellipsoid, &Ellipsoid::WGS84, 1.0, info)
+}
+
+/// Converts a WGS84 coordinate to the given datum. The reverse of to_wgs84.
+pub fn from_wgs84(coord: &Coordinate, datum: Datum) -> Option<Coordinate> {
+    let info = datum.info()?;
+    shift(coord, &Ellipsoid::WGS84, &info.ellipsoid, -1.0, info)
+}
+
+#[cfg(test)]
+mod test_datum {
+    use crate::Coordinate;
+
+    use super::{from_wgs84, to_wgs84, Datum};
+
+    fn coord(latitude: f32, longitude: f32, altitude: Option<f32>) -> Coordinate {
+        Coordinate { utc: 0.0, latitude: Some(latitude), longitude: Some(longitude), altitude }
+    }
+
+    #[test]
+    fn wgs84_unchanged() {
+        let c = to_wgs84(&coord(51.5, -0.1, Some(45.0)), Datum::WGS84).unwrap();
+        assert!((c.latitude.unwrap() - 51.5).abs() < 1e-5);
+        assert!((c.longitude.unwrap() + 0.1).abs() < 1e-5);
+        assert!((c.altitude.unwrap() - 45.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn tokyo_to_wgs84() {
+        // GSI's approximation for Japan gives +0.00322 deg latitude and -0.00278 deg longitude here.
+        let c = to_wgs84(&coord(35.0, 135.0, None), Datum::TOKYO_M).unwrap();
+        assert!((c.latitude.unwrap() - 35.00322).abs() < 1e-4, "{:?}", c);
+        assert!((c.longitude.unwrap() - 134.99722).abs() < 1e-4, "{:?}", c);
+        assert_eq!(c.altitude, None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let start = coord(-20.0, 25.0, Some(1000.0));
+        let back = from_wgs84(&to_wgs84(&start, Datum(17)).unwrap(), Datum(17)).unwrap();
+        assert!((back.latitude.unwrap() + 20.0).abs() < 1e-5);
+        assert!((back.longitude.unwrap() - 25.0).abs() < 1e-5);
+        assert!((back.altitude.unwrap() - 1000.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn unknown_datum() {
+        assert_eq!(to_wgs84(&coord(0.0, 0.0, None), Datum::USER), None);
+        assert_eq!(to_wgs84(&coord(0.0, 0.0, None), Datum(Datum::COUNT)), None);
+        assert_eq!(to_wgs84(&Coordinate::default(), Datum::WGS84), None);
+    }
+}
@@ -7,6 +7,7 @@
 //todo - size of error for a long lat: 51.0, 1.0 is x m^2 area.
 // todo - expected distance error for a given pdop.
 
+pub mod datum;
 pub mod kinematics;
 pub mod position;
 
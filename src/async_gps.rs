@@ -0,0 +1,549 @@
+//! # Async gps
+//! With the `tokio` feature, AsyncGps reads the gps from anything that is AsyncRead + AsyncWrite,
+//! such as a tokio-serial SerialStream or a TcpStream to a serial server, without blocking the
+//! runtime.
+//!
+//! gps.sentences() is a Stream of GpsSentences, and the PMTK commands are sent with the async
+//! send_with_ack and send_query, which follow gps.command_policy like the sync ones and match
+//! replies to the command the same way. The query_* methods, apply_config and read_config are
+//! the async forms of the sync ones, and share their command text and reply parsing.
+//! ```ignore
+//! let mut gps = AsyncGps::new(tokio_serial::new("/dev/serial0", 9600).open_native_async()?);
+//! let ack = gps.send_with_ack("PMTK220,1000").await;
+//! let mut sentences = gps.sentences();
+//! while let Some(sentence) = sentences.next().await {
+//!     println!("{:?}", sentence);
+//! }
+//! ```
+//!
+//! The sync Gps is not changed by the feature.
+
+pub mod tokio_gps {
+    //! AsyncGps and its sentence Stream.
+    use std::collections::VecDeque;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    use futures_core::Stream;
+    use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+    use tokio::time::{sleep, timeout, Instant};
+
+    use crate::config::receiver_config::ReceiverConfig;
+    use crate::datum::datums::Datum;
+    use crate::nmea::parse_nmea::{parse_sentence, Talker};
+    use crate::open_gps::gps::{is_expected_talker, parse_line, push_capped, GpsSentence, MAX_PENDING, MAX_STRAY};
+    use crate::pmtk::chip_support::is_supported;
+    use crate::pmtk::send_pmtk::{add_checksum, command_number, easy_from_q_reply, reply_arg, Chip, CommandPolicy, DgpsMode,
+                                 GnssSearchMode, NmeaOutput, Pmtk001Ack, PmtkAck, QueryError, Sbas, SbasMode, Wait,
+                                 Waited};
+    use crate::trace::wire_trace::{Direction, TraceEvent, TraceSink};
+
+    /// A line from the port, or why there isn't one.
+    enum Line {
+        Valid(String),
+        InvalidBytes,
+        NoConnection,
+        Closed,
+    }
+
+    /// The async version of Gps, over any port that is AsyncRead + AsyncWrite.
+    ///
+    /// talker, stray_acks, stray_replies, command_policy, last_attempt, pending, chip and trace are as for
+    /// [Gps](crate::Gps), with the same MAX_STRAY and MAX_PENDING limits.
+    ///
+    /// read_timeout: How long to wait for a line before giving NoConnection. 1 second by default,
+    /// as for the sync port.
+    pub struct AsyncGps<P> {
+        port: BufReader<P>,
+        partial: Vec<u8>,
+        pub talker: Option<Talker>,
+        pub stray_acks: VecDeque<PmtkAck>,
+        pub stray_replies: VecDeque<String>,
+        pub command_policy: CommandPolicy,
+        pub last_attempt: u32,
+        pub pending: VecDeque<String>,
+        pub chip: Option<Chip>,
+        pub read_timeout: Duration,
+        pub trace: Option<Box<dyn TraceSink>>,
+    }
+
+    impl<P: AsyncRead + AsyncWrite + Unpin> AsyncGps<P> {
+        pub fn new(port: P) -> AsyncGps<P> {
+            AsyncGps {
+                port: BufReader::new(port),
+                partial: Vec::new(),
+                talker: None,
+                stray_acks: VecDeque::new(),
+                stray_replies: VecDeque::new(),
+                command_policy: CommandPolicy::default(),
+                last_attempt: 0,
+                pending: VecDeque::new(),
+                chip: None,
+                read_timeout: Duration::from_secs(1),
+                trace: None,
+            }
+        }
+
+        /// The port, giving up any bytes read but not yet given as a line.
+        pub fn into_inner(self) -> P {
+            self.port.into_inner()
+        }
+
+        /// Reads up to the next \n. A line cut off by the read timeout is kept and finished by the
+        /// next read.
+        async fn read_line(&mut self) -> Line {
+            match timeout(self.read_timeout, self.port.read_until(b'\n', &mut self.partial)).await {
+                Err(_) => Line::NoConnection,
+                Ok(Err(_)) | Ok(Ok(0)) => Line::Closed,
+                Ok(Ok(_)) => {
+                    let bytes = std::mem::take(&mut self.partial);
+                    if let Some(sink) = self.trace.as_mut() {
+                        sink.trace(&TraceEvent::new(Direction::Received, &String::from_utf8_lossy(&bytes)));
+                    }
+                    match String::from_utf8(bytes) {
+                        Ok(line) => Line::Valid(line),
+                        Err(_) => Line::InvalidBytes,
+                    }
+                }
+            }
+        }
+
+        async fn next_line(&mut self) -> Line {
+            match self.pending.pop_front() {
+                Some(line) => Line::Valid(line),
+                None => self.read_line().await,
+            }
+        }
+
+        /// The next sentence, with every message of a GSV sequence. None once the port is closed.
+        pub async fn next_sentence(&mut self) -> Option<GpsSentence> {
+            let line = match self.next_line().await {
+                Line::Valid(line) => line,
+                Line::InvalidBytes => return Some(GpsSentence::InvalidBytes),
+                Line::NoConnection => return Some(GpsSentence::NoConnection),
+                Line::Closed => return None,
+            };
+            if !is_expected_talker(self.talker, line.as_str()) {
+                return Some(GpsSentence::InvalidSentence);
+            }
+            match parse_line(line.as_str()) {
+                GpsSentence::GSV(mut satellites) => {
+                    let messages: i32 = parse_sentence(line.as_str())
+                        .and_then(|s| s.get(1).and_then(|n| n.parse().ok()))
+                        .unwrap_or(1);
+                    for _message in 1..messages {
+                        if let Line::Valid(line) = self.next_line().await {
+                            match parse_line(line.as_str()) {
+                                GpsSentence::GSV(mut more) => satellites.append(&mut more),
+                                _ => {
+                                    // A GSV message was lost: give this line as its own sentence.
+                                    self.pending.push_front(line);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Some(GpsSentence::GSV(satellites))
+                }
+                sentence => Some(sentence),
+            }
+        }
+
+        /// As gps.update(): the next sentence, NoConnection if there is none or the port is closed.
+        pub async fn update(&mut self) -> GpsSentence {
+            self.next_sentence().await.unwrap_or(GpsSentence::NoConnection)
+        }
+
+        /// A Stream of the sentences, which ends when the port is closed. Drop it to send commands,
+        /// then call sentences() again to carry on.
+        pub fn sentences(&mut self) -> SentenceStream<'_, P>
+            where P: Send {
+            SentenceStream { next: Some(Box::pin(step(self))) }
+        }
+
+        /// Send the PMTK command. Input: no $ and no *checksum.
+        pub async fn send_command(&mut self, cmd: &str) -> std::io::Result<()> {
+            let cmd = add_checksum(cmd.to_string());
+            if let Some(sink) = self.trace.as_mut() {
+                sink.trace(&TraceEvent::new(Direction::Sent, cmd.as_str()));
+            }
+            let port = self.port.get_mut();
+            port.write_all(cmd.as_bytes()).await?;
+            port.flush().await
+        }
+
+        /// Keeps a line read while waiting for a reply for update() to give later, if it is a sentence.
+        fn keep_sentence(&mut self, line: String) {
+            if !matches!(parse_line(line.as_str()), GpsSentence::InvalidSentence) {
+                push_capped(&mut self.pending, line, MAX_PENDING);
+            }
+        }
+
+        /// Reads lines until the one wait is for, sorted as the sync Gps does. Replies to other
+        /// commands are kept in gps.stray_acks and gps.stray_replies.
+        async fn wait_for(&mut self, wait: &Wait, wait_time: Duration) -> Option<Waited> {
+            let deadline = Instant::now() + wait_time;
+            loop {
+                let line = match timeout(deadline.saturating_duration_since(Instant::now()), self.read_line()).await {
+                    Ok(Line::Valid(line)) => line,
+                    Ok(Line::InvalidBytes) => continue,
+                    Ok(Line::NoConnection) | Ok(Line::Closed) | Err(_) => return None,
+                };
+                match wait.check(line.as_str()) {
+                    Waited::StrayAck(ack) => push_capped(&mut self.stray_acks, ack, MAX_STRAY),
+                    Waited::StrayReply(reply) => push_capped(&mut self.stray_replies, reply, MAX_STRAY),
+                    Waited::Other => self.keep_sentence(line),
+                    waited => return Some(waited),
+                }
+            }
+        }
+
+        /// Sends cmd until there is a reply, following gps.command_policy.
+        async fn send_with_retries(&mut self, cmd: &str, wait: Wait) -> Option<Waited> {
+            let wait_time = self.command_policy.timeout_for(command_number(cmd));
+            for attempt in 1..=self.command_policy.retries + 1 {
+                sleep(self.command_policy.backoff_for(attempt)).await;
+                if self.send_command(cmd).await.is_err() {
+                    continue;
+                }
+                if let Some(reply) = self.wait_for(&wait, wait_time).await {
+                    self.last_attempt = attempt;
+                    return Some(reply);
+                }
+            }
+            self.last_attempt = 0;
+            None
+        }
+
+        /// Ok if gps.chip supports the packet, or the chip isn't known. Err gives the chip.
+        pub fn check_supported(&self, packet: u16) -> Result<(), Chip> {
+            match self.chip {
+                Some(chip) if !is_supported(chip, packet) => Err(chip),
+                _ => Ok(()),
+            }
+        }
+
+        /// Sends a command and waits for its PMTK001 reply, resending it if there is no reply.
+        pub async fn send_with_ack(&mut self, cmd: &str) -> PmtkAck {
+            let number = command_number(cmd);
+            if let Err(chip) = self.check_supported(number) {
+                return PmtkAck::new(number, Pmtk001Ack::NotOnChip(chip));
+            }
+            match self.send_with_retries(cmd, Wait::Ack(number)).await {
+                Some(Waited::Ack(mut ack)) => {
+                    ack.attempt = self.last_attempt;
+                    ack
+                }
+                _ => PmtkAck::new(number, Pmtk001Ack::NoPacket),
+            }
+        }
+
+        /// Sends a query and waits for its reply packet, such as 501 for PMTK401, resending it if
+        /// there is no reply. Gives the reply without the checksum.
+        pub async fn send_query(&mut self, cmd: &str, reply: u16) -> Result<String, QueryError> {
+            self.check_supported(command_number(cmd)).map_err(QueryError::NotOnChip)?;
+            match self.send_with_retries(cmd, Wait::Reply(Some(reply))).await {
+                Some(Waited::Reply(line)) => Ok(line),
+                _ => Err(QueryError::NoReply),
+            }
+        }
+
+        /// Sends a restart command, such as PMTK101, and waits for the startup message.
+        pub async fn send_with_startup(&mut self, cmd: &str) -> bool {
+            if self.check_supported(command_number(cmd)).is_err() {
+                return false;
+            }
+            matches!(self.send_with_retries(cmd, Wait::Startup).await, Some(Waited::Startup))
+        }
+
+        /// Check for the PMTK001 reply to command cmd, without sending anything.
+        pub async fn wait_for_ack_to(&mut self, cmd: u16) -> PmtkAck {
+            let wait_time = self.command_policy.timeout_for(cmd);
+            match self.wait_for(&Wait::Ack(cmd), wait_time).await {
+                Some(Waited::Ack(ack)) => ack,
+                _ => PmtkAck::new(cmd, Pmtk001Ack::NoPacket),
+            }
+        }
+
+        /// Check for any PMTK500 style reply, without sending anything.
+        pub async fn pmtk_500(&mut self) -> Option<String> {
+            let wait_time = self.command_policy.timeout;
+            match self.wait_for(&Wait::Reply(None), wait_time).await {
+                Some(Waited::Reply(line)) => Some(line),
+                _ => None,
+            }
+        }
+
+        /// Checks if the gps rebooted, without sending anything.
+        pub async fn pmtk_startup(&mut self) -> bool {
+            let wait_time = self.command_policy.timeout_for(101);
+            matches!(self.wait_for(&Wait::Startup, wait_time).await, Some(Waited::Startup))
+        }
+
+        /// Takes the PMTK001 replies that didn't match the command being waited for.
+        pub fn take_stray_acks(&mut self) -> Vec<PmtkAck> {
+            self.stray_acks.drain(..).collect()
+        }
+
+        /// Takes the PMTK500 style replies that didn't match the query being waited for.
+        pub fn take_stray_replies(&mut self) -> Vec<String> {
+            self.stray_replies.drain(..).collect()
+        }
+
+        /// As gps.query_dgps_mode().
+        pub async fn query_dgps_mode(&mut self) -> Option<DgpsMode> {
+            DgpsMode::from_q_reply(self.send_query("PMTK401", 501).await.ok()?.as_str())
+        }
+
+        /// As gps.query_sbas_enabled().
+        pub async fn query_sbas_enabled(&mut self) -> Option<Sbas> {
+            Sbas::from_q_reply(self.send_query("PMTK413", 513).await.ok()?.as_str())
+        }
+
+        /// As gps.query_nmea_output().
+        pub async fn query_nmea_output(&mut self) -> Option<NmeaOutput> {
+            NmeaOutput::from_q_reply(self.send_query("PMTK414", 514).await.ok()?.as_str())
+        }
+
+        /// As gps.query_sbas_mode().
+        pub async fn query_sbas_mode(&mut self) -> Option<SbasMode> {
+            SbasMode::from_q_reply(self.send_query("PMTK419", 519).await.ok()?.as_str())
+        }
+
+        /// As gps.pmtk_430_api_q_datum().
+        pub async fn query_datum(&mut self) -> Option<Datum> {
+            reply_arg(self.send_query("PMTK430", 530).await.ok()?.as_str(), 530, 0).map(Datum)
+        }
+
+        /// As gps.query_nav_threshold().
+        pub async fn query_nav_threshold(&mut self) -> Option<f32> {
+            reply_arg(self.send_query("PMTK447", 527).await.ok()?.as_str(), 527, 0)
+        }
+
+        /// As gps.query_easy().
+        pub async fn query_easy(&mut self) -> Option<bool> {
+            easy_from_q_reply(self.send_query("PMTK869,0", 869).await.ok()?.as_str())
+        }
+
+        /// As gps.pmtk_355_api_q_gnss_search_mode(), which also sets gps.talker.
+        pub async fn query_gnss_search_mode(&mut self) -> Option<GnssSearchMode> {
+            let ack = self.send_with_ack("PMTK355").await;
+            if ack.flag != Pmtk001Ack::Success {
+                return None;
+            }
+            let search_mode = GnssSearchMode::from_q_value(ack.value?.as_str())?;
+            self.talker = search_mode.expected_talker();
+            Some(search_mode)
+        }
+
+        /// As gps.pmtk_406_api_q_min_snr().
+        pub async fn query_min_snr(&mut self) -> Option<u8> {
+            reply_arg(self.send_query("PMTK406", 506).await.ok()?.as_str(), 506, 0)
+        }
+
+        /// As gps.pmtk_411_api_q_elev_mask().
+        pub async fn query_elev_mask(&mut self) -> Option<u8> {
+            reply_arg(self.send_query("PMTK411", 511).await.ok()?.as_str(), 511, 0)
+        }
+
+        /// As gps.apply_config(): sends every setting in the config that isn't None, with the same
+        /// commands, and gives the ack for each.
+        pub async fn apply_config(&mut self, config: &ReceiverConfig) -> Vec<(&'static str, PmtkAck)> {
+            let nav_packet = if self.check_supported(386).is_ok() { 386 } else { 397 };
+            let mut acks = Vec::new();
+            for command in config.commands(nav_packet) {
+                let ack = match &command.cmd {
+                    None => PmtkAck::new(command.packet, Pmtk001Ack::Invalid),
+                    Some(cmd) if command.restarts => {
+                        let flag = match self.check_supported(command.packet) {
+                            Err(chip) => Pmtk001Ack::NotOnChip(chip),
+                            Ok(()) if self.send_with_startup(cmd).await => Pmtk001Ack::Success,
+                            Ok(()) => Pmtk001Ack::NoPacket,
+                        };
+                        let mut ack = PmtkAck::new(command.packet, flag);
+                        ack.attempt = self.last_attempt;
+                        ack
+                    }
+                    Some(cmd) => self.send_with_ack(cmd).await,
+                };
+                if let ("gnss_search_mode", Some(mode)) = (command.setting, &config.gnss_search_mode) {
+                    if ack == Pmtk001Ack::Success {
+                        self.talker = mode.expected_talker();
+                    }
+                }
+                acks.push((command.setting, ack));
+            }
+            acks
+        }
+
+        /// As gps.read_config(): the settings the gps can be asked for, None for any with no reply.
+        pub async fn read_config(&mut self) -> ReceiverConfig {
+            ReceiverConfig {
+                nmea_output: self.query_nmea_output().await,
+                dgps_mode: self.query_dgps_mode().await,
+                sbas: self.query_sbas_enabled().await,
+                sbas_mode: self.query_sbas_mode().await,
+                datum: self.query_datum().await,
+                nav_speed_threshold: self.query_nav_threshold().await,
+                easy: self.query_easy().await,
+                gnss_search_mode: self.query_gnss_search_mode().await,
+                min_snr: self.query_min_snr().await,
+                elevation_mask: self.query_elev_mask().await,
+                ..ReceiverConfig::default()
+            }
+        }
+    }
+
+    type Step<'a, P> = Pin<Box<dyn Future<Output = (&'a mut AsyncGps<P>, Option<GpsSentence>)> + Send + 'a>>;
+
+    async fn step<P: AsyncRead + AsyncWrite + Unpin + Send>(gps: &mut AsyncGps<P>)
+                                                            -> (&mut AsyncGps<P>, Option<GpsSentence>) {
+        let sentence = gps.next_sentence().await;
+        (gps, sentence)
+    }
+
+    /// The Stream given by gps.sentences().
+    pub struct SentenceStream<'a, P> {
+        next: Option<Step<'a, P>>,
+    }
+
+    impl<'a, P: AsyncRead + AsyncWrite + Unpin + Send> Stream for SentenceStream<'a, P> {
+        type Item = GpsSentence;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<GpsSentence>> {
+            let next = match self.next.as_mut() {
+                Some(next) => next,
+                None => return Poll::Ready(None),
+            };
+            match next.as_mut().poll(cx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready((gps, Some(sentence))) => {
+                    self.next = Some(Box::pin(step(gps)));
+                    Poll::Ready(Some(sentence))
+                }
+                Poll::Ready((_gps, None)) => {
+                    self.next = None;
+                    Poll::Ready(None)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod async_gps_test {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::time::Duration;
+
+    use futures_core::Stream;
+    use tokio::io::{duplex, AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+
+    use crate::open_gps::gps::GpsSentence;
+    use crate::open_gps::mock_port::GGA;
+    use crate::pmtk::send_pmtk::{add_checksum, DgpsMode, Pmtk001Ack};
+
+    use super::tokio_gps::AsyncGps;
+
+    fn run<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap().block_on(future)
+    }
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    /// A gps on one end of a pipe, and the other end to play the gps with.
+    fn pipe() -> (AsyncGps<DuplexStream>, DuplexStream) {
+        let (port, wire) = duplex(1024);
+        let mut gps = AsyncGps::new(port);
+        gps.read_timeout = Duration::from_millis(200);
+        gps.command_policy.timeout = Duration::from_millis(200);
+        gps.command_policy.backoff = Duration::from_millis(0);
+        (gps, wire)
+    }
+
+    #[test]
+    fn stream_of_sentences() {
+        run(async {
+            let (mut gps, mut wire) = pipe();
+            let gsv_1 = "$GPGSV,2,1,08,01,40,083,46,02,17,308,41,12,07,344,39,14,22,228,45*75\r\n";
+            let gsv_2 = "$GPGSV,2,2,08,15,53,141,41,17,45,053,44,19,21,309,40,24,30,187,43*7A\r\n";
+            wire.write_all([GGA, gsv_1, gsv_2].concat().as_bytes()).await.unwrap();
+            drop(wire);
+
+            let mut sentences = gps.sentences();
+            assert!(matches!(next(&mut sentences).await, Some(GpsSentence::GGA(_))));
+            match next(&mut sentences).await {
+                Some(GpsSentence::GSV(satellites)) => assert_eq!(satellites.len(), 8),
+                sentence => panic!("Expected both GSV messages, got {:?}", sentence),
+            }
+            assert_eq!(next(&mut sentences).await, None);
+        });
+    }
+
+    #[test]
+    fn lost_gsv_message() {
+        run(async {
+            let (mut gps, mut wire) = pipe();
+            let gsv_1 = "$GPGSV,2,1,08,01,40,083,46,02,17,308,41,12,07,344,39,14,22,228,45*75\r\n";
+            wire.write_all([gsv_1, GGA].concat().as_bytes()).await.unwrap();
+            drop(wire);
+
+            match gps.update().await {
+                GpsSentence::GSV(satellites) => assert_eq!(satellites.len(), 4),
+                sentence => panic!("Expected the first GSV message, got {:?}", sentence),
+            }
+            assert!(matches!(gps.update().await, GpsSentence::GGA(_)));
+        });
+    }
+
+    #[test]
+    fn no_bytes_is_no_connection() {
+        run(async {
+            let (mut gps, _wire) = pipe();
+            assert_eq!(gps.update().await, GpsSentence::NoConnection);
+        });
+    }
+
+    #[test]
+    fn ack_and_kept_sentences() {
+        run(async {
+            let (mut gps, wire) = pipe();
+            let gps_side = tokio::spawn(async move {
+                let mut wire = BufReader::new(wire);
+                let mut command = String::new();
+                wire.read_line(&mut command).await.unwrap();
+                let ack = add_checksum("PMTK001,220,3".to_string());
+                wire.get_mut().write_all([GGA, ack.as_str()].concat().as_bytes()).await.unwrap();
+                command
+            });
+            let ack = gps.send_with_ack("PMTK220,1000").await;
+            assert_eq!(ack, Pmtk001Ack::Success);
+            assert_eq!(ack.attempt, 1);
+            assert_eq!(gps_side.await.unwrap(), add_checksum("PMTK220,1000".to_string()));
+            assert!(matches!(gps.update().await, GpsSentence::GGA(_)));
+        });
+    }
+
+    #[test]
+    fn late_reply_is_not_taken_for_query() {
+        run(async {
+            let (mut gps, wire) = pipe();
+            let gps_side = tokio::spawn(async move {
+                let mut wire = BufReader::new(wire);
+                let mut command = String::new();
+                wire.read_line(&mut command).await.unwrap();
+                let late = add_checksum("PMTK513,1".to_string());
+                let reply = add_checksum("PMTK501,2".to_string());
+                wire.get_mut().write_all([late, reply].concat().as_bytes()).await.unwrap();
+                command
+            });
+            assert_eq!(gps.query_dgps_mode().await, Some(DgpsMode::WAAS));
+            assert_eq!(gps_side.await.unwrap(), add_checksum("PMTK401".to_string()));
+            assert_eq!(gps.take_stray_replies(), vec!["$PMTK513,1".to_string()]);
+        });
+    }
+}
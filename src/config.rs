@@ -0,0 +1,542 @@
+//! # Receiver configuration
+//! All the gps settings in one struct, so a set up can be kept in a file rather than as a list of
+//! pmtk calls.
+//!
+//! ReceiverConfig can be loaded with serde from TOML, JSON or anything else serde reads. Settings
+//! that are left out are not changed.
+//! ```ignore
+//! let config: ReceiverConfig = toml::from_str(&std::fs::read_to_string("gps.toml")?)?;
+//! let acks = gps.apply_config(&config);
+//! let not_taken = config.diff(&gps.read_config());
+//! ```
+//!
+//! A UserOption stored in the gps flash with pmtk_390_api_set_user_option is checked with
+//! gps.verify_user_option(&option), which power cycles the gps and compares what it comes back with.
+//!
+//! DeviceInfo is everything the gps can be asked, from gps.device_info(), for logs and printing.
+
+pub mod receiver_config {
+    //! ReceiverConfig and the gps methods that apply it and read it back.
+    use std::fmt::Debug;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::datum::datums::Datum;
+    use crate::open_gps::gps::Gps;
+    use crate::pmtk::send_pmtk::{DgpsMode, GnssSearchMode, NmeaOutput, Pmtk001Ack, PmtkAck, PowerMode,
+                                 PpsConfig, Sbas, SbasMode, UserOption, ELEVATION_MASK_RANGE, MIN_SNR_RANGE};
+
+    /// Every setting that can be sent to the gps. None leaves the setting as it is.
+    ///
+    /// - update_rate: Milliseconds between fixes, 100 to 10_000. pmtk_220_set_nmea_updaterate.
+    /// - nmea_output: pmtk_314_api_set_nmea_output.
+    /// - dgps_mode: pmtk_301_api_set_dgps_mode.
+    /// - sbas: pmtk_313_api_set_sbas_enabled.
+    /// - sbas_mode: pmtk_319_api_set_sbas_mode. The gps restarts after this one.
+    /// - datum: pmtk_330_api_set_datum.
+    /// - nav_speed_threshold: m/s, pmtk_386_set_nav_speed_threshold, or pmtk_397 for MT3318 and
+    ///   MT3329 chips.
+    /// - aic: pmtk_286_cmd_aic_mode.
+    /// - easy: pmtk_869_cmd_easy_enable.
+    /// - gnss_search_mode: pmtk_353_api_set_gnss_search_mode.
+    /// - min_snr: pmtk_306_api_set_min_snr.
+    /// - elevation_mask: pmtk_311_api_set_elev_mask.
+    /// - qzss_nmea: pmtk_351_api_set_support_qzss_nmea.
+    /// - qzss: pmtk_352_api_set_stop_qzss.
+    /// - sync_pps_nmea: pmtk_255_set_sync_pps_nmea.
+    /// - pps: pmtk_285_set_pps_config.
+    /// - locus_interval: Seconds, pmtk_187_locus_config.
+    /// - power_mode: pmtk_225_cmd_periodic_mode. Sent last, as the gps may stop giving output.
+    #[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct ReceiverConfig {
+        pub update_rate: Option<u16>,
+        pub nmea_output: Option<NmeaOutput>,
+        pub dgps_mode: Option<DgpsMode>,
+        pub sbas: Option<Sbas>,
+        pub sbas_mode: Option<SbasMode>,
+        pub datum: Option<Datum>,
+        pub nav_speed_threshold: Option<f32>,
+        pub aic: Option<bool>,
+        pub easy: Option<bool>,
+        pub gnss_search_mode: Option<GnssSearchMode>,
+        pub min_snr: Option<u8>,
+        pub elevation_mask: Option<u8>,
+        pub qzss_nmea: Option<bool>,
+        pub qzss: Option<bool>,
+        pub sync_pps_nmea: Option<bool>,
+        pub pps: Option<PpsConfig>,
+        pub locus_interval: Option<i8>,
+        pub power_mode: Option<PowerMode>,
+    }
+
+    /// A setting that is not what it should be.
+    ///
+    /// actual is None if the setting could not be read back from the gps.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct ConfigMismatch {
+        pub setting: &'static str,
+        pub wanted: String,
+        pub actual: Option<String>,
+    }
+
+    /// The nav speed threshold as it is sent to the gps.
+    fn nav_threshold_arg(threshold: f32) -> String {
+        format!("{:.1}", threshold)
+    }
+
+    /// The nav speed threshold the gps is given for threshold.
+    fn as_sent(threshold: f32) -> f32 {
+        nav_threshold_arg(threshold).parse().unwrap_or(threshold)
+    }
+
+    fn compare<T: Debug + PartialEq>(setting: &'static str, wanted: &Option<T>, actual: &Option<T>,
+                                      mismatches: &mut Vec<ConfigMismatch>) {
+        if let Some(wanted) = wanted {
+            if actual.as_ref() != Some(wanted) {
+                mismatches.push(ConfigMismatch {
+                    setting,
+                    wanted: format!("{:?}", wanted),
+                    actual: actual.as_ref().map(|a| format!("{:?}", a)),
+                });
+            }
+        }
+    }
+
+    impl ReceiverConfig {
+        /// The settings in self that are not the same in actual, which is usually from gps.read_config().
+        ///
+        /// Only the settings the gps can be asked for are compared: nmea_output, dgps_mode, sbas,
+        /// sbas_mode, datum, nav_speed_threshold, easy, gnss_search_mode, min_snr and elevation_mask.
+        pub fn diff(&self, actual: &ReceiverConfig) -> Vec<ConfigMismatch> {
+            let mut mismatches = Vec::new();
+            compare("nmea_output", &self.nmea_output, &actual.nmea_output, &mut mismatches);
+            compare("dgps_mode", &self.dgps_mode, &actual.dgps_mode, &mut mismatches);
+            compare("sbas", &self.sbas, &actual.sbas, &mut mismatches);
+            compare("sbas_mode", &self.sbas_mode, &actual.sbas_mode, &mut mismatches);
+            compare("datum", &self.datum, &actual.datum, &mut mismatches);
+            // The threshold is sent to 1 decimal place, so is compared at that.
+            compare("nav_speed_threshold", &self.nav_speed_threshold.map(as_sent),
+                    &actual.nav_speed_threshold.map(as_sent), &mut mismatches);
+            compare("easy", &self.easy, &actual.easy, &mut mismatches);
+            compare("gnss_search_mode", &self.gnss_search_mode, &actual.gnss_search_mode, &mut mismatches);
+            compare("min_snr", &self.min_snr, &actual.min_snr, &mut mismatches);
+            compare("elevation_mask", &self.elevation_mask, &actual.elevation_mask, &mut mismatches);
+            mismatches
+        }
+    }
+
+    /// A setting of a ReceiverConfig as the command that sets it, shared by Gps and AsyncGps.
+    ///
+    /// cmd is None if the setting is out of range, which gives Invalid without anything being sent.
+    /// restarts is true if the gps gives its startup message rather than a PMTK001 reply.
+    pub(crate) struct SettingCommand {
+        pub setting: &'static str,
+        pub packet: u16,
+        pub cmd: Option<String>,
+        pub restarts: bool,
+    }
+
+    fn setting(setting: &'static str, packet: u16, args: Option<String>) -> SettingCommand {
+        let cmd = args.map(|args| format!("PMTK{:03},{}", packet, args));
+        SettingCommand { setting, packet, cmd, restarts: packet == 319 }
+    }
+
+    impl ReceiverConfig {
+        /// The command for each setting that isn't None, in the order they are sent. nav_packet is
+        /// 386, or 397 for the MT3318 and MT3329.
+        pub(crate) fn commands(&self, nav_packet: u16) -> Vec<SettingCommand> {
+            let flag = |on: bool| if on { "1" } else { "0" };
+            let mut commands = Vec::new();
+            if let Some(output) = &self.nmea_output {
+                commands.push(setting("nmea_output", 314, Some(output.to_args())));
+            }
+            if let Some(rate) = self.update_rate {
+                commands.push(setting("update_rate", 220, Some(rate.to_string())));
+            }
+            if let Some(mode) = &self.dgps_mode {
+                commands.push(setting("dgps_mode", 301, mode.to_args().map(String::from)));
+            }
+            if let Some(sbas) = &self.sbas {
+                commands.push(setting("sbas", 313, sbas.to_args().map(String::from)));
+            }
+            if let Some(mode) = &self.sbas_mode {
+                commands.push(setting("sbas_mode", 319, mode.to_args().map(String::from)));
+            }
+            if let Some(datum) = self.datum {
+                commands.push(setting("datum", 330, Some(datum.0.to_string()).filter(|_| datum.0 < Datum::COUNT)));
+            }
+            if let Some(threshold) = self.nav_speed_threshold {
+                commands.push(setting("nav_speed_threshold", nav_packet, Some(nav_threshold_arg(threshold))));
+            }
+            if let Some(aic) = self.aic {
+                commands.push(setting("aic", 286, Some(flag(aic).to_string())));
+            }
+            if let Some(easy) = self.easy {
+                commands.push(setting("easy", 869, Some(format!("1,{}", flag(easy)))));
+            }
+            if let Some(mode) = &self.gnss_search_mode {
+                commands.push(setting("gnss_search_mode", 353, mode.expected_talker().map(|_| mode.to_args())));
+            }
+            if let Some(snr) = self.min_snr {
+                commands.push(setting("min_snr", 306, Some(snr.to_string()).filter(|_| MIN_SNR_RANGE.contains(&snr))));
+            }
+            if let Some(elevation) = self.elevation_mask {
+                let args = Some(elevation.to_string()).filter(|_| ELEVATION_MASK_RANGE.contains(&elevation));
+                commands.push(setting("elevation_mask", 311, args));
+            }
+            if let Some(qzss_nmea) = self.qzss_nmea {
+                commands.push(setting("qzss_nmea", 351, Some(flag(qzss_nmea).to_string())));
+            }
+            if let Some(qzss) = self.qzss {
+                // PMTK352 stops QZSS, so 0 is enabled.
+                commands.push(setting("qzss", 352, Some(flag(!qzss).to_string())));
+            }
+            if let Some(sync) = self.sync_pps_nmea {
+                commands.push(setting("sync_pps_nmea", 255, Some(flag(sync).to_string())));
+            }
+            if let Some(pps) = &self.pps {
+                commands.push(setting("pps", 285, pps.to_args()));
+            }
+            if let Some(interval) = self.locus_interval {
+                commands.push(setting("locus_interval", 187, Some(format!("1,{}", interval))));
+            }
+            if let Some(mode) = &self.power_mode {
+                commands.push(setting("power_mode", 225, mode.to_args()));
+            }
+            commands
+        }
+    }
+
+    impl UserOption {
+        /// The settings the gps should have after a full cold start with this user option stored.
+        pub fn receiver_config(&self) -> ReceiverConfig {
+            ReceiverConfig {
+                update_rate: Some(1000 / self.update_rate.max(1) as u16),
+                nmea_output: Some(self.nmea_output.clone()),
+                dgps_mode: Some(self.dgps_mode.clone()),
+                sbas: Some(self.sbas.clone()),
+                sbas_mode: Some(self.sbas_mode.clone()),
+                datum: Some(self.datum),
+                ..ReceiverConfig::default()
+            }
+        }
+    }
+
+    impl Gps {
+        /// Sends every setting in the config that isn't None. Gives the ack for each setting sent.
+        ///
+        /// sbas_mode gives a Success ack if the gps restarted after it, and NoPacket if it didn't.
+        pub fn apply_config(&mut self, config: &ReceiverConfig) -> Vec<(&'static str, PmtkAck)> {
+            let nav_packet = if self.supports(386) { 386 } else { 397 };
+            let mut acks = Vec::new();
+            for command in config.commands(nav_packet) {
+                let ack = match &command.cmd {
+                    None => PmtkAck::new(command.packet, Pmtk001Ack::Invalid),
+                    Some(cmd) if command.restarts => {
+                        let flag = match self.check_supported(command.packet) {
+                            Err(chip) => Pmtk001Ack::NotOnChip(chip),
+                            Ok(()) if self.send_with_startup(cmd) => Pmtk001Ack::Success,
+                            Ok(()) => Pmtk001Ack::NoPacket,
+                        };
+                        let mut ack = PmtkAck::new(command.packet, flag);
+                        ack.attempt = self.last_attempt;
+                        ack
+                    }
+                    Some(cmd) => self.send_with_ack(cmd),
+                };
+                if ack == Pmtk001Ack::Success {
+                    match (command.setting, &config.gnss_search_mode, &config.power_mode) {
+                        ("gnss_search_mode", Some(mode), _) => self.talker = mode.expected_talker(),
+                        ("power_mode", _, Some(mode)) => self.power_state = mode.power_state(),
+                        _ => (),
+                    }
+                }
+                acks.push((command.setting, ack));
+            }
+            acks
+        }
+
+        /// Builds a ReceiverConfig from the pmtk_4xx queries. Settings that can't be queried, or
+        /// that got no reply, are None.
+        pub fn read_config(&mut self) -> ReceiverConfig {
+            ReceiverConfig {
+                nmea_output: self.query_nmea_output(),
+                dgps_mode: self.query_dgps_mode(),
+                sbas: self.query_sbas_enabled(),
+                sbas_mode: self.query_sbas_mode(),
+                datum: self.pmtk_430_api_q_datum(),
+                nav_speed_threshold: self.query_nav_threshold(),
+                easy: self.query_easy(),
+                gnss_search_mode: self.pmtk_355_api_q_gnss_search_mode(),
+                min_snr: self.pmtk_406_api_q_min_snr(),
+                elevation_mask: self.pmtk_411_api_q_elev_mask(),
+                ..ReceiverConfig::default()
+            }
+        }
+
+        /// Checks that a user option stored with pmtk_390_api_set_user_option survives a power cycle.
+        ///
+        /// Does a full cold start, which drops every setting back to the stored defaults, switches
+        /// the port to the stored baud rate and then asks the gps for the stored option and the
+        /// settings it is now using. Gives the settings that are not what option says, so an empty
+        /// Vec means the defaults were kept.
+        ///
+        /// - restart: No startup message was seen after the cold start, or gps.chip doesn't support
+        ///   PMTK104 so it wasn't sent.
+        /// - user_option: The option read back with pmtk_490_api_q_user_option.
+        /// - The rest are the live settings, as for ReceiverConfig::diff. The update rate can't be
+        ///   queried, so isn't checked.
+        pub fn verify_user_option(&mut self, option: &UserOption) -> Vec<ConfigMismatch> {
+            let mut mismatches = Vec::new();
+            // The gps comes back up at the stored baud rate, so the startup message has to be read
+            // at that rate rather than the one the command was sent at.
+            if let Err(chip) = self.check_supported(104) {
+                let actual = Some(format!("{:?}", Pmtk001Ack::NotOnChip(chip)));
+                mismatches.push(ConfigMismatch { setting: "restart", wanted: "PMTK011".to_string(), actual });
+                return mismatches;
+            }
+            if !self.pmtk_104_cmd_full_cold_start_at(option.baud_rate) {
+                mismatches.push(ConfigMismatch { setting: "restart", wanted: "PMTK011".to_string(), actual: None });
+            }
+            compare("user_option", &Some(option.clone()), &self.pmtk_490_api_q_user_option(), &mut mismatches);
+            let actual = ReceiverConfig {
+                nmea_output: self.query_nmea_output(),
+                dgps_mode: self.query_dgps_mode(),
+                sbas: self.query_sbas_enabled(),
+                sbas_mode: self.query_sbas_mode(),
+                datum: self.pmtk_430_api_q_datum(),
+                ..ReceiverConfig::default()
+            };
+            mismatches.extend(option.receiver_config().diff(&actual));
+            mismatches
+        }
+    }
+}
+
+pub mod device_info {
+    //! A snapshot of everything the gps can be asked about.
+    use std::fmt;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::datum::datums::Datum;
+    use crate::open_gps::gps::Gps;
+    use crate::pmtk::send_pmtk::{DgpsMode, EpoData, FirmwareRelease, NmeaOutput, Sbas, SbasMode};
+
+    /// The answer to every gps query. None means the gps gave no reply, or the reply couldn't be read.
+    ///
+    /// Can be put at the start of a log with `GpsSentence::DeviceInfo(info).append_to(file)`, and
+    /// printed with `println!("{}", info)`.
+    #[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+    pub struct DeviceInfo {
+        pub release: Option<FirmwareRelease>,
+        pub datum: Option<Datum>,
+        pub sbas: Option<Sbas>,
+        pub sbas_mode: Option<SbasMode>,
+        pub dgps_mode: Option<DgpsMode>,
+        pub nmea_output: Option<NmeaOutput>,
+        pub nav_threshold: Option<f32>,
+        pub easy: Option<bool>,
+        pub epo: Option<EpoData>,
+    }
+
+    fn field<T: fmt::Debug>(f: &mut fmt::Formatter, name: &str, value: &Option<T>) -> fmt::Result {
+        match value {
+            Some(value) => writeln!(f, "{:<14}{:?}", name, value),
+            None => writeln!(f, "{:<14}no reply", name),
+        }
+    }
+
+    impl fmt::Display for DeviceInfo {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match &self.release {
+                Some(release) => writeln!(f, "{:<14}{}", "release", release.raw)?,
+                None => writeln!(f, "{:<14}no reply", "release")?,
+            }
+            field(f, "datum", &self.datum)?;
+            field(f, "sbas", &self.sbas)?;
+            field(f, "sbas_mode", &self.sbas_mode)?;
+            field(f, "dgps_mode", &self.dgps_mode)?;
+            field(f, "nmea_output", &self.nmea_output)?;
+            field(f, "nav_threshold", &self.nav_threshold)?;
+            field(f, "easy", &self.easy)?;
+            field(f, "epo", &self.epo)
+        }
+    }
+
+    impl Gps {
+        /// Asks the gps for its release, datum, SBAS, DGPS, NMEA output, nav threshold, EASY and EPO.
+        pub fn device_info(&mut self) -> DeviceInfo {
+            DeviceInfo {
+                release: self.pmtk_605_q_release(),
+                datum: self.pmtk_430_api_q_datum(),
+                sbas: self.query_sbas_enabled(),
+                sbas_mode: self.query_sbas_mode(),
+                dgps_mode: self.query_dgps_mode(),
+                nmea_output: self.query_nmea_output(),
+                nav_threshold: self.query_nav_threshold(),
+                easy: self.query_easy(),
+                epo: self.query_epo_info(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod receiver_config_test {
+    use crate::datum::datums::Datum;
+    use crate::open_gps::mock_port::mock_gps_with_replies;
+    use crate::pmtk::send_pmtk::{add_checksum, DgpsMode, Pmtk001Ack, Sbas};
+
+    use super::receiver_config::{ConfigMismatch, ReceiverConfig};
+
+    #[test]
+    fn load_partial_json() {
+        let config: ReceiverConfig =
+            serde_json::from_str(r#"{"update_rate": 200, "sbas": "Enabled", "min_snr": 20}"#).unwrap();
+        assert_eq!(config, ReceiverConfig {
+            update_rate: Some(200),
+            sbas: Some(Sbas::Enabled),
+            min_snr: Some(20),
+            ..ReceiverConfig::default()
+        });
+    }
+
+    #[test]
+    fn diff_reports_settings_that_did_not_take() {
+        let wanted = ReceiverConfig {
+            update_rate: Some(200),
+            dgps_mode: Some(DgpsMode::WAAS),
+            datum: Some(Datum::WGS84),
+            nav_speed_threshold: Some(0.4),
+            min_snr: Some(20),
+            ..ReceiverConfig::default()
+        };
+        let actual = ReceiverConfig {
+            dgps_mode: Some(DgpsMode::NoDgps),
+            datum: Some(Datum::WGS84),
+            nav_speed_threshold: Some(0.40),
+            ..ReceiverConfig::default()
+        };
+        assert_eq!(wanted.diff(&actual), vec![
+            ConfigMismatch { setting: "dgps_mode", wanted: "WAAS".to_string(), actual: Some("NoDgps".to_string()) },
+            ConfigMismatch { setting: "min_snr", wanted: "20".to_string(), actual: None },
+        ]);
+    }
+
+    #[test]
+    fn nav_threshold_compared_as_sent() {
+        let wanted = ReceiverConfig { nav_speed_threshold: Some(0.45), ..ReceiverConfig::default() };
+        let sent = wanted.commands(386).into_iter().next().unwrap().cmd.unwrap();
+        assert_eq!(sent, "PMTK386,0.4");
+        let actual = ReceiverConfig { nav_speed_threshold: Some(0.40), ..ReceiverConfig::default() };
+        assert_eq!(wanted.diff(&actual), vec![]);
+        let actual = ReceiverConfig { nav_speed_threshold: Some(0.6), ..ReceiverConfig::default() };
+        assert_eq!(wanted.diff(&actual).len(), 1);
+    }
+
+    #[test]
+    fn apply_config_sends_each_setting() {
+        let replies: Vec<String> = ["PMTK001,220,3", "PMTK001,313,3", "PMTK001,306,2"].iter()
+            .map(|r| add_checksum(r.to_string())).collect();
+        let replies: Vec<&str> = replies.iter().map(|r| r.as_str()).collect();
+        let (mut gps, written) = mock_gps_with_replies(&[], &replies);
+        let config = ReceiverConfig {
+            update_rate: Some(200),
+            sbas: Some(Sbas::Enabled),
+            min_snr: Some(20),
+            ..ReceiverConfig::default()
+        };
+
+        let acks = gps.apply_config(&config);
+        let acks: Vec<(&str, Pmtk001Ack)> = acks.into_iter().map(|(s, ack)| (s, ack.flag)).collect();
+        assert_eq!(acks, vec![
+            ("update_rate", Pmtk001Ack::Success),
+            ("sbas", Pmtk001Ack::Success),
+            ("min_snr", Pmtk001Ack::Failed),
+        ]);
+        let expected = ["PMTK220,200", "PMTK313,1", "PMTK306,20"].iter()
+            .map(|c| add_checksum(c.to_string())).collect::<String>();
+        assert_eq!(String::from_utf8(written.lock().unwrap().clone()).unwrap(), expected);
+    }
+}
+
+#[cfg(test)]
+mod user_option_test {
+    use std::time::Duration;
+
+    use crate::open_gps::mock_port::mock_gps_with_replies;
+    use crate::pmtk::send_pmtk::{add_checksum, DgpsMode, NmeaOutput, Sbas, SbasMode, UserOption};
+    use crate::datum::datums::Datum;
+
+    use super::receiver_config::ConfigMismatch;
+
+    #[test]
+    fn verify_after_cold_start() {
+        let option = UserOption {
+            update_rate: 1,
+            baud_rate: 9600,
+            nmea_output: NmeaOutput { gll: 0, rmc: 1, vtg: 1, gga: 1, gsa: 1, gsv: 5, pmtkchn_interval: 0 },
+            zda: 0,
+            datum: Datum::WGS84,
+            dgps_mode: DgpsMode::NoDgps,
+            sbas: Sbas::Enabled,
+            sbas_mode: SbasMode::Integrity,
+            nmea_v31: false,
+        };
+        let replies = [
+            add_checksum("PMTK011,MTKGPS".to_string()),
+            add_checksum(format!("PMTK590,{}", option.to_args().unwrap())),
+            // GSV went back to the factory default of every fix.
+            add_checksum("PMTK514,0,1,1,1,1,1,0,0,0,0,0,0,0,0,0,0,0,0,0".to_string()),
+            add_checksum("PMTK501,0".to_string()),
+            add_checksum("PMTK513,1".to_string()),
+            add_checksum("PMTK519,1".to_string()),
+            add_checksum("PMTK530,0".to_string()),
+        ];
+        let replies: Vec<&str> = replies.iter().map(|r| r.as_str()).collect();
+        let (mut gps, written) = mock_gps_with_replies(&[], &replies);
+        gps.command_policy.backoff = Duration::from_millis(0);
+
+        assert_eq!(gps.verify_user_option(&option), vec![ConfigMismatch {
+            setting: "nmea_output",
+            wanted: format!("{:?}", option.nmea_output),
+            actual: Some(format!("{:?}", NmeaOutput { gsv: 1, ..option.nmea_output.clone() })),
+        }]);
+        let written = String::from_utf8(written.lock().unwrap().clone()).unwrap();
+        assert!(written.starts_with(add_checksum("PMTK104".to_string()).as_str()));
+    }
+}
+
+#[cfg(test)]
+mod device_info_test {
+    use crate::datum::datums::Datum;
+    use crate::open_gps::gps::GpsSentence;
+    use crate::pmtk::send_pmtk::{FirmwareRelease, Sbas};
+
+    use super::device_info::DeviceInfo;
+
+    #[test]
+    fn display_shows_failed_queries() {
+        let info = DeviceInfo {
+            release: FirmwareRelease::parse("AXN_5.1.7_3333_19020118,0027,PA1010D,1.0"),
+            sbas: Some(Sbas::Enabled),
+            ..DeviceInfo::default()
+        };
+        let printed = info.to_string();
+        assert!(printed.contains("release       AXN_5.1.7_3333_19020118,0027,PA1010D,1.0\n"));
+        assert!(printed.contains("sbas          Enabled\n"));
+        assert!(printed.contains("datum         no reply\n"));
+    }
+
+    #[test]
+    fn log_header() {
+        let info = DeviceInfo { datum: Some(Datum::WGS84), nav_threshold: Some(0.4), ..DeviceInfo::default() };
+        GpsSentence::DeviceInfo(info.clone()).append_to("device_info_test");
+        GpsSentence::NoConnection.append_to("device_info_test");
+        let read = GpsSentence::read_from("device_info_test");
+        let _ = std::fs::remove_file("device_info_test");
+        assert_eq!(read, vec![GpsSentence::DeviceInfo(info), GpsSentence::NoConnection]);
+    }
+}
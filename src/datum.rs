@@ -0,0 +1,589 @@
+//! # Datums
+//! The MTK chips can give positions in one of 222 datums, set with pmtk_330_api_set_datum. WGS84
+//! (0) is the default and what everything else in this crate expects.
+//!
+//! DATUMS lists the datums by their MTK number, in the order of the GlobalTop datum list, with the
+//! ellipsoid and the shift to WGS84 from NIMA TR8350.2 Appendix B. Every number but 3, the user
+//! datum, is in it.
+//!
+//! The geodesy crate uses the table to convert positions back to WGS84.
+
+pub mod datums {
+    //! Datum numbers, and the table of their ellipsoids and shifts to WGS84.
+    use std::fmt;
+
+    use serde::{Deserialize, Serialize};
+
+    /// A reference ellipsoid: semi-major axis a in metres, and the inverse flattening.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub struct Ellipsoid {
+        pub name: &'static str,
+        pub a: f64,
+        pub inverse_flattening: f64,
+    }
+
+    impl Ellipsoid {
+        pub const WGS84: Ellipsoid = Ellipsoid { name: "WGS 84", a: 6_378_137.0, inverse_flattening: 298.257_223_563 };
+        pub const BESSEL_1841: Ellipsoid = Ellipsoid { name: "Bessel 1841", a: 6_377_397.155, inverse_flattening: 299.152_812_8 };
+        pub const CLARKE_1866: Ellipsoid = Ellipsoid { name: "Clarke 1866", a: 6_378_206.4, inverse_flattening: 294.978_698_2 };
+        pub const CLARKE_1880: Ellipsoid = Ellipsoid { name: "Clarke 1880", a: 6_378_249.145, inverse_flattening: 293.465 };
+        pub const INTERNATIONAL_1924: Ellipsoid = Ellipsoid { name: "International 1924", a: 6_378_388.0, inverse_flattening: 297.0 };
+        pub const KRASSOVSKY_1940: Ellipsoid = Ellipsoid { name: "Krassovsky 1940", a: 6_378_245.0, inverse_flattening: 298.3 };
+        pub const AUSTRALIAN_NATIONAL: Ellipsoid = Ellipsoid { name: "Australian National", a: 6_378_160.0, inverse_flattening: 298.25 };
+        pub const AIRY_1830: Ellipsoid = Ellipsoid { name: "Airy 1830", a: 6_377_563.396, inverse_flattening: 299.324_964_6 };
+        pub const MODIFIED_AIRY: Ellipsoid = Ellipsoid { name: "Modified Airy", a: 6_377_340.189, inverse_flattening: 299.324_964_6 };
+        pub const BESSEL_1841_NAMIBIA: Ellipsoid = Ellipsoid { name: "Bessel 1841 (Namibia)", a: 6_377_483.865, inverse_flattening: 299.152_812_8 };
+        pub const EVEREST_1830: Ellipsoid = Ellipsoid { name: "Everest 1830", a: 6_377_276.345, inverse_flattening: 300.801_7 };
+        pub const EVEREST_1948: Ellipsoid = Ellipsoid { name: "Everest 1948", a: 6_377_304.063, inverse_flattening: 300.801_7 };
+        pub const EVEREST_1956: Ellipsoid = Ellipsoid { name: "Everest 1956", a: 6_377_301.243, inverse_flattening: 300.801_7 };
+        pub const EVEREST_SABAH_SARAWAK: Ellipsoid = Ellipsoid { name: "Everest (Sabah and Sarawak)", a: 6_377_298.556, inverse_flattening: 300.801_7 };
+        pub const EVEREST_PAKISTAN: Ellipsoid = Ellipsoid { name: "Everest (Pakistan)", a: 6_377_309.613, inverse_flattening: 300.801_7 };
+        pub const GRS80: Ellipsoid = Ellipsoid { name: "GRS 80", a: 6_378_137.0, inverse_flattening: 298.257_222_101 };
+        pub const HELMERT_1906: Ellipsoid = Ellipsoid { name: "Helmert 1906", a: 6_378_200.0, inverse_flattening: 298.3 };
+        pub const HOUGH_1960: Ellipsoid = Ellipsoid { name: "Hough 1960", a: 6_378_270.0, inverse_flattening: 297.0 };
+        pub const INDONESIAN_1974: Ellipsoid = Ellipsoid { name: "Indonesian 1974", a: 6_378_160.0, inverse_flattening: 298.247 };
+        pub const MODIFIED_FISCHER_1960: Ellipsoid = Ellipsoid { name: "Modified Fischer 1960", a: 6_378_155.0, inverse_flattening: 298.3 };
+        pub const SOUTH_AMERICAN_1969: Ellipsoid = Ellipsoid { name: "South American 1969", a: 6_378_160.0, inverse_flattening: 298.25 };
+        pub const WGS72: Ellipsoid = Ellipsoid { name: "WGS 72", a: 6_378_135.0, inverse_flattening: 298.26 };
+
+        /// Flattening, f.
+        pub fn flattening(&self) -> f64 {
+            1.0 / self.inverse_flattening
+        }
+
+        /// First eccentricity squared, e^2 = f(2 - f).
+        pub fn eccentricity_sq(&self) -> f64 {
+            let f = self.flattening();
+            f * (2.0 - f)
+        }
+    }
+
+    /// One datum in the MTK list.
+    ///
+    /// - number: The MTK datum number, as sent with PMTK330.
+    /// - name: Datum name and the area the shift is for.
+    /// - ellipsoid: The ellipsoid positions in this datum are given on.
+    /// - dx, dy, dz: Shift, in metres, of the datum's earth centred coordinates to WGS84 ones.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub struct DatumInfo {
+        pub number: u16,
+        pub name: &'static str,
+        pub ellipsoid: Ellipsoid,
+        pub dx: f64,
+        pub dy: f64,
+        pub dz: f64,
+    }
+
+    const fn datum(number: u16, name: &'static str, ellipsoid: Ellipsoid, shift: [f64; 3]) -> DatumInfo {
+        DatumInfo { number, name, ellipsoid, dx: shift[0], dy: shift[1], dz: shift[2] }
+    }
+
+    /// The MTK datum list, by number. 3 is the user set datum, which has no fixed parameters.
+    ///
+    /// 218, WGS 72, has only the 4.5 m z shift: TR8350.2 also gives a small rotation and scale,
+    /// which are left out.
+    pub const DATUMS: &[DatumInfo] = &[
+        datum(0, "WGS 84", Ellipsoid::WGS84, [0.0, 0.0, 0.0]),
+        datum(1, "Tokyo, mean for Japan, South Korea, Okinawa", Ellipsoid::BESSEL_1841, [-148.0, 507.0, 685.0]),
+        datum(2, "Tokyo, Japan", Ellipsoid::BESSEL_1841, [-148.0, 507.0, 685.0]),
+        datum(4, "Adindan, Burkina Faso", Ellipsoid::CLARKE_1880, [-118.0, -14.0, 218.0]),
+        datum(5, "Adindan, Cameroon", Ellipsoid::CLARKE_1880, [-134.0, -2.0, 210.0]),
+        datum(6, "Adindan, Ethiopia", Ellipsoid::CLARKE_1880, [-165.0, -11.0, 206.0]),
+        datum(7, "Adindan, Mali", Ellipsoid::CLARKE_1880, [-123.0, -20.0, 220.0]),
+        datum(8, "Adindan, mean for Ethiopia, Sudan", Ellipsoid::CLARKE_1880, [-166.0, -15.0, 204.0]),
+        datum(9, "Adindan, Senegal", Ellipsoid::CLARKE_1880, [-128.0, -18.0, 224.0]),
+        datum(10, "Adindan, Sudan", Ellipsoid::CLARKE_1880, [-161.0, -14.0, 205.0]),
+        datum(11, "Afgooye, Somalia", Ellipsoid::KRASSOVSKY_1940, [-43.0, -163.0, 45.0]),
+        datum(12, "Ain el Abd 1970, Bahrain", Ellipsoid::INTERNATIONAL_1924, [-150.0, -250.0, -1.0]),
+        datum(13, "Ain el Abd 1970, Saudi Arabia", Ellipsoid::INTERNATIONAL_1924, [-143.0, -236.0, 7.0]),
+        datum(14, "American Samoa 1962", Ellipsoid::CLARKE_1866, [-115.0, 118.0, 426.0]),
+        datum(15, "Anna 1 Astro 1965, Cocos Islands", Ellipsoid::AUSTRALIAN_NATIONAL, [-491.0, -22.0, 435.0]),
+        datum(16, "Antigua Island Astro 1943", Ellipsoid::CLARKE_1880, [-270.0, 13.0, 62.0]),
+        datum(17, "Arc 1950, Botswana", Ellipsoid::CLARKE_1880, [-138.0, -105.0, -289.0]),
+        datum(18, "Arc 1950, Burundi", Ellipsoid::CLARKE_1880, [-153.0, -5.0, -292.0]),
+        datum(19, "Arc 1950, Lesotho", Ellipsoid::CLARKE_1880, [-125.0, -108.0, -295.0]),
+        datum(20, "Arc 1950, Malawi", Ellipsoid::CLARKE_1880, [-161.0, -73.0, -317.0]),
+        datum(
+            21,
+            "Arc 1950, mean for Botswana, Lesotho, Malawi, Swaziland, Zaire, Zambia, Zimbabwe",
+            Ellipsoid::CLARKE_1880,
+            [-143.0, -90.0, -294.0],
+        ),
+        datum(22, "Arc 1950, Swaziland", Ellipsoid::CLARKE_1880, [-134.0, -105.0, -295.0]),
+        datum(23, "Arc 1950, Zaire", Ellipsoid::CLARKE_1880, [-169.0, -19.0, -278.0]),
+        datum(24, "Arc 1950, Zambia", Ellipsoid::CLARKE_1880, [-147.0, -74.0, -283.0]),
+        datum(25, "Arc 1950, Zimbabwe", Ellipsoid::CLARKE_1880, [-142.0, -96.0, -293.0]),
+        datum(26, "Arc 1960, mean for Kenya, Tanzania", Ellipsoid::CLARKE_1880, [-160.0, -6.0, -302.0]),
+        datum(27, "Arc 1960, Kenya", Ellipsoid::CLARKE_1880, [-157.0, -2.0, -299.0]),
+        datum(28, "Arc 1960, Tanzania", Ellipsoid::CLARKE_1880, [-175.0, -23.0, -303.0]),
+        datum(29, "Ascension Island 1958", Ellipsoid::INTERNATIONAL_1924, [-205.0, 107.0, 53.0]),
+        datum(30, "Astro Beacon E 1945, Iwo Jima", Ellipsoid::INTERNATIONAL_1924, [145.0, 75.0, -272.0]),
+        datum(31, "Astro DOS 71/4, St Helena Island", Ellipsoid::INTERNATIONAL_1924, [-320.0, 550.0, -494.0]),
+        datum(32, "Astro Tern Island (FRIG) 1961", Ellipsoid::INTERNATIONAL_1924, [114.0, -116.0, -333.0]),
+        datum(33, "Astronomical Station 1952, Marcus Island", Ellipsoid::INTERNATIONAL_1924, [124.0, -234.0, -25.0]),
+        datum(
+            34,
+            "Australian Geodetic 1966, Australia, Tasmania",
+            Ellipsoid::AUSTRALIAN_NATIONAL,
+            [-133.0, -48.0, 148.0],
+        ),
+        datum(
+            35,
+            "Australian Geodetic 1984, Australia, Tasmania",
+            Ellipsoid::AUSTRALIAN_NATIONAL,
+            [-134.0, -48.0, 149.0],
+        ),
+        datum(36, "Ayabelle Lighthouse, Djibouti", Ellipsoid::CLARKE_1880, [-79.0, -129.0, 145.0]),
+        datum(
+            37,
+            "Bellevue (IGN), Efate and Erromango Islands",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-127.0, -769.0, 472.0],
+        ),
+        datum(38, "Bermuda 1957", Ellipsoid::CLARKE_1866, [-73.0, 213.0, 296.0]),
+        datum(39, "Bissau, Guinea-Bissau", Ellipsoid::INTERNATIONAL_1924, [-173.0, 253.0, 27.0]),
+        datum(40, "Bogota Observatory, Colombia", Ellipsoid::INTERNATIONAL_1924, [307.0, 304.0, -318.0]),
+        datum(
+            41,
+            "Bukit Rimpah, Indonesia (Bangka and Belitung Islands)",
+            Ellipsoid::BESSEL_1841,
+            [-384.0, 664.0, -48.0],
+        ),
+        datum(
+            42,
+            "Camp Area Astro, Antarctica (McMurdo Camp Area)",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-104.0, -129.0, 239.0],
+        ),
+        datum(43, "Campo Inchauspe, Argentina", Ellipsoid::INTERNATIONAL_1924, [-148.0, 136.0, 90.0]),
+        datum(44, "Canton Astro 1966, Phoenix Islands", Ellipsoid::INTERNATIONAL_1924, [298.0, -304.0, -375.0]),
+        datum(45, "Cape, South Africa", Ellipsoid::CLARKE_1880, [-136.0, -108.0, -292.0]),
+        datum(46, "Cape Canaveral, Bahamas, Florida", Ellipsoid::CLARKE_1866, [-2.0, 151.0, 181.0]),
+        datum(47, "Carthage, Tunisia", Ellipsoid::CLARKE_1880, [-263.0, 6.0, 431.0]),
+        datum(
+            48,
+            "Chatham Island Astro 1971, New Zealand (Chatham Island)",
+            Ellipsoid::INTERNATIONAL_1924,
+            [175.0, -38.0, 113.0],
+        ),
+        datum(49, "Chua Astro, Paraguay", Ellipsoid::INTERNATIONAL_1924, [-134.0, 229.0, -29.0]),
+        datum(50, "Corrego Alegre, Brazil", Ellipsoid::INTERNATIONAL_1924, [-206.0, 172.0, -6.0]),
+        datum(51, "Dabola, Guinea", Ellipsoid::CLARKE_1880, [-83.0, 37.0, 124.0]),
+        datum(52, "Deception Island, Antarctica", Ellipsoid::CLARKE_1880, [260.0, 12.0, -147.0]),
+        datum(53, "Djakarta (Batavia), Indonesia (Sumatra)", Ellipsoid::BESSEL_1841, [-377.0, 681.0, -50.0]),
+        datum(
+            54,
+            "DOS 1968, New Georgia Islands (Gizo Island)",
+            Ellipsoid::INTERNATIONAL_1924,
+            [230.0, -199.0, -752.0],
+        ),
+        datum(55, "Easter Island 1967", Ellipsoid::INTERNATIONAL_1924, [211.0, 147.0, 111.0]),
+        datum(56, "Estonia Coordinate System 1937", Ellipsoid::BESSEL_1841, [374.0, 150.0, 588.0]),
+        datum(57, "European 1950, Cyprus", Ellipsoid::INTERNATIONAL_1924, [-104.0, -101.0, -140.0]),
+        datum(58, "European 1950, Egypt", Ellipsoid::INTERNATIONAL_1924, [-130.0, -117.0, -151.0]),
+        datum(
+            59,
+            "European 1950, England, Channel Islands, Scotland, Shetland Islands",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-86.0, -96.0, -120.0],
+        ),
+        datum(
+            60,
+            "European 1950, England, Ireland, Scotland, Shetland Islands",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-86.0, -96.0, -120.0],
+        ),
+        datum(61, "European 1950, Finland, Norway", Ellipsoid::INTERNATIONAL_1924, [-87.0, -95.0, -120.0]),
+        datum(62, "European 1950, Greece", Ellipsoid::INTERNATIONAL_1924, [-84.0, -95.0, -130.0]),
+        datum(63, "European 1950, Iran", Ellipsoid::INTERNATIONAL_1924, [-117.0, -132.0, -164.0]),
+        datum(64, "European 1950, Italy (Sardinia)", Ellipsoid::INTERNATIONAL_1924, [-97.0, -103.0, -120.0]),
+        datum(65, "European 1950, Italy (Sicily)", Ellipsoid::INTERNATIONAL_1924, [-97.0, -88.0, -135.0]),
+        datum(66, "European 1950, Malta", Ellipsoid::INTERNATIONAL_1924, [-107.0, -88.0, -149.0]),
+        datum(
+            67,
+            "European 1950, mean for Austria, Belgium, Denmark, Finland, France, W Germany, Gibraltar, Greece, Italy, Luxembourg, Netherlands, Norway, Portugal, Spain, Sweden, Switzerland",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-87.0, -98.0, -121.0],
+        ),
+        datum(
+            68,
+            "European 1950, mean for Austria, Denmark, France, W Germany, Netherlands, Switzerland",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-87.0, -96.0, -120.0],
+        ),
+        datum(
+            69,
+            "European 1950, mean for Iraq, Israel, Jordan, Lebanon, Kuwait, Saudi Arabia, Syria",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-103.0, -106.0, -141.0],
+        ),
+        datum(70, "European 1950, Portugal, Spain", Ellipsoid::INTERNATIONAL_1924, [-84.0, -107.0, -120.0]),
+        datum(71, "European 1950, Tunisia", Ellipsoid::INTERNATIONAL_1924, [-112.0, -77.0, -145.0]),
+        datum(
+            72,
+            "European 1979, mean for Austria, Finland, Netherlands, Norway, Spain, Sweden, Switzerland",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-86.0, -98.0, -119.0],
+        ),
+        datum(73, "Fort Thomas 1955, Nevis, St Kitts", Ellipsoid::CLARKE_1880, [-7.0, 215.0, 225.0]),
+        datum(74, "Gan 1970, Republic of Maldives", Ellipsoid::INTERNATIONAL_1924, [-133.0, -321.0, 50.0]),
+        datum(75, "Geodetic Datum 1949, New Zealand", Ellipsoid::INTERNATIONAL_1924, [84.0, -22.0, 209.0]),
+        datum(
+            76,
+            "Graciosa Base SW 1948, Azores (Faial, Graciosa, Pico, Sao Jorge, Terceira)",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-104.0, 167.0, -38.0],
+        ),
+        datum(77, "Guam 1963", Ellipsoid::CLARKE_1866, [-100.0, -248.0, 259.0]),
+        datum(78, "Gunung Segara, Indonesia (Kalimantan)", Ellipsoid::BESSEL_1841, [-403.0, 684.0, 41.0]),
+        datum(79, "GUX 1 Astro, Guadalcanal Island", Ellipsoid::INTERNATIONAL_1924, [252.0, -209.0, -751.0]),
+        datum(80, "Herat North, Afghanistan", Ellipsoid::INTERNATIONAL_1924, [-333.0, -222.0, 114.0]),
+        datum(
+            81,
+            "Hermannskogel, Croatia-Serbia, Bosnia-Herzegovina",
+            Ellipsoid::BESSEL_1841_NAMIBIA,
+            [682.0, -203.0, 480.0],
+        ),
+        datum(82, "Hjorsey 1955, Iceland", Ellipsoid::INTERNATIONAL_1924, [-73.0, 46.0, -86.0]),
+        datum(83, "Hong Kong 1963", Ellipsoid::INTERNATIONAL_1924, [-156.0, -271.0, -189.0]),
+        datum(84, "Hu-Tzu-Shan, Taiwan", Ellipsoid::INTERNATIONAL_1924, [-637.0, -549.0, -203.0]),
+        datum(85, "Indian, Bangladesh", Ellipsoid::EVEREST_1830, [282.0, 726.0, 254.0]),
+        datum(86, "Indian, India, Nepal", Ellipsoid::EVEREST_1956, [295.0, 736.0, 257.0]),
+        datum(87, "Indian, Pakistan", Ellipsoid::EVEREST_PAKISTAN, [283.0, 682.0, 231.0]),
+        datum(88, "Indian 1954, Thailand", Ellipsoid::EVEREST_1830, [217.0, 823.0, 299.0]),
+        datum(89, "Indian 1960, Vietnam (Con Son Island)", Ellipsoid::EVEREST_1830, [182.0, 915.0, 344.0]),
+        datum(90, "Indian 1960, Vietnam (near 16N)", Ellipsoid::EVEREST_1830, [198.0, 881.0, 317.0]),
+        datum(91, "Indian 1975, Thailand", Ellipsoid::EVEREST_1830, [209.0, 818.0, 290.0]),
+        datum(92, "Indonesian 1974", Ellipsoid::INDONESIAN_1974, [-24.0, -15.0, 5.0]),
+        datum(93, "Ireland 1965", Ellipsoid::MODIFIED_AIRY, [506.0, -122.0, 611.0]),
+        datum(94, "ISTS 061 Astro 1968, South Georgia Islands", Ellipsoid::INTERNATIONAL_1924, [-794.0, 119.0, -298.0]),
+        datum(95, "ISTS 073 Astro 1969, Diego Garcia", Ellipsoid::INTERNATIONAL_1924, [208.0, -435.0, -229.0]),
+        datum(96, "Johnston Island 1961", Ellipsoid::INTERNATIONAL_1924, [189.0, -79.0, -202.0]),
+        datum(97, "Kandawala, Sri Lanka", Ellipsoid::EVEREST_1830, [-97.0, 787.0, 86.0]),
+        datum(98, "Kerguelen Island 1949", Ellipsoid::INTERNATIONAL_1924, [145.0, -187.0, 103.0]),
+        datum(99, "Kertau 1948, West Malaysia and Singapore", Ellipsoid::EVEREST_1948, [-11.0, 851.0, 5.0]),
+        datum(100, "Kusaie Astro 1951, Caroline Islands", Ellipsoid::INTERNATIONAL_1924, [647.0, 1777.0, -1124.0]),
+        datum(101, "L.C. 5 Astro 1961, Cayman Brac Island", Ellipsoid::CLARKE_1866, [42.0, 124.0, 147.0]),
+        datum(102, "Leigon, Ghana", Ellipsoid::CLARKE_1880, [-130.0, 29.0, 364.0]),
+        datum(103, "Liberia 1964", Ellipsoid::CLARKE_1880, [-90.0, 40.0, 88.0]),
+        datum(104, "Luzon, Philippines (excluding Mindanao)", Ellipsoid::CLARKE_1866, [-133.0, -77.0, -51.0]),
+        datum(105, "Luzon, Philippines (Mindanao)", Ellipsoid::CLARKE_1866, [-133.0, -79.0, -72.0]),
+        datum(106, "M'Poraloko, Gabon", Ellipsoid::CLARKE_1880, [-74.0, -130.0, 42.0]),
+        datum(107, "Mahe 1971, Mahe Island", Ellipsoid::CLARKE_1880, [41.0, -220.0, -134.0]),
+        datum(108, "Massawa, Ethiopia (Eritrea)", Ellipsoid::BESSEL_1841, [639.0, 405.0, 60.0]),
+        datum(109, "Merchich, Morocco", Ellipsoid::CLARKE_1880, [31.0, 146.0, 47.0]),
+        datum(110, "Midway Astro 1961, Midway Islands", Ellipsoid::INTERNATIONAL_1924, [912.0, -58.0, 1227.0]),
+        datum(111, "Minna, Cameroon", Ellipsoid::CLARKE_1880, [-81.0, -84.0, 115.0]),
+        datum(112, "Minna, Nigeria", Ellipsoid::CLARKE_1880, [-92.0, -93.0, 122.0]),
+        datum(113, "Montserrat Island Astro 1958", Ellipsoid::CLARKE_1880, [174.0, 359.0, 365.0]),
+        datum(114, "Nahrwan, Oman (Masirah Island)", Ellipsoid::CLARKE_1880, [-247.0, -148.0, 369.0]),
+        datum(115, "Nahrwan, Saudi Arabia", Ellipsoid::CLARKE_1880, [-243.0, -192.0, 477.0]),
+        datum(116, "Nahrwan, United Arab Emirates", Ellipsoid::CLARKE_1880, [-249.0, -156.0, 381.0]),
+        datum(117, "Naparima BWI, Trinidad and Tobago", Ellipsoid::INTERNATIONAL_1924, [-10.0, 375.0, 165.0]),
+        datum(
+            118,
+            "North American 1927, Alaska (excluding Aleutian Islands)",
+            Ellipsoid::CLARKE_1866,
+            [-5.0, 135.0, 172.0],
+        ),
+        datum(
+            119,
+            "North American 1927, Alaska (Aleutian Islands east of 180W)",
+            Ellipsoid::CLARKE_1866,
+            [-2.0, 152.0, 149.0],
+        ),
+        datum(
+            120,
+            "North American 1927, Alaska (Aleutian Islands west of 180W)",
+            Ellipsoid::CLARKE_1866,
+            [2.0, 204.0, 105.0],
+        ),
+        datum(
+            121,
+            "North American 1927, Bahamas (except San Salvador Island)",
+            Ellipsoid::CLARKE_1866,
+            [-4.0, 154.0, 178.0],
+        ),
+        datum(122, "North American 1927, Bahamas (San Salvador Island)", Ellipsoid::CLARKE_1866, [1.0, 140.0, 165.0]),
+        datum(
+            123,
+            "North American 1927, Canada (Alberta, British Columbia)",
+            Ellipsoid::CLARKE_1866,
+            [-7.0, 162.0, 188.0],
+        ),
+        datum(124, "North American 1927, Canada (Manitoba, Ontario)", Ellipsoid::CLARKE_1866, [-9.0, 157.0, 184.0]),
+        datum(
+            125,
+            "North American 1927, Canada (New Brunswick, Newfoundland, Nova Scotia, Quebec)",
+            Ellipsoid::CLARKE_1866,
+            [-22.0, 160.0, 190.0],
+        ),
+        datum(
+            126,
+            "North American 1927, Canada (Northwest Territories, Saskatchewan)",
+            Ellipsoid::CLARKE_1866,
+            [4.0, 159.0, 188.0],
+        ),
+        datum(127, "North American 1927, Canada (Yukon)", Ellipsoid::CLARKE_1866, [-7.0, 139.0, 181.0]),
+        datum(128, "North American 1927, Canal Zone", Ellipsoid::CLARKE_1866, [0.0, 125.0, 201.0]),
+        datum(129, "North American 1927, Cuba", Ellipsoid::CLARKE_1866, [-9.0, 152.0, 178.0]),
+        datum(130, "North American 1927, Greenland (Hayes Peninsula)", Ellipsoid::CLARKE_1866, [11.0, 114.0, 195.0]),
+        datum(
+            131,
+            "North American 1927, mean for Antigua, Barbados, Barbuda, Caicos Islands, Cuba, Dominican Republic, Grand Cayman, Jamaica, Turks Islands",
+            Ellipsoid::CLARKE_1866,
+            [-3.0, 142.0, 183.0],
+        ),
+        datum(
+            132,
+            "North American 1927, mean for Belize, Costa Rica, El Salvador, Guatemala, Honduras, Nicaragua",
+            Ellipsoid::CLARKE_1866,
+            [0.0, 125.0, 194.0],
+        ),
+        datum(133, "North American 1927, mean for Canada", Ellipsoid::CLARKE_1866, [-10.0, 158.0, 187.0]),
+        datum(134, "North American 1927, mean for CONUS", Ellipsoid::CLARKE_1866, [-8.0, 160.0, 176.0]),
+        datum(
+            135,
+            "North American 1927, mean for CONUS (east of the Mississippi, including Louisiana, Missouri, Minnesota)",
+            Ellipsoid::CLARKE_1866,
+            [-9.0, 161.0, 179.0],
+        ),
+        datum(
+            136,
+            "North American 1927, mean for CONUS (west of the Mississippi, excluding Louisiana, Missouri, Minnesota)",
+            Ellipsoid::CLARKE_1866,
+            [-8.0, 159.0, 175.0],
+        ),
+        datum(137, "North American 1927, Mexico", Ellipsoid::CLARKE_1866, [-12.0, 130.0, 190.0]),
+        datum(138, "North American 1983, Alaska (excluding Aleutian Islands)", Ellipsoid::GRS80, [0.0, 0.0, 0.0]),
+        datum(139, "North American 1983, Aleutian Islands", Ellipsoid::GRS80, [-2.0, 0.0, 4.0]),
+        datum(140, "North American 1983, Canada", Ellipsoid::GRS80, [0.0, 0.0, 0.0]),
+        datum(141, "North American 1983, CONUS", Ellipsoid::GRS80, [0.0, 0.0, 0.0]),
+        datum(142, "North American 1983, Hawaii", Ellipsoid::GRS80, [1.0, 1.0, -1.0]),
+        datum(143, "North American 1983, Mexico, Central America", Ellipsoid::GRS80, [0.0, 0.0, 0.0]),
+        datum(144, "North Sahara 1959, Algeria", Ellipsoid::CLARKE_1880, [-186.0, -93.0, 310.0]),
+        datum(
+            145,
+            "Observatorio Meteorologico 1939, Azores (Corvo and Flores Islands)",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-425.0, -169.0, 81.0],
+        ),
+        datum(146, "Old Egyptian 1907, Egypt", Ellipsoid::HELMERT_1906, [-130.0, 110.0, -13.0]),
+        datum(147, "Old Hawaiian, Hawaii", Ellipsoid::CLARKE_1866, [89.0, -279.0, -183.0]),
+        datum(148, "Old Hawaiian, Kauai", Ellipsoid::CLARKE_1866, [45.0, -290.0, -172.0]),
+        datum(149, "Old Hawaiian, Maui", Ellipsoid::CLARKE_1866, [65.0, -290.0, -190.0]),
+        datum(150, "Old Hawaiian, mean for Hawaii, Kauai, Maui, Oahu", Ellipsoid::CLARKE_1866, [61.0, -285.0, -181.0]),
+        datum(151, "Old Hawaiian, Oahu", Ellipsoid::CLARKE_1866, [58.0, -283.0, -182.0]),
+        datum(152, "Oman", Ellipsoid::CLARKE_1880, [-346.0, -1.0, 224.0]),
+        datum(153, "Ordnance Survey Great Britain 1936, England", Ellipsoid::AIRY_1830, [371.0, -112.0, 434.0]),
+        datum(
+            154,
+            "Ordnance Survey Great Britain 1936, England, Isle of Man, Wales",
+            Ellipsoid::AIRY_1830,
+            [371.0, -111.0, 434.0],
+        ),
+        datum(
+            155,
+            "Ordnance Survey Great Britain 1936, mean for England, Isle of Man, Scotland, Shetland Islands, Wales",
+            Ellipsoid::AIRY_1830,
+            [375.0, -111.0, 431.0],
+        ),
+        datum(
+            156,
+            "Ordnance Survey Great Britain 1936, Scotland, Shetland Islands",
+            Ellipsoid::AIRY_1830,
+            [384.0, -111.0, 425.0],
+        ),
+        datum(157, "Ordnance Survey Great Britain 1936, Wales", Ellipsoid::AIRY_1830, [370.0, -108.0, 434.0]),
+        datum(158, "Pico de las Nieves, Canary Islands", Ellipsoid::INTERNATIONAL_1924, [-307.0, -92.0, 127.0]),
+        datum(159, "Pitcairn Astro 1967, Pitcairn Island", Ellipsoid::INTERNATIONAL_1924, [185.0, 165.0, 42.0]),
+        datum(160, "Point 58, mean for Burkina Faso, Niger", Ellipsoid::CLARKE_1880, [-106.0, -129.0, 165.0]),
+        datum(161, "Pointe Noire 1948, Congo", Ellipsoid::CLARKE_1880, [-148.0, 51.0, -291.0]),
+        datum(
+            162,
+            "Porto Santo 1936, Porto Santo, Madeira Islands",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-499.0, -249.0, 314.0],
+        ),
+        datum(163, "Provisional South American 1956, Bolivia", Ellipsoid::INTERNATIONAL_1924, [-270.0, 188.0, -388.0]),
+        datum(
+            164,
+            "Provisional South American 1956, Chile (northern, near 19S)",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-270.0, 183.0, -390.0],
+        ),
+        datum(
+            165,
+            "Provisional South American 1956, Chile (southern, near 43S)",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-305.0, 243.0, -442.0],
+        ),
+        datum(166, "Provisional South American 1956, Colombia", Ellipsoid::INTERNATIONAL_1924, [-282.0, 169.0, -371.0]),
+        datum(167, "Provisional South American 1956, Ecuador", Ellipsoid::INTERNATIONAL_1924, [-278.0, 171.0, -367.0]),
+        datum(168, "Provisional South American 1956, Guyana", Ellipsoid::INTERNATIONAL_1924, [-298.0, 159.0, -369.0]),
+        datum(
+            169,
+            "Provisional South American 1956, mean for Bolivia, Chile, Colombia, Ecuador, Guyana, Peru, Venezuela",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-288.0, 175.0, -376.0],
+        ),
+        datum(170, "Provisional South American 1956, Peru", Ellipsoid::INTERNATIONAL_1924, [-279.0, 175.0, -379.0]),
+        datum(
+            171,
+            "Provisional South American 1956, Venezuela",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-295.0, 173.0, -371.0],
+        ),
+        datum(
+            172,
+            "Provisional South Chilean 1963, Chile (near 53S, Hito XVIII)",
+            Ellipsoid::INTERNATIONAL_1924,
+            [16.0, 196.0, 93.0],
+        ),
+        datum(173, "Puerto Rico, Puerto Rico, Virgin Islands", Ellipsoid::CLARKE_1866, [11.0, 72.0, -101.0]),
+        datum(174, "Pulkovo 1942, Russia", Ellipsoid::KRASSOVSKY_1940, [28.0, -130.0, -95.0]),
+        datum(175, "Qatar National, Qatar", Ellipsoid::INTERNATIONAL_1924, [-128.0, -283.0, 22.0]),
+        datum(176, "Qornoq, Greenland (south)", Ellipsoid::INTERNATIONAL_1924, [164.0, 138.0, -189.0]),
+        datum(177, "Reunion, Mascarene Islands", Ellipsoid::INTERNATIONAL_1924, [94.0, -948.0, -1262.0]),
+        datum(178, "Rome 1940, Italy (Sardinia)", Ellipsoid::INTERNATIONAL_1924, [-225.0, -65.0, 9.0]),
+        datum(179, "S-42 (Pulkovo 1942), Hungary", Ellipsoid::KRASSOVSKY_1940, [28.0, -121.0, -77.0]),
+        datum(180, "S-42 (Pulkovo 1942), Poland", Ellipsoid::KRASSOVSKY_1940, [23.0, -124.0, -82.0]),
+        datum(181, "S-42 (Pulkovo 1942), Czechoslovakia", Ellipsoid::KRASSOVSKY_1940, [26.0, -121.0, -78.0]),
+        datum(182, "S-42 (Pulkovo 1942), Latvia", Ellipsoid::KRASSOVSKY_1940, [24.0, -124.0, -82.0]),
+        datum(183, "S-42 (Pulkovo 1942), Kazakhstan", Ellipsoid::KRASSOVSKY_1940, [15.0, -130.0, -84.0]),
+        datum(184, "S-42 (Pulkovo 1942), Albania", Ellipsoid::KRASSOVSKY_1940, [24.0, -130.0, -92.0]),
+        datum(185, "S-42 (Pulkovo 1942), Romania", Ellipsoid::KRASSOVSKY_1940, [28.0, -121.0, -77.0]),
+        datum(186, "S-JTSK, Czechoslovakia (before 1 Jan 1993)", Ellipsoid::BESSEL_1841, [589.0, 76.0, 480.0]),
+        datum(187, "Santo (DOS) 1965, Espirito Santo Island", Ellipsoid::INTERNATIONAL_1924, [170.0, 42.0, 84.0]),
+        datum(
+            188,
+            "Sao Braz, Azores (Sao Miguel, Santa Maria Islands)",
+            Ellipsoid::INTERNATIONAL_1924,
+            [-203.0, 141.0, 53.0],
+        ),
+        datum(189, "Sapper Hill 1943, East Falkland Island", Ellipsoid::INTERNATIONAL_1924, [-355.0, 21.0, 72.0]),
+        datum(190, "Schwarzeck, Namibia", Ellipsoid::BESSEL_1841_NAMIBIA, [616.0, 97.0, -251.0]),
+        datum(191, "Selvagem Grande 1938, Salvage Islands", Ellipsoid::INTERNATIONAL_1924, [-289.0, -124.0, 60.0]),
+        datum(192, "Sierra Leone 1960", Ellipsoid::CLARKE_1880, [-88.0, 4.0, 101.0]),
+        datum(193, "South American 1969, Argentina", Ellipsoid::SOUTH_AMERICAN_1969, [-62.0, -1.0, -37.0]),
+        datum(194, "South American 1969, Bolivia", Ellipsoid::SOUTH_AMERICAN_1969, [-61.0, 2.0, -48.0]),
+        datum(195, "South American 1969, Brazil", Ellipsoid::SOUTH_AMERICAN_1969, [-60.0, -2.0, -41.0]),
+        datum(196, "South American 1969, Chile", Ellipsoid::SOUTH_AMERICAN_1969, [-75.0, -1.0, -44.0]),
+        datum(197, "South American 1969, Colombia", Ellipsoid::SOUTH_AMERICAN_1969, [-44.0, 6.0, -36.0]),
+        datum(198, "South American 1969, Ecuador", Ellipsoid::SOUTH_AMERICAN_1969, [-48.0, 3.0, -44.0]),
+        datum(
+            199,
+            "South American 1969, Ecuador (Baltra, Galapagos)",
+            Ellipsoid::SOUTH_AMERICAN_1969,
+            [-47.0, 26.0, -42.0],
+        ),
+        datum(200, "South American 1969, Guyana", Ellipsoid::SOUTH_AMERICAN_1969, [-53.0, 3.0, -47.0]),
+        datum(
+            201,
+            "South American 1969, mean for Argentina, Bolivia, Brazil, Chile, Colombia, Ecuador, Guyana, Paraguay, Peru, Trinidad and Tobago, Venezuela",
+            Ellipsoid::SOUTH_AMERICAN_1969,
+            [-57.0, 1.0, -41.0],
+        ),
+        datum(202, "South American 1969, Paraguay", Ellipsoid::SOUTH_AMERICAN_1969, [-61.0, 2.0, -33.0]),
+        datum(203, "South American 1969, Peru", Ellipsoid::SOUTH_AMERICAN_1969, [-58.0, 0.0, -44.0]),
+        datum(204, "South American 1969, Trinidad and Tobago", Ellipsoid::SOUTH_AMERICAN_1969, [-45.0, 12.0, -33.0]),
+        datum(205, "South American 1969, Venezuela", Ellipsoid::SOUTH_AMERICAN_1969, [-45.0, 8.0, -33.0]),
+        datum(206, "South Asia, Singapore", Ellipsoid::MODIFIED_FISCHER_1960, [7.0, -10.0, -26.0]),
+        datum(207, "Tananarive Observatory 1925, Madagascar", Ellipsoid::INTERNATIONAL_1924, [-189.0, -242.0, -91.0]),
+        datum(
+            208,
+            "Timbalai 1948, Brunei, E Malaysia (Sabah, Sarawak)",
+            Ellipsoid::EVEREST_SABAH_SARAWAK,
+            [-679.0, 669.0, -48.0],
+        ),
+        datum(209, "Tokyo, Japan", Ellipsoid::BESSEL_1841, [-148.0, 507.0, 685.0]),
+        datum(210, "Tokyo, mean for Japan, South Korea, Okinawa", Ellipsoid::BESSEL_1841, [-148.0, 507.0, 685.0]),
+        datum(211, "Tokyo, Okinawa", Ellipsoid::BESSEL_1841, [-158.0, 507.0, 676.0]),
+        datum(212, "Tokyo, South Korea", Ellipsoid::BESSEL_1841, [-147.0, 506.0, 687.0]),
+        datum(213, "Tristan Astro 1968, Tristan da Cunha", Ellipsoid::INTERNATIONAL_1924, [-632.0, 438.0, -609.0]),
+        datum(214, "Viti Levu 1916, Fiji (Viti Levu Island)", Ellipsoid::CLARKE_1880, [51.0, 391.0, -36.0]),
+        datum(215, "Voirol 1960, Algeria", Ellipsoid::CLARKE_1880, [-123.0, -206.0, 219.0]),
+        datum(216, "Wake Island Astro 1952, Wake Atoll", Ellipsoid::INTERNATIONAL_1924, [276.0, -57.0, 149.0]),
+        datum(217, "Wake-Eniwetok 1960, Marshall Islands", Ellipsoid::HOUGH_1960, [102.0, 52.0, -38.0]),
+        datum(218, "WGS 72", Ellipsoid::WGS72, [0.0, 0.0, 4.5]),
+        datum(219, "WGS 84, global definition", Ellipsoid::WGS84, [0.0, 0.0, 0.0]),
+        datum(220, "Yacare, Uruguay", Ellipsoid::INTERNATIONAL_1924, [-155.0, 171.0, 37.0]),
+        datum(221, "Zanderij, Suriname", Ellipsoid::INTERNATIONAL_1924, [-265.0, 120.0, -358.0]),
+    ];
+
+    /// An MTK datum number, 0 to 221. Serialises as the number.
+    ///
+    /// ```ignore
+    /// gps.pmtk_330_api_set_datum(Datum::TOKYO_M);
+    /// let datum = gps.pmtk_430_api_q_datum();
+    /// ```
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+    pub struct Datum(pub u16);
+
+    impl Datum {
+        pub const WGS84: Datum = Datum(0);
+        pub const TOKYO_M: Datum = Datum(1);
+        pub const TOKYO_A: Datum = Datum(2);
+        pub const USER: Datum = Datum(3);
+        /// Number of datums the MTK chips know.
+        pub const COUNT: u16 = 222;
+
+        /// The datum's ellipsoid and shift to WGS84, None for the user datum or a number past the end.
+        pub fn info(&self) -> Option<&'static DatumInfo> {
+            DATUMS.iter().find(|d| d.number == self.0)
+        }
+
+        /// Datum by name, as in DATUMS. Not case sensitive.
+        pub fn from_name(name: &str) -> Option<Datum> {
+            DATUMS.iter().find(|d| d.name.eq_ignore_ascii_case(name)).map(|d| Datum(d.number))
+        }
+    }
+
+    impl Default for Datum {
+        fn default() -> Datum {
+            Datum::WGS84
+        }
+    }
+
+    impl fmt::Display for Datum {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.info() {
+                Some(info) => write!(f, "{} ({})", self.0, info.name),
+                None => write!(f, "{}", self.0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod datum_test {
+    use super::datums::{Datum, Ellipsoid, DATUMS};
+
+    #[test]
+    fn table() {
+        assert_eq!(Datum::WGS84.info().unwrap().ellipsoid, Ellipsoid::WGS84);
+        assert_eq!(Datum::TOKYO_M.info().unwrap().dy, 507.0);
+        assert_eq!(Datum(3).info(), None);
+        assert_eq!(Datum::from_name("tokyo, japan"), Some(Datum::TOKYO_A));
+        assert_eq!(Datum(12).to_string(), "12 (Ain el Abd 1970, Bahrain)");
+        assert!(DATUMS.windows(2).all(|d| d[0].number < d[1].number));
+        assert!(DATUMS.iter().all(|d| d.number < Datum::COUNT));
+    }
+
+    #[test]
+    fn every_datum_but_user_has_info() {
+        for number in 0..Datum::COUNT {
+            assert_eq!(Datum(number).info().is_some(), Datum(number) != Datum::USER, "datum {}", number);
+        }
+        assert_eq!(DATUMS.len(), Datum::COUNT as usize - 1);
+        assert_eq!(Datum(221).info().unwrap().name, "Zanderij, Suriname");
+        let wgs72 = Datum(218).info().unwrap();
+        assert_eq!((wgs72.name, wgs72.ellipsoid, wgs72.dz), ("WGS 72", Ellipsoid::WGS72, 4.5));
+        assert_eq!(Datum(157).info().unwrap().ellipsoid, Ellipsoid::AIRY_1830);
+    }
+
+    #[test]
+    fn wgs84_eccentricity() {
+        assert!((Ellipsoid::WGS84.eccentricity_sq() - 0.006_694_379_990_14).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,253 @@
+//! # Line framing
+//! LineFramer cuts the bytes read from the port into lines. gps.read_line() reads the port in bulk
+//! and gives the bytes to it, rather than reading a byte at a time.
+//!
+//! - A line starts at a $ and ends at a \n. Bytes before a $ are garbage and are dropped, so a
+//!   line that was started before the port was opened doesn't give an InvalidSentence.
+//! - A $ part way through a line starts a new line, dropping the cut off one.
+//! - Bytes after the last full line are kept for the next call.
+//! - A line that isn't utf8 is given as invalid bytes on its own, without dropping the lines
+//!   around it.
+//!
+//! FramerStats counts what was dropped, which is a good check of the wiring and baud rate.
+//! ```ignore
+//! let sentence = gps.update();
+//! println!("{:?}", gps.framer.stats());
+//! ```
+
+pub mod line_framer {
+    //! LineFramer and its statistics.
+    use std::time::{Instant, SystemTime};
+
+    /// A line is dropped once it is this long without a \n. Twice the longest PMTK sentence, so
+    /// only runaway garbage is dropped.
+    pub const MAX_LINE_LEN: usize = 512;
+
+    /// Counts of what the framer has been given and what it dropped.
+    /// - bytes: every byte given to the framer.
+    /// - lines: full lines given, utf8 or not.
+    /// - invalid_lines: lines that were not utf8.
+    /// - garbage_bytes: bytes dropped because they were not part of a line: before a $, in a line
+    ///   cut off by a $, or in a line longer than MAX_LINE_LEN.
+    /// - dropped_lines: lines cut off by a $ or longer than MAX_LINE_LEN.
+    #[derive(Debug, PartialEq, Default, Clone, Copy)]
+    pub struct FramerStats {
+        pub bytes: u64,
+        pub lines: u64,
+        pub invalid_lines: u64,
+        pub garbage_bytes: u64,
+        pub dropped_lines: u64,
+    }
+
+    /// Cuts bytes into lines. See the [module docs](index.html).
+    #[derive(Debug)]
+    pub struct LineFramer {
+        buffer: Vec<u8>,
+        /// Start of the first byte not looked at yet.
+        scanned: usize,
+        garbage: Vec<u8>,
+        stats: FramerStats,
+        last_input: Option<(Instant, SystemTime)>,
+    }
+
+    impl Default for LineFramer {
+        fn default() -> LineFramer {
+            LineFramer::new()
+        }
+    }
+
+    impl LineFramer {
+        pub fn new() -> LineFramer {
+            LineFramer {
+                buffer: Vec::with_capacity(MAX_LINE_LEN * 2),
+                scanned: 0,
+                garbage: Vec::new(),
+                stats: FramerStats::default(),
+                last_input: None,
+            }
+        }
+
+        /// Adds bytes read from the port.
+        pub fn push(&mut self, bytes: &[u8]) {
+            if bytes.is_empty() {
+                return;
+            }
+            self.stats.bytes += bytes.len() as u64;
+            self.last_input = Some((Instant::now(), SystemTime::now()));
+            self.buffer.extend_from_slice(bytes);
+        }
+
+        /// The next full line, with its \r\n. Err with the bytes of a line that isn't utf8.
+        /// None until a full line has been pushed.
+        pub fn next_line(&mut self) -> Option<Result<String, Vec<u8>>> {
+            loop {
+                // Drop everything before the first $.
+                match self.buffer.iter().position(|b| *b == b'$') {
+                    Some(0) => (),
+                    Some(start) => self.drop_garbage(start, false),
+                    None => {
+                        let len = self.buffer.len();
+                        self.drop_garbage(len, false);
+                        return None;
+                    }
+                }
+                let end = self.buffer[self.scanned.max(1)..].iter()
+                    .position(|b| *b == b'$' || *b == b'\n')
+                    .map(|p| p + self.scanned.max(1));
+                match end {
+                    Some(end) if self.buffer[end] == b'$' => self.drop_garbage(end, true),
+                    Some(end) => {
+                        let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                        self.scanned = 0;
+                        self.stats.lines += 1;
+                        return Some(String::from_utf8(line).map_err(|e| {
+                            self.stats.invalid_lines += 1;
+                            e.into_bytes()
+                        }));
+                    }
+                    None if self.buffer.len() > MAX_LINE_LEN => {
+                        let len = self.buffer.len();
+                        self.drop_garbage(len, true);
+                    }
+                    None => {
+                        self.scanned = self.buffer.len();
+                        return None;
+                    }
+                }
+            }
+        }
+
+        fn drop_garbage(&mut self, end: usize, line: bool) {
+            self.stats.garbage_bytes += end as u64;
+            if line {
+                self.stats.dropped_lines += 1;
+            }
+            self.garbage.extend(self.buffer.drain(..end));
+            if self.garbage.len() > MAX_LINE_LEN {
+                let extra = self.garbage.len() - MAX_LINE_LEN;
+                self.garbage.drain(..extra);
+            }
+            self.scanned = 0;
+        }
+
+        /// The garbage bytes dropped since the last call, if any, up to the last MAX_LINE_LEN.
+        pub fn take_garbage(&mut self) -> Option<Vec<u8>> {
+            if self.garbage.is_empty() {
+                None
+            } else {
+                Some(std::mem::take(&mut self.garbage))
+            }
+        }
+
+        /// When bytes were last pushed.
+        pub fn last_input(&self) -> Option<Instant> {
+            self.last_input.map(|(instant, _)| instant)
+        }
+
+        /// When bytes were last pushed, by the wall clock.
+        pub fn last_input_time(&self) -> Option<SystemTime> {
+            self.last_input.map(|(_, time)| time)
+        }
+
+        /// Bytes waiting for the rest of their line.
+        pub fn leftover(&self) -> &[u8] {
+            &self.buffer
+        }
+
+        /// Drops the bytes waiting, such as after changing the baud rate.
+        pub fn clear(&mut self) {
+            self.buffer.clear();
+            self.scanned = 0;
+        }
+
+        pub fn stats(&self) -> FramerStats {
+            self.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod line_framer_test {
+    use std::time::{Duration, Instant};
+
+    use crate::open_gps::gps::{GpsSentence, PortConnection};
+    use crate::open_gps::mock_port::{mock_gps, GGA};
+
+    use super::line_framer::{FramerStats, LineFramer, MAX_LINE_LEN};
+
+    const VTG: &str = "$GNVTG,0.00,T,,M,0.00,N,0.00,K,A*23\r\n";
+
+    #[test]
+    fn keeps_leftover_between_pushes() {
+        let mut framer = LineFramer::new();
+        let (first, rest) = GGA.as_bytes().split_at(30);
+        framer.push(first);
+        assert_eq!(framer.next_line(), None);
+        assert_eq!(framer.leftover(), first);
+        framer.push(rest);
+        framer.push(&VTG.as_bytes()[..5]);
+        assert_eq!(framer.next_line(), Some(Ok(GGA.to_string())));
+        assert_eq!(framer.next_line(), None);
+        assert_eq!(framer.leftover(), &VTG.as_bytes()[..5]);
+    }
+
+    #[test]
+    fn resyncs_on_dollar() {
+        let mut framer = LineFramer::new();
+        framer.push(b"31613.000,5132.7314,N*60\r\n");
+        framer.push(b"$GNGGA,1316");
+        framer.push(GGA.as_bytes());
+        framer.push(b"\xff\xfe");
+        framer.push(VTG.as_bytes());
+        assert_eq!(framer.next_line(), Some(Ok(GGA.to_string())));
+        assert_eq!(framer.next_line(), Some(Ok(VTG.to_string())));
+        assert_eq!(framer.next_line(), None);
+        assert_eq!(framer.stats(), FramerStats {
+            bytes: (26 + 11 + GGA.len() + 2 + VTG.len()) as u64,
+            lines: 2,
+            invalid_lines: 0,
+            garbage_bytes: 26 + 11 + 2,
+            dropped_lines: 1,
+        });
+        assert_eq!(framer.take_garbage().unwrap().len(), 39);
+        assert_eq!(framer.take_garbage(), None);
+    }
+
+    #[test]
+    fn invalid_and_overlong_lines() {
+        let mut framer = LineFramer::new();
+        framer.push(b"$GP\xff\xfeGA*00\r\n");
+        framer.push(GGA.as_bytes());
+        assert_eq!(framer.next_line(), Some(Err(b"$GP\xff\xfeGA*00\r\n".to_vec())));
+        assert_eq!(framer.next_line(), Some(Ok(GGA.to_string())));
+
+        framer.push(b"$");
+        framer.push(&[b'A'; MAX_LINE_LEN]);
+        assert_eq!(framer.next_line(), None);
+        assert!(framer.leftover().is_empty());
+        framer.push(VTG.as_bytes());
+        assert_eq!(framer.next_line(), Some(Ok(VTG.to_string())));
+        let stats = framer.stats();
+        assert_eq!((stats.lines, stats.invalid_lines, stats.dropped_lines), (3, 1, 1));
+    }
+
+    const NOISE: &str = "\u{0}\u{0}13.000,5132.7314,N,00005.9099,W,1,9*60\r\n";
+
+    #[test]
+    fn gps_reads_lines_in_bulk() {
+        let (mut gps, _written) = mock_gps(&[NOISE, GGA, VTG]);
+        assert!(matches!(gps.update(), GpsSentence::GGA(_)));
+        assert!(matches!(gps.update(), GpsSentence::VTG(_)));
+        assert_eq!(gps.framer.stats().garbage_bytes, NOISE.len() as u64);
+        assert_eq!(gps.framer.stats().bytes, (NOISE.len() + GGA.len() + VTG.len()) as u64);
+    }
+
+    #[test]
+    fn gps_gives_garbage_after_a_second_without_a_line() {
+        let (mut gps, _written) = mock_gps(&[NOISE]);
+        let start = Instant::now();
+        assert_eq!(gps.read_line(), PortConnection::InvalidBytes(NOISE.as_bytes().to_vec()));
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(gps.read_line(), PortConnection::NoConnection);
+    }
+}
@@ -0,0 +1,1174 @@
+//! # gpsd
+//! Speaks the gpsd JSON protocol, so gpsd clients such as cgps, foxtrotgps and the python gps
+//! module can use a gps this crate has open.
+//!
+//! - gpsd_json: The VERSION, DEVICES, WATCH, TPV and SKY reports, and FixState, which builds TPV
+//!   and SKY from the sentences.
+//! - gpsd_server: GpsdServer, which reads a Gps on a thread and gives the reports to every client
+//!   that has sent ?WATCH. Clients that ask for "nmea":true also get the sentences as read. Each
+//!   client has its own writer thread, and one that falls CLIENT_QUEUE lines behind, or sends a
+//!   command over 4 KiB, is dropped.
+//! - gpsd_client: GpsdPort, the other way round: reads a gps that gpsd already has open, through
+//!   the normal Gps API. Gps::new_gpsd() connects one.
+//!
+//! The server only listens where it is told to, use 127.0.0.1 to keep it to the host.
+//! ```ignore
+//! let server = GpsdServer::start(Gps::new("/dev/serial0", "9600")?, ("127.0.0.1", GPSD_PORT))?;
+//! // cgps now works.
+//! let gps = server.stop();
+//! ```
+
+pub mod gpsd_json {
+    //! gpsd JSON reports. See https://gpsd.gitlab.io/gpsd/gpsd_json.html
+    //!
+    //! Only the fields this crate can fill are given. None fields are left out.
+    use serde::{Deserialize, Serialize};
+
+    use crate::nmea::gga::SatFix;
+    use crate::nmea::gsa::DimensionFix;
+    use crate::open_gps::gps::GpsSentence;
+    use crate::timing::utc::{split_utc, UtcDateTime};
+
+    /// Version of the gpsd protocol spoken.
+    pub const PROTO_MAJOR: u32 = 3;
+    pub const PROTO_MINOR: u32 = 14;
+
+    pub(super) const KNOTS_TO_MPS: f64 = 1852.0 / 3600.0;
+
+    /// Sent to each client when it connects.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct Version {
+        pub class: String,
+        pub release: String,
+        pub rev: String,
+        pub proto_major: u32,
+        pub proto_minor: u32,
+    }
+
+    impl Default for Version {
+        fn default() -> Version {
+            Version {
+                class: "VERSION".to_string(),
+                release: env!("CARGO_PKG_VERSION").to_string(),
+                rev: concat!("adafruit_gps ", env!("CARGO_PKG_VERSION")).to_string(),
+                proto_major: PROTO_MAJOR,
+                proto_minor: PROTO_MINOR,
+            }
+        }
+    }
+
+    /// One gps.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct Device {
+        pub class: String,
+        pub path: String,
+        pub driver: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub bps: Option<u32>,
+    }
+
+    impl Device {
+        pub fn new(path: &str, bps: Option<u32>) -> Device {
+            Device { class: "DEVICE".to_string(), path: path.to_string(), driver: "NMEA0183".to_string(), bps }
+        }
+    }
+
+    /// The gpses the server has: always just the one.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct Devices {
+        pub class: String,
+        pub devices: Vec<Device>,
+    }
+
+    impl Devices {
+        pub fn new(devices: Vec<Device>) -> Devices {
+            Devices { class: "DEVICES".to_string(), devices }
+        }
+    }
+
+    /// What a client wants to be sent. ?WATCH={...} changes only the fields it gives.
+    /// - enable: Send anything at all.
+    /// - json: Send TPV and SKY reports.
+    /// - nmea: Send the sentences as read from the gps.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct Watch {
+        pub class: String,
+        pub enable: bool,
+        pub json: bool,
+        pub nmea: bool,
+    }
+
+    impl Default for Watch {
+        fn default() -> Watch {
+            Watch { class: "WATCH".to_string(), enable: false, json: false, nmea: false }
+        }
+    }
+
+    impl Watch {
+        /// Applies the fields given in the JSON of a ?WATCH= command. A bare ?WATCH changes nothing.
+        ///
+        /// As in gpsd, enabling without saying json or nmea turns json on.
+        pub fn apply(&mut self, args: &str) -> Result<(), serde_json::Error> {
+            if args.trim().is_empty() {
+                return Ok(());
+            }
+            let args: serde_json::Map<String, serde_json::Value> = serde_json::from_str(args)?;
+            if let Some(enable) = args.get("enable").and_then(|v| v.as_bool()) {
+                self.enable = enable;
+            }
+            if let Some(json) = args.get("json").and_then(|v| v.as_bool()) {
+                self.json = json;
+            }
+            if let Some(nmea) = args.get("nmea").and_then(|v| v.as_bool()) {
+                self.nmea = nmea;
+            }
+            if self.enable && !args.contains_key("json") && !args.contains_key("nmea") && !self.nmea {
+                self.json = true;
+            }
+            Ok(())
+        }
+    }
+
+    /// Time, position and velocity.
+    /// - mode: 0 unknown, 1 no fix, 2 2D fix, 3 3D fix.
+    /// - status: 2 for a DGPS fix.
+    /// - alt: Altitude above mean sea level in metres, as is alt_msl.
+    /// - alt_hae: Altitude above the WGS84 ellipsoid in metres.
+    /// - speed: Metres per second.
+    /// - track: Degrees from true north.
+    #[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct Tpv {
+        pub class: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub device: Option<String>,
+        pub mode: u8,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub status: Option<u8>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub time: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub lat: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub lon: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub alt: Option<f64>,
+        #[serde(rename = "altMSL", skip_serializing_if = "Option::is_none")]
+        pub alt_msl: Option<f64>,
+        #[serde(rename = "altHAE", skip_serializing_if = "Option::is_none")]
+        pub alt_hae: Option<f64>,
+        #[serde(rename = "geoidSep", skip_serializing_if = "Option::is_none")]
+        pub geoid_sep: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub speed: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub track: Option<f64>,
+    }
+
+    /// One satellite in a SKY report.
+    #[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct SkySatellite {
+        #[serde(rename = "PRN")]
+        pub prn: i32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub el: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub az: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ss: Option<f64>,
+        pub used: bool,
+    }
+
+    /// Satellites in view and the dilutions of precision.
+    #[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct Sky {
+        pub class: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub device: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub hdop: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub vdop: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub pdop: Option<f64>,
+        pub satellites: Vec<SkySatellite>,
+    }
+
+    /// A report to send.
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum Report {
+        Tpv(Tpv),
+        Sky(Sky),
+    }
+
+    impl Report {
+        /// The report as one line of JSON, with the \r\n.
+        pub fn to_json_line(&self) -> String {
+            let json = match self {
+                Report::Tpv(tpv) => serde_json::to_string(tpv),
+                Report::Sky(sky) => serde_json::to_string(sky),
+            };
+            format!("{}\r\n", json.unwrap_or_default())
+        }
+    }
+
+    /// Everything known about the fix so far, built up from the sentences as they are read.
+    ///
+    /// A TPV is given for each GGA and RMC, and a SKY for each GSV. The GSA and GSV sentences of
+    /// each satellite system in a row are put together.
+    #[derive(Debug, PartialEq, Clone, Default)]
+    pub struct FixState {
+        pub device: Option<String>,
+        pub tpv: Tpv,
+        pub sky: Sky,
+        date: Option<(u16, u8, u8)>,
+        gsa_mode: Option<u8>,
+        used: Vec<i32>,
+        last_was_gsa: bool,
+        last_was_gsv: bool,
+    }
+
+    impl FixState {
+        pub fn new(device: Option<String>) -> FixState {
+            FixState {
+                device: device.clone(),
+                tpv: Tpv { class: "TPV".to_string(), device: device.clone(), ..Tpv::default() },
+                sky: Sky { class: "SKY".to_string(), device, ..Sky::default() },
+                ..FixState::default()
+            }
+        }
+
+        fn set_time(&mut self, utc: f64) {
+            if let Some((year, month, day)) = self.date {
+                let (hour, minute, second) = split_utc(utc);
+                self.tpv.time = Some(UtcDateTime { year, month, day, hour, minute, second }.to_iso8601());
+            }
+        }
+
+        fn fix_mode(&self, fix: bool) -> u8 {
+            match (fix, self.gsa_mode) {
+                (false, _) => 1,
+                (true, Some(mode)) if mode >= 2 => mode,
+                (true, _) => if self.tpv.alt.is_some() { 3 } else { 2 },
+            }
+        }
+
+        /// Takes in the next sentence, giving the report it completes, if any.
+        pub fn update(&mut self, sentence: &GpsSentence) -> Option<Report> {
+            let was_gsa = std::mem::replace(&mut self.last_was_gsa, matches!(sentence, GpsSentence::GSA(_)));
+            let was_gsv = std::mem::replace(&mut self.last_was_gsv, matches!(sentence, GpsSentence::GSV(_)));
+            match sentence {
+                GpsSentence::GGA(gga) => {
+                    let fix = gga.sat_fix != SatFix::NoFix;
+                    self.tpv.lat = gga.lat.map(f64::from);
+                    self.tpv.lon = gga.long.map(f64::from);
+                    self.tpv.alt = gga.msl_alt.map(f64::from);
+                    self.tpv.alt_msl = self.tpv.alt;
+                    self.tpv.geoid_sep = gga.geoidal_sep.map(f64::from);
+                    self.tpv.alt_hae = self.tpv.alt.zip(self.tpv.geoid_sep).map(|(alt, sep)| alt + sep);
+                    self.tpv.status = if gga.sat_fix == SatFix::DgpsFix { Some(2) } else { None };
+                    self.tpv.mode = self.fix_mode(fix);
+                    self.set_time(gga.utc);
+                    Some(Report::Tpv(self.tpv.clone()))
+                }
+                GpsSentence::RMC(rmc) => {
+                    if let Some(date) = UtcDateTime::from_rmc(rmc) {
+                        self.date = Some((date.year, date.month, date.day));
+                    }
+                    self.tpv.lat = rmc.latitude.map(f64::from);
+                    self.tpv.lon = rmc.longitude.map(f64::from);
+                    self.tpv.speed = rmc.speed.map(|knots| knots as f64 * KNOTS_TO_MPS);
+                    self.tpv.track = rmc.course.map(f64::from);
+                    self.tpv.mode = self.fix_mode(rmc.fix_status);
+                    self.set_time(rmc.utc);
+                    Some(Report::Tpv(self.tpv.clone()))
+                }
+                GpsSentence::ZDA(zda) => {
+                    if let Some(date) = UtcDateTime::from_zda(zda) {
+                        self.date = Some((date.year, date.month, date.day));
+                    }
+                    None
+                }
+                GpsSentence::VTG(vtg) => {
+                    self.tpv.speed = vtg.speed_kph.map(|kph| kph as f64 / 3.6).or(self.tpv.speed);
+                    self.tpv.track = vtg.true_course.map(f64::from).or(self.tpv.track);
+                    None
+                }
+                GpsSentence::GSA(gsa) => {
+                    if !was_gsa {
+                        self.used.clear();
+                    }
+                    self.used.extend(gsa.used_satellites());
+                    self.gsa_mode = Some(match gsa.dimension_fix {
+                        DimensionFix::NotAvailable => 1,
+                        DimensionFix::Dimension2d => 2,
+                        DimensionFix::Dimension3d => 3,
+                    });
+                    self.sky.pdop = gsa.pdop.map(f64::from);
+                    self.sky.hdop = gsa.hdop.map(f64::from);
+                    self.sky.vdop = gsa.vdop.map(f64::from);
+                    None
+                }
+                GpsSentence::GSV(satellites) => {
+                    if !was_gsv {
+                        self.sky.satellites.clear();
+                    }
+                    let used = &self.used;
+                    self.sky.satellites.extend(satellites.iter().filter_map(|sat| {
+                        let prn = sat.id?;
+                        Some(SkySatellite {
+                            prn,
+                            el: sat.elevation.map(f64::from),
+                            az: sat.azimuth.map(f64::from),
+                            ss: sat.snr.map(f64::from),
+                            used: used.contains(&prn),
+                        })
+                    }));
+                    Some(Report::Sky(self.sky.clone()))
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+pub mod gpsd_server {
+    //! GpsdServer: the threads, clients and commands.
+    use std::io::{self, ErrorKind, Read, Write};
+    use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, sleep, JoinHandle};
+    use std::time::Duration;
+
+    use super::gpsd_json::{Device, Devices, FixState, Version, Watch};
+    use crate::open_gps::gps::Gps;
+    use crate::trace::wire_trace::{Direction, TraceEvent, TraceSink};
+
+    /// The port gpsd listens on.
+    pub const GPSD_PORT: u16 = 2947;
+
+    /// How often the threads check if the server has been stopped.
+    const POLL: Duration = Duration::from_millis(50);
+
+    /// Lines kept for a client that isn't reading them fast enough. A client with this many
+    /// waiting is disconnected, rather than holding up the gps and the other clients.
+    pub const CLIENT_QUEUE: usize = 256;
+
+    /// How long a write to a client can block before it is disconnected.
+    const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Longest command a client can send. A client that sends more without a ; or new line is
+    /// disconnected.
+    const MAX_COMMAND: usize = 4096;
+
+    /// The lines waiting to be written to a client by its writer thread. Everything sent to a
+    /// client goes through here, so lines are never mixed up.
+    #[derive(Clone)]
+    struct Outbox {
+        lines: SyncSender<String>,
+        socket: Arc<TcpStream>,
+    }
+
+    impl Outbox {
+        /// Starts the writer thread for the client on stream.
+        fn start(stream: &TcpStream) -> io::Result<Outbox> {
+            let writer = stream.try_clone()?;
+            writer.set_write_timeout(Some(WRITE_TIMEOUT))?;
+            let (lines, queued) = sync_channel(CLIENT_QUEUE);
+            thread::spawn(move || write_client(writer, queued));
+            Ok(Outbox { lines, socket: Arc::new(stream.try_clone()?) })
+        }
+
+        /// Queues line, with \r\n added. False, and the connection is closed, if the client has
+        /// CLIENT_QUEUE lines waiting or the writer has stopped.
+        fn send(&self, line: &str) -> bool {
+            let queued = self.lines.try_send(format!("{}\r\n", line.trim_end())).is_ok();
+            if !queued {
+                self.close();
+            }
+            queued
+        }
+
+        fn close(&self) {
+            let _ = self.socket.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// Writes each queued line to the client, until the queue is dropped or a write fails.
+    fn write_client(mut writer: TcpStream, queued: Receiver<String>) {
+        for line in queued {
+            if writer.write_all(line.as_bytes()).is_err() {
+                let _ = writer.shutdown(Shutdown::Both);
+                break;
+            }
+        }
+    }
+
+    struct Client {
+        id: u64,
+        outbox: Outbox,
+        watch: Arc<Mutex<Watch>>,
+    }
+
+    type Clients = Arc<Mutex<Vec<Client>>>;
+
+    /// Gives the received lines to the server, and to the trace sink the gps already had.
+    struct PassthroughSink {
+        raw: Sender<String>,
+        inner: Arc<Mutex<Option<Box<dyn TraceSink>>>>,
+    }
+
+    impl TraceSink for PassthroughSink {
+        fn trace(&mut self, event: &TraceEvent) {
+            if event.direction == Direction::Received {
+                let _ = self.raw.send(event.line.trim_end().to_string());
+            }
+            if let Some(inner) = self.inner.lock().unwrap().as_mut() {
+                inner.trace(event);
+            }
+        }
+    }
+
+    /// A gpsd compatible server. See the [module docs](index.html).
+    pub struct GpsdServer {
+        addr: SocketAddr,
+        running: Arc<AtomicBool>,
+        clients: Clients,
+        reader: Option<JoinHandle<Gps>>,
+        acceptor: Option<JoinHandle<()>>,
+        inner_trace: Arc<Mutex<Option<Box<dyn TraceSink>>>>,
+    }
+
+    impl GpsdServer {
+        /// Listens on addr and starts reading the gps on a new thread.
+        pub fn start(mut gps: Gps, addr: impl ToSocketAddrs) -> io::Result<GpsdServer> {
+            let listener = TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+            let addr = listener.local_addr()?;
+            let path = gps.port.name().unwrap_or_else(|| "gps".to_string());
+            let devices = Devices::new(vec![Device::new(path.as_str(), gps.port.baud_rate().ok())]);
+
+            let (raw, raw_rx) = channel();
+            let inner_trace = Arc::new(Mutex::new(gps.take_trace()));
+            gps.set_trace(PassthroughSink { raw, inner: inner_trace.clone() });
+
+            let running = Arc::new(AtomicBool::new(true));
+            let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+            let reader = {
+                let (running, clients) = (running.clone(), clients.clone());
+                thread::spawn(move || read_gps(gps, raw_rx, FixState::new(Some(path)), running, clients))
+            };
+            let acceptor = {
+                let (running, clients) = (running.clone(), clients.clone());
+                thread::spawn(move || accept(listener, devices, running, clients))
+            };
+            Ok(GpsdServer { addr, running, clients, reader: Some(reader), acceptor: Some(acceptor), inner_trace })
+        }
+
+        /// Where the server is listening. Useful after binding to port 0.
+        pub fn local_addr(&self) -> SocketAddr {
+            self.addr
+        }
+
+        /// Number of clients connected.
+        pub fn clients(&self) -> usize {
+            self.clients.lock().unwrap().len()
+        }
+
+        /// Disconnects every client and gives back the gps, with the trace sink it had. None if
+        /// the reader thread panicked.
+        pub fn stop(mut self) -> Option<Gps> {
+            self.stop_threads()
+        }
+
+        fn stop_threads(&mut self) -> Option<Gps> {
+            self.running.store(false, Ordering::SeqCst);
+            if let Some(acceptor) = self.acceptor.take() {
+                let _ = acceptor.join();
+            }
+            for client in self.clients.lock().unwrap().drain(..) {
+                client.outbox.close();
+            }
+            let mut gps = self.reader.take()?.join().ok()?;
+            gps.trace = self.inner_trace.lock().unwrap().take();
+            Some(gps)
+        }
+    }
+
+    impl Drop for GpsdServer {
+        fn drop(&mut self) {
+            self.stop_threads();
+        }
+    }
+
+    fn to_json<T: serde::Serialize>(report: &T) -> String {
+        serde_json::to_string(report).unwrap_or_default()
+    }
+
+    /// Reads the gps and queues what was read for each watching client, dropping clients that have
+    /// gone away or fallen CLIENT_QUEUE lines behind. Nothing here waits on a client.
+    fn read_gps(mut gps: Gps, raw: Receiver<String>, mut state: FixState, running: Arc<AtomicBool>, clients: Clients) -> Gps {
+        while running.load(Ordering::SeqCst) {
+            let sentence = gps.update();
+            let lines: Vec<String> = raw.try_iter().collect();
+            let report = state.update(&sentence).map(|report| report.to_json_line());
+            clients.lock().unwrap().retain(|client| {
+                let watch = client.watch.lock().unwrap().clone();
+                if !watch.enable {
+                    return true;
+                }
+                let mut sent = true;
+                if watch.nmea {
+                    sent = lines.iter().all(|line| client.outbox.send(line));
+                }
+                if watch.json {
+                    if let Some(report) = report.as_ref() {
+                        sent = sent && client.outbox.send(report);
+                    }
+                }
+                sent
+            });
+        }
+        gps
+    }
+
+    fn accept(listener: TcpListener, devices: Devices, running: Arc<AtomicBool>, clients: Clients) {
+        let next_id = AtomicU64::new(0);
+        while running.load(Ordering::SeqCst) {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    sleep(POLL);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            let id = next_id.fetch_add(1, Ordering::SeqCst);
+            let watch = Arc::new(Mutex::new(Watch::default()));
+            let setup = stream.set_nonblocking(false)
+                .and_then(|_| stream.set_read_timeout(Some(POLL)))
+                .and_then(|_| Outbox::start(&stream));
+            let outbox = match setup {
+                Ok(outbox) => outbox,
+                Err(_) => continue,
+            };
+            if !outbox.send(to_json(&Version::default()).as_str()) {
+                continue;
+            }
+            clients.lock().unwrap().push(Client { id, outbox: outbox.clone(), watch: watch.clone() });
+            let (devices, running, clients) = (devices.clone(), running.clone(), clients.clone());
+            thread::spawn(move || serve_client(id, stream, outbox, watch, devices, running, clients));
+        }
+    }
+
+    /// Reads a client's commands until it disconnects. Commands end with ; or a new line.
+    fn serve_client(id: u64, mut stream: TcpStream, outbox: Outbox, watch: Arc<Mutex<Watch>>, devices: Devices, running: Arc<AtomicBool>, clients: Clients) {
+        let mut pending = String::new();
+        let mut buf = [0u8; 512];
+        while running.load(Ordering::SeqCst) {
+            match stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => pending.push_str(String::from_utf8_lossy(&buf[..read]).as_ref()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => continue,
+                Err(_) => break,
+            }
+            while let Some(end) = pending.find([';', '\n']) {
+                let command: String = pending.drain(..=end).collect();
+                let reply = command_reply(command.trim_end_matches([';', '\n']).trim(), &watch, &devices);
+                for line in reply {
+                    if !outbox.send(line.as_str()) {
+                        break;
+                    }
+                }
+            }
+            if pending.len() > MAX_COMMAND {
+                break;
+            }
+        }
+        clients.lock().unwrap().retain(|client| client.id != id);
+        outbox.close();
+    }
+
+    /// The lines to send back for a command.
+    fn command_reply(command: &str, watch: &Mutex<Watch>, devices: &Devices) -> Vec<String> {
+        if command.is_empty() {
+            return Vec::new();
+        }
+        let (name, args) = match command.find('=') {
+            Some(equals) => (&command[..equals], &command[equals + 1..]),
+            None => (command, ""),
+        };
+        match name {
+            "?VERSION" => vec![to_json(&Version::default())],
+            "?DEVICES" => vec![to_json(devices)],
+            "?WATCH" => {
+                let mut watch = watch.lock().unwrap();
+                match watch.apply(args) {
+                    Ok(()) => vec![to_json(devices), to_json(&*watch)],
+                    Err(e) => vec![error(format!("Invalid WATCH: {}", e).as_str())],
+                }
+            }
+            _ => vec![error(format!("Unrecognized request '{}'", name.trim_start_matches('?')).as_str())],
+        }
+    }
+
+    fn error(message: &str) -> String {
+        serde_json::json!({"class": "ERROR", "message": message}).to_string()
+    }
+}
+
+pub mod gpsd_client {
+    //! GpsdPort: reads a gps through gpsd rather than from the device.
+    use std::collections::VecDeque;
+    use std::io::{self, Read, Write};
+    use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+    use std::time::Duration;
+
+    use serialport::prelude::*;
+    use serialport::{ClearBuffer, Error, ErrorKind};
+
+    use super::gpsd_json::{Sky, Tpv, KNOTS_TO_MPS};
+    use crate::open_gps::gps::Gps;
+    use crate::pmtk::send_pmtk::add_checksum;
+
+    /// What to ask gpsd for.
+    /// - Nmea: The sentences gpsd reads, given as they are. gpsd makes up NMEA for gpses that
+    ///   don't give it. Default.
+    /// - Json: TPV and SKY reports, turned back into GGA, RMC, GSA and GSV sentences. Only what
+    ///   gpsd reports is kept, so the sentences have fewer fields filled than the gps gave.
+    #[derive(Debug, PartialEq, Clone, Copy, Default)]
+    pub enum GpsdFormat {
+        #[default]
+        Nmea,
+        Json,
+    }
+
+    impl GpsdFormat {
+        /// The ?WATCH command asking for the format.
+        pub fn watch_command(&self) -> &'static str {
+            match self {
+                GpsdFormat::Nmea => "?WATCH={\"enable\":true,\"nmea\":true};\n",
+                GpsdFormat::Json => "?WATCH={\"enable\":true,\"json\":true};\n",
+            }
+        }
+    }
+
+    /// Reads a gps through a gpsd socket. Implements SerialPort so it can be given to
+    /// Gps::from_port.
+    ///
+    /// gpsd owns the gps, so commands can't be sent: writes give an Unsupported error. gpsd
+    /// closing the connection gives a ConnectionAborted error, which sets gps.port_error.
+    pub struct GpsdPort {
+        stream: TcpStream,
+        addr: SocketAddr,
+        pub format: GpsdFormat,
+        /// Bytes of the line being read.
+        line: Vec<u8>,
+        input: VecDeque<u8>,
+        timeout: Duration,
+        /// The last reports, to fill the sentences made from the next.
+        tpv: Tpv,
+        sky: Sky,
+    }
+
+    impl GpsdPort {
+        /// Connects to gpsd at addr, such as ("127.0.0.1", GPSD_PORT), and asks for format.
+        pub fn connect(addr: impl ToSocketAddrs, format: GpsdFormat) -> io::Result<GpsdPort> {
+            let mut stream = TcpStream::connect(addr)?;
+            let timeout = Duration::from_millis(100);
+            stream.set_read_timeout(Some(timeout))?;
+            stream.write_all(format.watch_command().as_bytes())?;
+            Ok(GpsdPort {
+                addr: stream.peer_addr()?,
+                stream,
+                format,
+                line: Vec::new(),
+                input: VecDeque::new(),
+                timeout,
+                tpv: Tpv::default(),
+                sky: Sky::default(),
+            })
+        }
+
+        /// Takes in bytes read from gpsd, keeping the sentences and dropping the other reports.
+        fn take(&mut self, bytes: &[u8]) {
+            for byte in bytes {
+                self.line.push(*byte);
+                if *byte == b'\n' {
+                    let line = std::mem::take(&mut self.line);
+                    self.take_line(String::from_utf8_lossy(&line).as_ref());
+                }
+            }
+        }
+
+        fn take_line(&mut self, line: &str) {
+            match (self.format, line.as_bytes().first()) {
+                (GpsdFormat::Nmea, Some(b'$')) => self.input.extend(line.as_bytes()),
+                (GpsdFormat::Json, Some(b'{')) => {
+                    for sentence in self.sentences_from_json(line) {
+                        self.input.extend(sentence.as_bytes());
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        /// The sentences a TPV or SKY report stands for.
+        fn sentences_from_json(&mut self, json: &str) -> Vec<String> {
+            let report: serde_json::Value = match serde_json::from_str(json) {
+                Ok(report) => report,
+                Err(_) => return Vec::new(),
+            };
+            match report.get("class").and_then(|class| class.as_str()) {
+                Some("TPV") => match serde_json::from_value(report) {
+                    Ok(tpv) => {
+                        self.tpv = tpv;
+                        rmc_and_gga(&self.tpv, &self.sky)
+                    }
+                    Err(_) => Vec::new(),
+                },
+                Some("SKY") => match serde_json::from_value(report) {
+                    Ok(sky) => {
+                        self.sky = sky;
+                        gsa_and_gsv(&self.tpv, &self.sky)
+                    }
+                    Err(_) => Vec::new(),
+                },
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    /// An optional number as a sentence field, empty if None.
+    fn field(value: Option<f64>, decimals: usize) -> String {
+        value.map(|value| format!("{:.*}", decimals, value)).unwrap_or_default()
+    }
+
+    /// Degrees as a ddmm.mmmm (or dddmm.mmmm for longitude) field and its direction.
+    fn degrees_field(degrees: Option<f64>, width: usize, positive: char, negative: char) -> (String, String) {
+        match degrees {
+            Some(degrees) => {
+                let direction = if degrees < 0.0 { negative } else { positive };
+                let minutes = (degrees.abs() * 600_000.0).round() as u64;
+                let field = format!("{:0width$}{:02}.{:04}", minutes / 600_000, minutes % 600_000 / 10_000,
+                                    minutes % 10_000, width = width);
+                (field, direction.to_string())
+            }
+            None => (String::new(), String::new()),
+        }
+    }
+
+    /// The ddmmyy date and hhmmss.sss time fields of an ISO 8601 time, such as
+    /// 2020-05-20T11:09:42.000Z.
+    fn date_time_fields(time: &str) -> Option<(String, String)> {
+        let (date, time) = time.trim_end_matches('Z').split_once('T')?;
+        let mut date = date.split('-');
+        let (year, month, day) = (date.next()?, date.next()?, date.next()?);
+        let mut time = time.split(':');
+        let (hour, minute) = (time.next()?, time.next()?);
+        let second: f64 = time.next()?.parse().ok()?;
+        Some((format!("{}{}{}", day, month, year.get(2..)?), format!("{}{}{:06.3}", hour, minute, second)))
+    }
+
+    /// RMC and GGA for a TPV. None without a time, as GGA must have one.
+    fn rmc_and_gga(tpv: &Tpv, sky: &Sky) -> Vec<String> {
+        let (date, utc) = match tpv.time.as_deref().and_then(date_time_fields) {
+            Some(fields) => fields,
+            None => return Vec::new(),
+        };
+        let (lat, ns) = degrees_field(tpv.lat, 2, 'N', 'S');
+        let (lon, ew) = degrees_field(tpv.lon, 3, 'E', 'W');
+        let fix = tpv.mode >= 2;
+        let dgps = tpv.status == Some(2);
+        let rmc = format!("GPRMC,{},{},{},{},{},{},{},{},{},,,{}", utc, if fix { 'A' } else { 'V' }, lat, ns, lon, ew,
+                          field(tpv.speed.map(|speed| speed / KNOTS_TO_MPS), 2), field(tpv.track, 2), date,
+                          if !fix { 'N' } else if dgps { 'D' } else { 'A' });
+        let quality = if !fix { 0 } else if dgps { 2 } else { 1 };
+        let used = sky.satellites.iter().filter(|sat| sat.used).count();
+        let gga = format!("GPGGA,{},{},{},{},{},{},{},{},{},M,{},M,,", utc, lat, ns, lon, ew, quality, used,
+                          field(sky.hdop, 2), field(tpv.alt_msl.or(tpv.alt), 1), field(tpv.geoid_sep, 1));
+        vec![add_checksum(rmc), add_checksum(gga)]
+    }
+
+    /// GSA and the GSV messages for a SKY. Just the GSA if it has no satellites.
+    fn gsa_and_gsv(tpv: &Tpv, sky: &Sky) -> Vec<String> {
+        let mut used: Vec<String> = sky.satellites.iter().filter(|sat| sat.used).map(|sat| sat.prn.to_string()).take(12).collect();
+        used.resize(12, String::new());
+        let mut sentences = vec![add_checksum(format!("GPGSA,A,{},{},{},{},{}", tpv.mode.max(1), used.join(","),
+                                                      field(sky.pdop, 2), field(sky.hdop, 2), field(sky.vdop, 2)))];
+        let messages = sky.satellites.chunks(4).count();
+        for (number, satellites) in sky.satellites.chunks(4).enumerate() {
+            let mut gsv = format!("GPGSV,{},{},{:02}", messages, number + 1, sky.satellites.len());
+            for sat in satellites {
+                gsv.push_str(format!(",{:02},{},{},{}", sat.prn, field(sat.el, 0), field(sat.az, 0), field(sat.ss, 0)).as_str());
+            }
+            sentences.push(add_checksum(gsv));
+        }
+        sentences
+    }
+
+    impl Read for GpsdPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut chunk = [0u8; 1024];
+            while self.input.is_empty() {
+                match self.stream.read(&mut chunk)? {
+                    0 => return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "gpsd closed the connection")),
+                    read => self.take(&chunk[..read]),
+                }
+            }
+            let read = buf.len().min(self.input.len());
+            for (byte, input) in buf.iter_mut().zip(self.input.drain(..read)) {
+                *byte = input;
+            }
+            Ok(read)
+        }
+    }
+
+    impl Write for GpsdPort {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "gpsd owns the gps, commands can't be sent"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// gpsd has the serial settings, so these do nothing.
+    impl SerialPort for GpsdPort {
+        fn name(&self) -> Option<String> { Some(format!("gpsd {}", self.addr)) }
+        fn settings(&self) -> SerialPortSettings { SerialPortSettings::default() }
+        fn baud_rate(&self) -> serialport::Result<u32> { Ok(9600) }
+        fn data_bits(&self) -> serialport::Result<DataBits> { Ok(DataBits::Eight) }
+        fn flow_control(&self) -> serialport::Result<FlowControl> { Ok(FlowControl::None) }
+        fn parity(&self) -> serialport::Result<Parity> { Ok(Parity::None) }
+        fn stop_bits(&self) -> serialport::Result<StopBits> { Ok(StopBits::One) }
+        fn timeout(&self) -> Duration { self.timeout }
+        fn set_all(&mut self, _: &SerialPortSettings) -> serialport::Result<()> { Ok(()) }
+        fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> { Ok(()) }
+        fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> { Ok(()) }
+        fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> { Ok(()) }
+        fn set_parity(&mut self, _: Parity) -> serialport::Result<()> { Ok(()) }
+        fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> { Ok(()) }
+        fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+            self.stream.set_read_timeout(Some(timeout)).map_err(Error::from)?;
+            self.timeout = timeout;
+            Ok(())
+        }
+        fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> { Ok(()) }
+        fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> { Ok(()) }
+        fn read_clear_to_send(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_data_set_ready(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_ring_indicator(&mut self) -> serialport::Result<bool> { Ok(false) }
+        fn read_carrier_detect(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn bytes_to_read(&self) -> serialport::Result<u32> { Ok(self.input.len() as u32) }
+        fn bytes_to_write(&self) -> serialport::Result<u32> { Ok(0) }
+        fn clear(&self, _: ClearBuffer) -> serialport::Result<()> { Ok(()) }
+        fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+            Err(Error::new(ErrorKind::Unknown, "gpsd port can't be cloned"))
+        }
+    }
+
+    impl Gps {
+        /// Gps reading the sentences gpsd at addr reads, such as ("127.0.0.1", GPSD_PORT).
+        pub fn new_gpsd(addr: impl ToSocketAddrs) -> io::Result<Gps> {
+            Ok(Gps::from_port(Box::new(GpsdPort::connect(addr, GpsdFormat::Nmea)?)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod gpsd_client_test {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+    use std::time::{Duration, Instant};
+
+    use crate::open_gps::gps::{Gps, GpsSentence};
+    use crate::open_gps::mock_port::GGA;
+    use crate::pmtk::send_pmtk::Pmtk001Ack;
+
+    use super::gpsd_client::{GpsdFormat, GpsdPort};
+
+    const GSV_1: &str = "$GPGSV,2,1,08,12,12,100,,04,11,331,,16,06,282,,05,05,074,22*7C\r\n";
+    const GSV_2: &str = "$GPGSV,2,2,08,32,01,215,,41,,,*42\r\n";
+
+    /// A stand-in gpsd: gives the lines to the first client once it has sent a ?WATCH, then
+    /// closes the connection. Gives the ?WATCH sent.
+    fn stand_in(lines: Vec<String>) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let thread = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut client = BufReader::new(stream);
+            let mut watch = String::new();
+            client.read_line(&mut watch).unwrap();
+            for line in lines {
+                client.get_mut().write_all(line.as_bytes()).unwrap();
+            }
+            watch
+        });
+        (addr, thread)
+    }
+
+    const VERSION: &str = "{\"class\":\"VERSION\",\"release\":\"3.22\",\"proto_major\":3,\"proto_minor\":14}\r\n";
+
+    #[test]
+    fn reads_nmea() {
+        let lines = vec![VERSION, GGA, "{\"class\":\"DEVICES\",\"devices\":[]}\r\n", GSV_1, GSV_2];
+        let (addr, server) = stand_in(lines.into_iter().map(String::from).collect());
+        let mut gps = Gps::new_gpsd(addr.as_str()).unwrap();
+        assert!(matches!(gps.update(), GpsSentence::GGA(gga) if gga.satellites_used == 9));
+        assert!(matches!(gps.update(), GpsSentence::GSV(sats) if sats.len() == 6));
+        assert!(server.join().unwrap().contains("\"nmea\":true"));
+
+        assert_eq!(gps.update(), GpsSentence::NoConnection);
+        assert!(gps.port_error.is_some());
+    }
+
+    #[test]
+    fn commands_fail_without_waiting() {
+        let (addr, server) = stand_in(vec![VERSION.to_string(), GGA.to_string()]);
+        let mut gps = Gps::new_gpsd(addr.as_str()).unwrap();
+        gps.command_policy.backoff = Duration::from_millis(0);
+        let start = Instant::now();
+        assert_eq!(gps.send_with_ack("PMTK220,1000"), Pmtk001Ack::NoPacket);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(gps.last_attempt, 0);
+        assert!(matches!(gps.update(), GpsSentence::GGA(_)));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn reads_json() {
+        let sky = r#"{"class":"SKY","hdop":1.17,"pdop":1.42,"vdop":0.8,"satellites":[{"PRN":29,"el":12,"az":100,"ss":30,"used":true},{"PRN":5,"el":5,"az":74,"ss":22,"used":false},{"PRN":2,"el":40,"az":10,"ss":35,"used":true},{"PRN":12,"el":3,"az":300,"used":false},{"PRN":14,"el":60,"az":200,"ss":40,"used":true}]}"#;
+        let tpv = r#"{"class":"TPV","mode":3,"status":2,"time":"2020-05-20T11:09:42.500Z","lat":51.54566,"lon":-0.098608,"altMSL":42.4,"geoidSep":47.0,"speed":5.144,"track":280.6}"#;
+        let lines = vec![VERSION.to_string(), format!("{}\r\n", sky), format!("{}\r\n", tpv), GGA.to_string()];
+        let (addr, server) = stand_in(lines);
+        let mut gps = Gps::from_port(Box::new(GpsdPort::connect(addr.as_str(), GpsdFormat::Json).unwrap()));
+
+        let gsa = match gps.update() {
+            GpsSentence::GSA(gsa) => gsa,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(gsa.used_satellites(), vec![29, 2, 14]);
+        assert_eq!(gsa.vdop, Some(0.8));
+        assert!(matches!(gps.update(), GpsSentence::GSV(sats) if sats.len() == 5 && sats[3].snr.is_none()));
+        let rmc = match gps.update() {
+            GpsSentence::RMC(rmc) => rmc,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!((rmc.utc, rmc.date.as_str(), rmc.fix_status), (110942.5, "200520", true));
+        assert_eq!(rmc.speed, Some(10.0));
+        // ddmm.mmmm and f32 keep lat and lon to within a metre.
+        assert!((rmc.longitude.unwrap() + 0.098608).abs() < 0.00001);
+        let gga = match gps.update() {
+            GpsSentence::GGA(gga) => gga,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!((gga.satellites_used, gga.msl_alt, gga.hdop), (3, Some(42.4), Some(1.17)));
+        assert!((gga.lat.unwrap() - 51.54566).abs() < 0.00001);
+        // NMEA lines are not asked for in JSON, so are dropped.
+        assert_eq!(gps.update(), GpsSentence::NoConnection);
+        assert!(server.join().unwrap().contains("\"json\":true"));
+    }
+}
+
+#[cfg(all(test, unix))]
+mod gpsd_server_test {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpStream;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use serialport::posix::TTYPort;
+    use serialport::SerialPort;
+
+    use crate::open_gps::gps::{parse_line, Gps, GpsSentence};
+    use crate::pmtk::send_pmtk::add_checksum;
+
+    use super::gpsd_json::{FixState, Report, Sky, Tpv, Version, Watch};
+    use super::gpsd_server::GpsdServer;
+
+    /// At the same time as rmc().
+    const GGA: &str = "$GNGGA,110942.000,5132.7394,N,00005.9165,W,2,9,1.17,42.4,M,47.0,M,,*61\r\n";
+    const GSA: &str = "$GPGSA,A,3,29,02,26,25,31,14,,,,,,,1.42,1.17,0.80*07\r\n";
+    const GSV: &str = "$GPGSV,1,1,02,29,12,100,30,05,05,074,22*72\r\n";
+
+    fn rmc() -> String {
+        add_checksum("GNRMC,110942.000,A,5132.7394,N,00005.9165,W,10.00,280.60,200520,,,D".to_string())
+    }
+
+    fn parse(line: &str) -> Option<GpsSentence> {
+        Some(parse_line(line)).filter(|sentence| *sentence != GpsSentence::InvalidSentence)
+    }
+
+    fn connect(server: &GpsdServer) -> BufReader<TcpStream> {
+        let stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        BufReader::new(stream)
+    }
+
+    #[test]
+    fn fix_state_builds_tpv_and_sky() {
+        let mut state = FixState::new(Some("/dev/serial0".to_string()));
+        assert!(state.update(&parse(GSA).unwrap()).is_none());
+        let tpv = match state.update(&parse(rmc().as_str()).unwrap()) {
+            Some(Report::Tpv(tpv)) => tpv,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(tpv.time.as_deref(), Some("2020-05-20T11:09:42.000Z"));
+        assert_eq!(tpv.mode, 3);
+        assert!((tpv.speed.unwrap() - 5.144).abs() < 0.001);
+
+        let tpv = match state.update(&parse(GGA).unwrap()) {
+            Some(Report::Tpv(tpv)) => tpv,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!((tpv.alt_msl, tpv.status), (Some(42.4f32 as f64), Some(2)));
+        assert!((tpv.lat.unwrap() - 51.54566).abs() < 0.00001);
+
+        let sky = match state.update(&parse(GSV).unwrap()) {
+            Some(Report::Sky(sky)) => sky,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(sky.satellites.len(), 2);
+        assert!(sky.satellites[0].used);
+        assert!(!sky.satellites[1].used);
+        assert_eq!(sky.hdop, Some(1.17f32 as f64));
+    }
+
+    #[test]
+    fn watch_args() {
+        let mut watch = Watch::default();
+        watch.apply(r#"{"enable":true}"#).unwrap();
+        assert!(watch.enable && watch.json && !watch.nmea);
+        watch.apply(r#"{"json":false,"nmea":true}"#).unwrap();
+        assert!(watch.enable && !watch.json && watch.nmea);
+        watch.apply("").unwrap();
+        assert!(watch.nmea);
+        assert!(watch.apply("{enable").is_err());
+    }
+
+    /// Reads lines until one has the class, giving it.
+    fn read_class(client: &mut BufReader<TcpStream>, class: &str) -> String {
+        let start = Instant::now();
+        let mut line = String::new();
+        loop {
+            assert!(start.elapsed() < Duration::from_secs(10), "no {}", class);
+            line.clear();
+            client.read_line(&mut line).unwrap();
+            if line.contains(format!("\"class\":\"{}\"", class).as_str()) {
+                return line;
+            }
+        }
+    }
+
+    #[test]
+    fn serves_clients() {
+        let (mut master, slave) = TTYPort::pair().unwrap();
+        let gps = Gps::new(slave.name().unwrap().as_str(), "9600").unwrap();
+        drop(slave);
+        let server = GpsdServer::start(gps, "127.0.0.1:0").unwrap();
+
+        let mut json = connect(&server);
+        let version: Version = serde_json::from_str(read_class(&mut json, "VERSION").as_str()).unwrap();
+        assert_eq!(version.proto_major, 3);
+        json.get_mut().write_all(b"?WATCH={\"enable\":true,\"json\":true};\n").unwrap();
+        assert!(read_class(&mut json, "DEVICES").contains("NMEA0183"));
+        read_class(&mut json, "WATCH");
+
+        let mut nmea = connect(&server);
+        read_class(&mut nmea, "VERSION");
+        nmea.get_mut().write_all(b"?WATCH={\"enable\":true,\"nmea\":true}\n?POLL;").unwrap();
+        read_class(&mut nmea, "WATCH");
+        assert!(read_class(&mut nmea, "ERROR").contains("POLL"));
+        let start = Instant::now();
+        while server.clients() < 2 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+        }
+
+        let rmc = rmc();
+        for line in [GSA, rmc.as_str(), GGA, GSV].iter() {
+            master.write_all(line.as_bytes()).unwrap();
+        }
+        let tpv: Tpv = serde_json::from_str(read_class(&mut json, "TPV").as_str()).unwrap();
+        assert_eq!(tpv.time.as_deref(), Some("2020-05-20T11:09:42.000Z"));
+        let sky: Sky = serde_json::from_str(read_class(&mut json, "SKY").as_str()).unwrap();
+        assert_eq!(sky.satellites.len(), 2);
+
+        let mut line = String::new();
+        nmea.read_line(&mut line).unwrap();
+        assert_eq!(line, GSA);
+
+        drop(json);
+        let gps = server.stop().unwrap();
+        assert!(gps.trace.is_none());
+    }
+
+    #[test]
+    fn slow_client_is_dropped() {
+        let (master, slave) = TTYPort::pair().unwrap();
+        let gps = Gps::new(slave.name().unwrap().as_str(), "9600").unwrap();
+        drop(slave);
+        let server = GpsdServer::start(gps, "127.0.0.1:0").unwrap();
+
+        // Never reads after its WATCH.
+        let mut slow = connect(&server);
+        slow.get_mut().write_all(b"?WATCH={\"enable\":true,\"nmea\":true}\n").unwrap();
+        read_class(&mut slow, "WATCH");
+        let mut fast = connect(&server);
+        fast.get_mut().write_all(b"?WATCH={\"enable\":true,\"nmea\":true}\n").unwrap();
+        read_class(&mut fast, "WATCH");
+
+        let running = Arc::new(AtomicBool::new(true));
+        let gps_side = {
+            let (mut master, running) = (master, running.clone());
+            thread::spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    master.write_all(GGA.as_bytes()).unwrap();
+                }
+            })
+        };
+        let start = Instant::now();
+        let mut line = String::new();
+        while server.clients() > 1 {
+            assert!(start.elapsed() < Duration::from_secs(60), "slow client not dropped");
+            line.clear();
+            fast.read_line(&mut line).unwrap();
+            assert_eq!(line, GGA);
+        }
+        line.clear();
+        fast.read_line(&mut line).unwrap();
+        assert_eq!(line, GGA);
+        running.store(false, Ordering::SeqCst);
+        gps_side.join().unwrap();
+        server.stop().unwrap();
+    }
+
+    #[test]
+    fn endless_command_is_dropped() {
+        let (_master, slave) = TTYPort::pair().unwrap();
+        let gps = Gps::new(slave.name().unwrap().as_str(), "9600").unwrap();
+        drop(slave);
+        let server = GpsdServer::start(gps, "127.0.0.1:0").unwrap();
+        let mut client = connect(&server);
+        read_class(&mut client, "VERSION");
+        client.get_mut().write_all(&[b'?'; 5000]).unwrap();
+        let start = Instant::now();
+        while server.clients() > 0 {
+            assert!(start.elapsed() < Duration::from_secs(10), "client not dropped");
+            thread::sleep(Duration::from_millis(10));
+        }
+        // Closed, or reset as the rest of the command was never read.
+        assert!(!matches!(client.read_line(&mut String::new()), Ok(read) if read > 0));
+        server.stop().unwrap();
+    }
+}
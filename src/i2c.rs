@@ -0,0 +1,312 @@
+//! # I2C transport
+//! The PA1010D mini gps is usually wired over I2C, at address 0x10, rather than UART.
+//!
+//! I2cPort reads the gps in chunks and gives the bytes to the normal Gps API as if it was a serial
+//! port. The gps pads its I2C output with 0x0A (\n) when it has nothing to send, so any \n not
+//! after a \r is dropped. Commands are written to the bus as they would be to a serial port.
+//!
+//! Any bus that implements I2cBus can be used:
+//! - LinuxI2CDevice, from the i2cdev crate, with the `i2cdev` feature.
+//! - EmbeddedI2c, wrapping any embedded-hal 0.2 blocking I2C bus, with the `embedded-hal` feature.
+//! ```ignore
+//! let mut gps = Gps::new_i2c("/dev/i2c-1")?;
+//! // Or
+//! let mut gps = Gps::from_port(Box::new(I2cPort::new(EmbeddedI2c(i2c))));
+//! let sentence = gps.update();
+//! ```
+
+pub mod i2c_port {
+    //! I2cPort and the buses it can read from.
+    use std::collections::VecDeque;
+    use std::io::{self, Read, Write};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use serialport::prelude::*;
+    use serialport::{ClearBuffer, Error, ErrorKind};
+
+    /// I2C address of the PA1010D.
+    pub const PA1010D_ADDRESS: u8 = 0x10;
+
+    /// Bytes read from the bus at a time. The gps keeps up to 255 bytes waiting.
+    pub const I2C_CHUNK: usize = 32;
+
+    /// An I2C bus that I2cPort can read the gps from.
+    pub trait I2cBus: Send {
+        /// Reads buf.len() bytes from the device at address.
+        fn read(&mut self, address: u8, buf: &mut [u8]) -> io::Result<()>;
+        /// Writes bytes to the device at address.
+        fn write(&mut self, address: u8, bytes: &[u8]) -> io::Result<()>;
+    }
+
+    /// The address is set when the device is opened, so the address given is not used.
+    #[cfg(feature = "i2cdev")]
+    impl I2cBus for i2cdev::linux::LinuxI2CDevice {
+        fn read(&mut self, _address: u8, buf: &mut [u8]) -> io::Result<()> {
+            i2cdev::core::I2CDevice::read(self, buf).map_err(io::Error::from)
+        }
+
+        fn write(&mut self, _address: u8, bytes: &[u8]) -> io::Result<()> {
+            i2cdev::core::I2CDevice::write(self, bytes).map_err(io::Error::from)
+        }
+    }
+
+    /// Any embedded-hal 0.2 blocking I2C bus.
+    #[cfg(feature = "embedded-hal")]
+    pub struct EmbeddedI2c<I>(pub I);
+
+    #[cfg(feature = "embedded-hal")]
+    impl<I, E> I2cBus for EmbeddedI2c<I>
+    where
+        I: embedded_hal::blocking::i2c::Read<Error = E> + embedded_hal::blocking::i2c::Write<Error = E> + Send,
+        E: std::fmt::Debug,
+    {
+        fn read(&mut self, address: u8, buf: &mut [u8]) -> io::Result<()> {
+            self.0.read(address, buf).map_err(|e| io::Error::other(format!("{:?}", e)))
+        }
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> io::Result<()> {
+            self.0.write(address, bytes).map_err(|e| io::Error::other(format!("{:?}", e)))
+        }
+    }
+
+    /// Reads the gps over I2C. Implements SerialPort so it can be given to Gps::from_port.
+    pub struct I2cPort<B: I2cBus> {
+        bus: B,
+        pub address: u8,
+        /// Bytes read from the bus at a time.
+        pub chunk: usize,
+        /// How long to wait before giving TimedOut when the gps has nothing to send.
+        pub poll_interval: Duration,
+        input: VecDeque<u8>,
+        last: u8,
+    }
+
+    impl<B: I2cBus> I2cPort<B> {
+        /// A port for the PA1010D on bus.
+        pub fn new(bus: B) -> I2cPort<B> {
+            I2cPort::with_address(bus, PA1010D_ADDRESS)
+        }
+
+        pub fn with_address(bus: B, address: u8) -> I2cPort<B> {
+            I2cPort {
+                bus,
+                address,
+                chunk: I2C_CHUNK,
+                poll_interval: Duration::from_millis(10),
+                input: VecDeque::new(),
+                last: 0,
+            }
+        }
+
+        /// Gives back the bus.
+        pub fn into_inner(self) -> B {
+            self.bus
+        }
+
+        /// Reads a chunk from the bus, keeping everything but the padding.
+        fn read_chunk(&mut self) -> io::Result<()> {
+            let mut chunk = vec![0; self.chunk];
+            self.bus.read(self.address, &mut chunk)?;
+            for byte in chunk {
+                if byte == b'\n' && self.last != b'\r' {
+                    continue;
+                }
+                self.last = byte;
+                self.input.push_back(byte);
+            }
+            Ok(())
+        }
+    }
+
+    impl<B: I2cBus> Read for I2cPort<B> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.input.is_empty() {
+                self.read_chunk()?;
+            }
+            if self.input.is_empty() {
+                sleep(self.poll_interval);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Nothing from the gps"));
+            }
+            let mut read = 0;
+            while read < buf.len() {
+                match self.input.pop_front() {
+                    Some(byte) => buf[read] = byte,
+                    None => break,
+                }
+                read += 1;
+            }
+            Ok(read)
+        }
+    }
+
+    impl<B: I2cBus> Write for I2cPort<B> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bus.write(self.address, buf)?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// I2C has no serial settings, so these do nothing.
+    impl<B: I2cBus> SerialPort for I2cPort<B> {
+        fn name(&self) -> Option<String> { Some(format!("i2c 0x{:02x}", self.address)) }
+        fn settings(&self) -> SerialPortSettings { SerialPortSettings::default() }
+        fn baud_rate(&self) -> serialport::Result<u32> { Ok(9600) }
+        fn data_bits(&self) -> serialport::Result<DataBits> { Ok(DataBits::Eight) }
+        fn flow_control(&self) -> serialport::Result<FlowControl> { Ok(FlowControl::None) }
+        fn parity(&self) -> serialport::Result<Parity> { Ok(Parity::None) }
+        fn stop_bits(&self) -> serialport::Result<StopBits> { Ok(StopBits::One) }
+        fn timeout(&self) -> Duration { self.poll_interval }
+        fn set_all(&mut self, _: &SerialPortSettings) -> serialport::Result<()> { Ok(()) }
+        fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> { Ok(()) }
+        fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> { Ok(()) }
+        fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> { Ok(()) }
+        fn set_parity(&mut self, _: Parity) -> serialport::Result<()> { Ok(()) }
+        fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> { Ok(()) }
+        fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+            self.poll_interval = timeout;
+            Ok(())
+        }
+        fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> { Ok(()) }
+        fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> { Ok(()) }
+        fn read_clear_to_send(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_data_set_ready(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_ring_indicator(&mut self) -> serialport::Result<bool> { Ok(false) }
+        fn read_carrier_detect(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn bytes_to_read(&self) -> serialport::Result<u32> { Ok(self.input.len() as u32) }
+        fn bytes_to_write(&self) -> serialport::Result<u32> { Ok(0) }
+        fn clear(&self, _: ClearBuffer) -> serialport::Result<()> { Ok(()) }
+        fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+            Err(Error::new(ErrorKind::Unknown, "I2C port can't be cloned"))
+        }
+    }
+
+    #[cfg(feature = "i2cdev")]
+    impl crate::open_gps::gps::Gps {
+        /// Gps for a PA1010D on the linux I2C bus at path, such as /dev/i2c-1.
+        pub fn new_i2c(path: &str) -> io::Result<crate::open_gps::gps::Gps> {
+            let device = i2cdev::linux::LinuxI2CDevice::new(path, PA1010D_ADDRESS as u16).map_err(io::Error::from)?;
+            Ok(crate::open_gps::gps::Gps::from_port(Box::new(I2cPort::new(device))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod i2c_test {
+    use std::collections::VecDeque;
+    use std::io::{self, Read};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::open_gps::gps::{Gps, GpsSentence};
+    use crate::open_gps::mock_port::GGA;
+    use crate::pmtk::send_pmtk::{add_checksum, Pmtk001Ack};
+
+    use super::i2c_port::{I2cBus, I2cPort, I2C_CHUNK, PA1010D_ADDRESS};
+
+    /// Gives the stream a chunk at a time, then padding, and keeps what is written.
+    struct ReplayBus {
+        stream: VecDeque<u8>,
+        written: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl I2cBus for ReplayBus {
+        fn read(&mut self, address: u8, buf: &mut [u8]) -> io::Result<()> {
+            assert_eq!(address, PA1010D_ADDRESS);
+            for byte in buf.iter_mut() {
+                *byte = self.stream.pop_front().unwrap_or(b'\n');
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, _address: u8, bytes: &[u8]) -> io::Result<()> {
+            self.written.lock().unwrap().extend_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    /// What the gps gives when read in 32 byte chunks: padding between and after sentences.
+    fn captured(lines: &[&str]) -> VecDeque<u8> {
+        let mut stream = b"\n\n\n\n".to_vec();
+        for line in lines {
+            stream.extend_from_slice(line.as_bytes());
+            stream.extend_from_slice(b"\n\n\n\n\n\n\n");
+        }
+        stream.into_iter().collect()
+    }
+
+    #[test]
+    fn strips_padding_and_keeps_line_ends() {
+        let bus = ReplayBus { stream: captured(&[GGA, GGA]), written: Arc::new(Mutex::new(Vec::new())) };
+        let mut gps = Gps::from_port(Box::new(I2cPort::new(bus)));
+        for _ in 0..2 {
+            match gps.update() {
+                GpsSentence::GGA(gga) => assert_eq!(gga.satellites_used, 9),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn line_end_across_chunks_and_padding_mid_sentence() {
+        // Padding so the first sentence's \r is the last byte of a chunk and its \n starts the next.
+        let cr = GGA.len() - 2;
+        let mut stream = vec![b'\n'; I2C_CHUNK - 1 - cr % I2C_CHUNK];
+        stream.extend_from_slice(GGA.as_bytes());
+        assert_eq!(stream[I2C_CHUNK * (cr / I2C_CHUNK + 1) - 1..][..2], *b"\r\n");
+        // The gps ran out of bytes part way through the second sentence.
+        stream.extend_from_slice(GGA[..20].as_bytes());
+        stream.extend_from_slice(b"\n\n\n\n\n");
+        stream.extend_from_slice(GGA[20..].as_bytes());
+
+        let bus = ReplayBus { stream: stream.into_iter().collect(), written: Arc::new(Mutex::new(Vec::new())) };
+        let mut port = I2cPort::new(bus);
+        port.poll_interval = Duration::from_millis(0);
+        let mut read = Vec::new();
+        let mut buf = [0; 64];
+        while let Ok(n) = port.read(&mut buf) {
+            read.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(String::from_utf8(read).unwrap(), [GGA, GGA].concat());
+    }
+
+    #[test]
+    fn writes_commands() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let ack = add_checksum("PMTK001,220,3".to_string());
+        let bus = ReplayBus { stream: captured(&[GGA, ack.as_str()]), written: written.clone() };
+        let mut gps = Gps::from_port(Box::new(I2cPort::new(bus)));
+        assert_eq!(gps.send_with_ack("PMTK220,1000"), Pmtk001Ack::Success);
+        assert_eq!(written.lock().unwrap().as_slice(), add_checksum("PMTK220,1000".to_string()).as_bytes());
+        assert!(matches!(gps.update(), GpsSentence::GGA(_)));
+    }
+
+    #[cfg(feature = "embedded-hal")]
+    #[test]
+    fn embedded_hal_bus() {
+        use embedded_hal_mock::eh0::i2c::{Mock, Transaction};
+
+        use super::i2c_port::EmbeddedI2c;
+
+        let stream: Vec<u8> = captured(&[GGA]).into_iter().collect();
+        let mut transactions: Vec<Transaction> = stream
+            .chunks(32)
+            .map(|chunk| {
+                let mut chunk = chunk.to_vec();
+                chunk.resize(32, b'\n');
+                Transaction::read(PA1010D_ADDRESS, chunk)
+            })
+            .collect();
+        transactions.push(Transaction::write(PA1010D_ADDRESS, add_checksum("PMTK000".to_string()).into_bytes()));
+        let mut i2c = Mock::new(&transactions);
+
+        let mut gps = Gps::from_port(Box::new(I2cPort::new(EmbeddedI2c(i2c.clone()))));
+        assert!(matches!(gps.update(), GpsSentence::GGA(_)));
+        gps.send_command("PMTK000").unwrap();
+        i2c.done();
+    }
+}
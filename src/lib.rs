@@ -10,9 +10,16 @@
 //! ## Modules
 //! The PMTK module is a way of easily sending command to the GPS, changing it's settings.
 //!
+//! ReceiverConfig holds every setting in one struct that can be loaded from a file, applied with
+//! gps.apply_config() and checked against gps.read_config().
+//!
 //! The nmea module reads the data given by the GPS. Use the gps.update() trait to get easy to use
 //! data, but for specific use cases custom commands can be read.
 //!
+//! The nmea_core modules (checksum, fields, sentences, pmtk_encoder and, with the `embedded-hal`
+//! feature, serial_reader) need neither std nor an allocator. Build with `default-features = false`
+//! to use them on a microcontroller. Everything else needs the `std` feature, on by default.
+//!
 //! ## Hardware specs
 //! Please read the docs for the specific GPS module you are using.
 //!
@@ -32,7 +39,13 @@
 //! - RMC(RmcData) -> [RmcData](nmea/rmc/struct.RmcData.html): UTC, Latitude, Longitude, speed, course, date, magnetic variation.
 //! - NoConnection -> The gps is not connected, no bytes are being received
 //! - InvalidBytes -> Bytes being received are not valid, probably port baud rate and gps baud rate mismatch
-//! - InvalidSentence -> Sentence outputted has incorrect checksum, the sentence was probably incomplete.
+//! - InvalidSentence -> Sentence outputted has incorrect checksum, the sentence was probably incomplete,
+//!   or is from a different talker to gps.talker.
+//! - ZDA(ZdaData) -> [ZdaData](nmea/zda/struct.ZdaData.html): UTC, day, month, year.
+//! - Sleeping -> The gps is in a power saving mode so no bytes are expected, see gps.power_state.
+//! - DeviceInfo(DeviceInfo) -> Never given by gps.update(). Put at the start of a log file to record the
+//!   gps settings, see gps.device_info().
+//! - Timed(TimedSentence) -> Never given by gps.update(). A log entry written by TimedSentence::append_to().
 //!
 //! # Some technical information
 //! ## Dilution of precision
@@ -73,15 +86,86 @@
 //! GpsSentence::GGA(GgaData::default()).append_to("file"); // Append a single item to a file
 //! ```
 //!
+//! try_read_from() gives an error, rather than leaving out the rest of the file, if an entry can't
+//! be read.
+//!
+//! gps.update_timed() gives a TimedSentence, with when the sentence arrived at the host. Its
+//! append_to() writes to the same log, and its read_from() gives only the timed entries.
 //!
 //!
+//!
+
 
+#![cfg_attr(not(feature = "std"), no_std)]
 
-pub use crate::nmea::{gga, gll, gsa, gsv, rmc, vtg};
-pub use crate::open_gps::gps::{Gps, GpsSentence};
-pub use crate::pmtk::send_pmtk::{set_baud_rate, NmeaOutput};
+#[cfg(feature = "std")]
+pub use crate::config::device_info::DeviceInfo;
+#[cfg(feature = "std")]
+pub use crate::datum::datums::{Datum, DatumInfo, Ellipsoid, DATUMS};
+#[cfg(feature = "std")]
+pub use crate::config::receiver_config::{ConfigMismatch, ReceiverConfig};
+#[cfg(feature = "std")]
+pub use crate::nmea::{gga, gll, gsa, gsv, rmc, vtg, zda};
+#[cfg(feature = "std")]
+pub use crate::open_gps::gps::{Gps, GpsSentence, parse_line, PowerState, MAX_PENDING, MAX_STRAY};
+#[cfg(feature = "std")]
+pub use crate::nmea::parse_nmea::Talker;
+#[cfg(feature = "std")]
+pub use crate::pmtk::chip_support;
+#[cfg(feature = "std")]
+pub use crate::pmtk::send_pmtk::{set_baud_rate, CommandPolicy, Pmtk001Ack, PmtkAck, ELEVATION_MASK_RANGE, MIN_SNR_RANGE, AidingPosition, AidingResult, BuildDate, Chip, DgpsMode, EpoData, FirmwareRelease, GnssSearchMode, NmeaOutput, Sbas, SbasMode, PeriodicTimes, PowerMode, PpsConfig, PpsMode, UserOption, BAUD_RATES};
+#[cfg(feature = "std")]
+pub use crate::timing::{pps, utc};
+#[cfg(feature = "std")]
+pub use crate::service::gps_service::{GpsService, SUBSCRIBER_BUFFER};
+#[cfg(feature = "std")]
+pub use crate::supervisor::gps_supervisor::{Backoff, Supervisor, SupervisorEvent};
+#[cfg(feature = "std")]
+pub use crate::trace::wire_trace;
+#[cfg(feature = "std")]
+pub use crate::timed::timed_sentence::TimedSentence;
+#[cfg(feature = "std")]
+pub use crate::i2c::i2c_port;
+#[cfg(feature = "std")]
+pub use crate::framing::line_framer::{FramerStats, LineFramer, MAX_LINE_LEN};
+#[cfg(feature = "gpsd")]
+pub use crate::gpsd::{gpsd_json, gpsd_client::{GpsdFormat, GpsdPort}, gpsd_server::{GpsdServer, CLIENT_QUEUE, GPSD_PORT}};
+#[cfg(all(feature = "ntpshm", unix))]
+pub use crate::ntpshm::ntp_shm::{NtpShm, ShmSample, NTPD_BASE};
+#[cfg(feature = "tokio")]
+pub use crate::async_gps::tokio_gps::{AsyncGps, SentenceStream};
+pub use crate::nmea_core::{checksum, fields, pmtk_encoder, sentences};
+#[cfg(feature = "embedded-hal")]
+pub use crate::nmea_core::serial_reader;
 
+#[cfg(feature = "tokio")]
+mod async_gps;
+#[cfg(feature = "std")]
+mod config;
+#[cfg(feature = "std")]
+mod datum;
+#[cfg(feature = "std")]
+mod framing;
+#[cfg(feature = "gpsd")]
+mod gpsd;
+#[cfg(feature = "std")]
+mod i2c;
+#[cfg(feature = "std")]
 mod nmea;
+mod nmea_core;
+#[cfg(all(feature = "ntpshm", unix))]
+mod ntpshm;
+#[cfg(feature = "std")]
 mod pmtk;
+#[cfg(feature = "std")]
+mod service;
+#[cfg(feature = "std")]
 mod open_gps;
-
+#[cfg(feature = "std")]
+mod supervisor;
+#[cfg(feature = "std")]
+mod timed;
+#[cfg(feature = "std")]
+mod timing;
+#[cfg(feature = "std")]
+mod trace;
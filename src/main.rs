@@ -5,6 +5,9 @@ fn main() {
     let mut gps = Gps::new("/dev/serial0", "9600").unwrap();
     gps.pmtk_314_api_set_nmea_output(NmeaOutput { gga: 1, gsa: 1, gsv: 1, gll: 1, rmc: 1, vtg: 1, pmtkchn_interval: 1 });
     let _r = gps.pmtk_220_set_nmea_updaterate("1000");
+    let info = gps.device_info();
+    println!("{}", info);
+    GpsSentence::DeviceInfo(info).append_to("main_file");
     loop {
         let values = gps.update();
 
@@ -8,6 +8,7 @@
 //! - GSV -> Satellites in view data: sat id, elevation, azimuth and SNR for each sat seen.
 //! - RMC -> UTC, Latitude, Longitude, speed, course, date, magnetic variation.
 //! - GLL -> Latitude, Longitude.
+//! - ZDA -> UTC, day, month, year and local time zone.
 //!
 //! ## Sentence prefix: ${GP, GL, GA, GN}{GGA, GSA, GSV, RMC, VTG}
 //! GP is short for GPS (American)
@@ -16,6 +17,8 @@
 //!
 //! GA is short for Galileo (EU)
 //!
+//! GB is short for BeiDou (Chinese)
+//!
 //! GN is multi-system.
 //!
 //! ### Prefixes table ({} means heading of GP/GL/GA is added.
@@ -27,6 +30,9 @@
 //!
 //! In the GP+GL and GP+GL+GA modes, all satellites from those systems are used for the best fix.
 //!
+//! The systems searched are set with gps.pmtk_353_api_set_gnss_search_mode(), which also sets the
+//! talker that gps.update() expects for GGA, RMC, VTG and GLL.
+//!
 //! ## Notes
 //! For GSA sentences, there is a sentence for each family of satellites seen (GPS, GLONASS and Galileo)
 //!, probably all in that order. It seems that the DOPs are all the same between them.
@@ -34,38 +40,20 @@
 pub mod parse_nmea {
     //! Main module for parsing any NMEA sentence and exporting NMEA parsing to lib.rs
 
+    use crate::nmea_core::fields::Fields;
+    use crate::nmea_core::sentences;
     use crate::open_gps;
 
+    /// Degrees from ddmm.mmmm or dddmm.mmmm and N/S/E/W, as nmea_core::sentences::parse_degrees.
     pub fn _parse_degrees(degrees: &str, compass_direction: &str) -> Option<f32> {
-        // Parse NMEA lat/long data pair dddmm.mmmm into pure degrees value.
-        // ddd is degrees, mm.mmmm is minutes
-        // NMEA format is either ddmm.mmmmm or dddmm.mmmmm
-        // Formula is ->
-        if degrees.is_empty() {
-            return None;
-        }
-        let deg: f32;
-        let minutes: f32;
-        let first_half: Vec<&str> = degrees.split('.').collect();
-
-        if first_half[0].len() == 4 {
-            deg = (&degrees[0..2]).parse::<f32>().unwrap();
-            minutes = ((&degrees[2..]).parse::<f32>().unwrap()) / 60.0;
-        } else {
-            deg = (&degrees[0..3]).parse::<f32>().unwrap();
-            minutes = ((&degrees[3..]).parse::<f32>().unwrap()) / 60.0;
-        }
-
-        let r: f32 = deg + minutes;
-        let r: f32 = format!("{:.6}", r).parse().unwrap(); // Round to 6 decimal places.
+        sentences::parse_degrees(degrees, compass_direction)
+    }
 
-        if (compass_direction == "N") | (compass_direction == "E") {
-            return Some(r);
-        } else if (compass_direction == "S") | (compass_direction == "W") {
-            return Some(r * -1.0);
-        } else {
-            panic!("Compass direction not north or south")
-        }
+    /// Runs a nmea_core parser on the fields from parse_sentence.
+    pub fn with_fields<T>(args: &[&str], parse: fn(&Fields) -> T) -> T {
+        let header = args.first().copied().unwrap_or("");
+        let rest = if args.len() > 1 { Some(args[1..].join(",")) } else { None };
+        parse(&Fields::from_parts(header, rest.as_deref()))
     }
 
     pub fn _format_hhmmss(time: &str) -> String {
@@ -79,6 +67,33 @@ pub mod parse_nmea {
         return format!("{}:{}:{}", hours, mins, secs);
     }
 
+    /// The talker, the two letters after the $, says which satellite system gave the sentence.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum Talker {
+        Gps,
+        Glonass,
+        Galileo,
+        Beidou,
+        Qzss,
+        Multi,
+        Unknown,
+    }
+
+    impl Talker {
+        /// Talker from a sentence header, such as $GNGGA.
+        pub fn from_header(header: &str) -> Talker {
+            match header.get(1..3) {
+                Some("GP") => Talker::Gps,
+                Some("GL") => Talker::Glonass,
+                Some("GA") => Talker::Galileo,
+                Some("GB") | Some("BD") => Talker::Beidou,
+                Some("GQ") | Some("QZ") => Talker::Qzss,
+                Some("GN") => Talker::Multi,
+                _ => Talker::Unknown,
+            }
+        }
+    }
+
     pub fn parse_sentence(sentence: &str) -> Option<Vec<&str>> {
         // Assumes that a valid sentence is always given.
         // Convert sentence into a split vec along ','.
@@ -102,7 +117,8 @@ pub mod gga {
     //!
     //!
 
-    use super::parse_nmea::*;
+    use super::parse_nmea::with_fields;
+    use crate::nmea_core::sentences;
     use serde::{Serialize, Deserialize};
 
     /// Satellite fix type
@@ -159,35 +175,7 @@ pub mod gga {
             )
         }
 
-        // Parse time
-        let utc: f64 = args.get(1).unwrap().parse().unwrap();
-
-        // Parse lat
-        let lat: Option<f32> = _parse_degrees(args.get(2).unwrap(), args.get(3).unwrap());
-        let long: Option<f32> = _parse_degrees(args.get(4).unwrap(), args.get(5).unwrap());
-
-        let sat_fix = match args.get(6).unwrap() {
-            &"0" => SatFix::NoFix,
-            &"1" => SatFix::GpsFix,
-            &"2" => SatFix::DgpsFix,
-            _ => SatFix::NoFix,
-        };
-        let satellites_used: i32 = args.get(7).unwrap().parse().unwrap();
-        let hdop = args.get(8).unwrap().parse::<f32>().ok();
-        let msl_alt: Option<f32> = args.get(9).unwrap().parse::<f32>().ok();
-        let geoidal_sep: Option<f32> = args.get(11).unwrap().parse::<f32>().ok();
-        let age_diff_corr: Option<f32> = args.get(13).unwrap().parse::<f32>().ok();
-        return GgaData {
-            utc,
-            lat,
-            long,
-            sat_fix,
-            satellites_used,
-            hdop,
-            msl_alt,
-            geoidal_sep,
-            age_diff_corr,
-        };
+        GgaData::from(with_fields(&args, sentences::parse_gga))
     }
 }
 
@@ -196,6 +184,8 @@ pub mod gsa {
     //!
     //! Gives All the satellites that are being tracked and the HDOP, VDOP, PDOP.
 
+    use super::parse_nmea::with_fields;
+    use crate::nmea_core::sentences;
     use serde::{Serialize, Deserialize};
 
     /// Manual or automatic selection mode for 3d or 2d fix.
@@ -252,6 +242,45 @@ pub mod gsa {
         pub vdop: Option<f32>,
     }
 
+    impl GsaData {
+        /// Ids of the satellites used, sat1 to sat12.
+        pub fn used_satellites(&self) -> Vec<i32> {
+            [
+                self.sat1, self.sat2, self.sat3, self.sat4, self.sat5, self.sat6,
+                self.sat7, self.sat8, self.sat9, self.sat10, self.sat11, self.sat12,
+            ].iter().filter_map(|sat| *sat).collect()
+        }
+    }
+
+    /// Change in the satellites used between two sets of GSA sentences.
+    /// - removed: used before but not after.
+    /// - added: used after but not before.
+    /// - kept: used in both.
+    #[derive(PartialEq, Debug, Default, Clone)]
+    pub struct UsedSatellitesChange {
+        pub removed: Vec<i32>,
+        pub added: Vec<i32>,
+        pub kept: Vec<i32>,
+    }
+
+    /// Compares the satellites used in before and after.
+    ///
+    /// There is a GSA sentence for each satellite system, so give all the GSA sentences for one or
+    /// more fixes. Useful for seeing what changing the elevation mask or minimum SNR did.
+    pub fn compare_used_satellites(before: &[GsaData], after: &[GsaData]) -> UsedSatellitesChange {
+        let mut before: Vec<i32> = before.iter().flat_map(|gsa| gsa.used_satellites()).collect();
+        let mut after: Vec<i32> = after.iter().flat_map(|gsa| gsa.used_satellites()).collect();
+        before.sort_unstable();
+        before.dedup();
+        after.sort_unstable();
+        after.dedup();
+        UsedSatellitesChange {
+            removed: before.iter().filter(|sat| !after.contains(sat)).cloned().collect(),
+            added: after.iter().filter(|sat| !before.contains(sat)).cloned().collect(),
+            kept: before.iter().filter(|sat| after.contains(sat)).cloned().collect(),
+        }
+    }
+
     pub fn parse_gsa(args: Vec<&str>) -> GsaData {
         //! Format
         //! $G{}GSA, Mode, dimention_fix, Sat1, Sat2, Sat3, Sat4, Sat5, Sat6, Sat7, Sat8, Sat9, Sat10,
@@ -276,53 +305,7 @@ pub mod gsa {
             )
         }
 
-        let mode = match args.get(1).unwrap() {
-            &"M" => Mode::Manual,
-            &"A" => Mode::Automatic,
-            _ => Mode::Manual, // Default.
-        };
-        let dimention_fix = match args.get(2).unwrap() {
-            &"1" => DimensionFix::NotAvailable,
-            &"2" => DimensionFix::Dimension2d,
-            &"3" => DimensionFix::Dimension3d,
-            _ => DimensionFix::NotAvailable,
-        };
-        let sat1: Option<i32> = args.get(3).unwrap().parse::<i32>().ok();
-        let sat2: Option<i32> = args.get(4).unwrap().parse::<i32>().ok();
-        let sat3: Option<i32> = args.get(5).unwrap().parse::<i32>().ok();
-        let sat4: Option<i32> = args.get(6).unwrap().parse::<i32>().ok();
-        let sat5: Option<i32> = args.get(7).unwrap().parse::<i32>().ok();
-        let sat6: Option<i32> = args.get(8).unwrap().parse::<i32>().ok();
-        let sat7: Option<i32> = args.get(9).unwrap().parse::<i32>().ok();
-        let sat8: Option<i32> = args.get(10).unwrap().parse::<i32>().ok();
-        let sat9: Option<i32> = args.get(11).unwrap().parse::<i32>().ok();
-        let sat10: Option<i32> = args.get(12).unwrap().parse::<i32>().ok();
-        let sat11: Option<i32> = args.get(13).unwrap().parse::<i32>().ok();
-        let sat12: Option<i32> = args.get(14).unwrap().parse::<i32>().ok();
-
-        let pdop: Option<f32> = args.get(15).unwrap().parse::<f32>().ok();
-        let hdop: Option<f32> = args.get(16).unwrap().parse::<f32>().ok();
-        let vdop: Option<f32> = args.get(17).unwrap().parse::<f32>().ok();
-
-        return GsaData {
-            mode,
-            dimension_fix: dimention_fix,
-            sat1,
-            sat2,
-            sat3,
-            sat4,
-            sat5,
-            sat6,
-            sat7,
-            sat8,
-            sat9,
-            sat10,
-            sat11,
-            sat12,
-            pdop,
-            hdop,
-            vdop,
-        };
+        GsaData::from(with_fields(&args, sentences::parse_gsa))
     }
 }
 
@@ -333,6 +316,8 @@ pub mod gsv {
     //! multiple sentences.
     //!
 
+    use super::parse_nmea::with_fields;
+    use crate::nmea_core::sentences;
     use serde::{Serialize, Deserialize};
 
     /// The struct for a single satellite. To be accessed as a vector.
@@ -363,35 +348,13 @@ pub mod gsv {
         //! Max of 4 messages so 16 total satellites.
         //!
         //! Assumes that the sentences will always come one after another, I can just read the next sentences.
+        //!
+        //! Empty if the header isn't GSV.
 
-        let header = args.get(0).unwrap();
-        if &header[3..6] != "GSV" {
-            panic!(
-                "Incorrect sentence header. Should be GSV, it is {}",
-                header
-            )
-        }
-        let mut values = Vec::new();
-        let _meta = &args.get(0..4);
-        let sat1 = &args.get(4..8);
-        let sat2 = &args.get(8..12);
-        let sat3 = &args.get(12..16);
-        let sat4 = &args.get(16..20);
-        for sat in &[sat1, sat2, sat3, sat4] {
-            if sat.is_some() {
-                values.push(parse_sat(sat.unwrap()))
-            }
-        }
-        values
-    }
-
-    fn parse_sat(args: &[&str]) -> Satellites {
-        Satellites {
-            id: args.get(0).unwrap().parse().ok(),
-            elevation: args.get(1).unwrap().parse().ok(),
-            azimuth: args.get(2).unwrap().parse().ok(),
-            snr: args.get(3).unwrap().parse().ok(),
+        if args.first().and_then(|header| header.get(3..6)) != Some("GSV") {
+            return Vec::new();
         }
+        Vec::from(with_fields(&args, sentences::parse_gsv))
     }
 }
 
@@ -399,7 +362,8 @@ pub mod rmc {
     //! # Recommended Minimum data
     //!
     //! Gives UTC, latitude, longitude, Speed, True course, Magnetic course, Date, Magnatic variation
-    use super::parse_nmea::*;
+    use super::parse_nmea::with_fields;
+    use crate::nmea_core::sentences;
     use serde::{Serialize, Deserialize};
 
     /// # RmcData
@@ -431,32 +395,7 @@ pub mod rmc {
         //!         10                           11                  12
         //! magnetic variation (degrees), magnetic variation (E/W), Mode * checksum
 
-        let utc = args.get(1).unwrap().parse().unwrap_or(0.0);
-        let fix_status = match args.get(2).unwrap_or(&"V") {
-            &"A" => true,
-            &"V" => false,
-            _ => false,
-        };
-        let latitude: Option<f32> = _parse_degrees(args.get(3).unwrap(), args.get(4).unwrap());
-        let longitude: Option<f32> = _parse_degrees(args.get(5).unwrap(), args.get(6).unwrap());
-        let speed: Option<f32> = args.get(7).unwrap().parse::<f32>().ok();
-        let course: Option<f32> = args.get(8).unwrap().parse::<f32>().ok();
-        let date: String = args.get(9).unwrap_or(&"").to_string();
-        let mag_var: Option<f32> = match args.get(12).unwrap_or(&"") {
-            &"E" => args.get(11).unwrap().parse::<f32>().ok(),
-            &"W" => Some(args.get(11).unwrap().parse::<f32>().unwrap() * -1.0),
-            _ => None,
-        };
-        return RmcData {
-            utc,
-            fix_status,
-            latitude,
-            longitude,
-            speed,
-            course,
-            date,
-            mag_var,
-        };
+        RmcData::from(with_fields(&args, sentences::parse_rmc))
     }
 }
 
@@ -465,6 +404,8 @@ pub mod vtg {
     //!
     //! Gives course headings and speed data.
 
+    use super::parse_nmea::with_fields;
+    use crate::nmea_core::sentences;
     use serde::{Serialize, Deserialize};
 
     #[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
@@ -498,30 +439,14 @@ pub mod vtg {
         //!
         //! $GPVTG,  course, reference (True), course, reference (magnetic), Speed, knots,
         //! speed, kph, mode.
-        let true_course: Option<f32> = args.get(1).unwrap().parse::<f32>().ok();
-        let magnetic_course: Option<f32> = args.get(3).unwrap().parse::<f32>().ok();
-        let speed_knots: Option<f32> = args.get(5).unwrap().parse::<f32>().ok();
-        let speed_kph: Option<f32> = args.get(7).unwrap().parse::<f32>().ok();
-
-        let mode = match args.get(9).unwrap_or(&"N") {
-            &"A" => Mode::Autonomous,
-            &"D" => Mode::Differential,
-            &"E" => Mode::Estimated,
-            _ => Mode::Unknown,
-        };
-        return VtgData {
-            true_course,
-            magnetic_course,
-            speed_knots,
-            speed_kph,
-            mode,
-        };
+        VtgData::from(with_fields(&args, sentences::parse_vtg))
     }
 }
 
 pub mod gll {
     //! # Longitude and Latitude data only
-    use super::parse_nmea::*;
+    use super::parse_nmea::with_fields;
+    use crate::nmea_core::sentences;
     use serde::{Serialize, Deserialize};
 
     /// # GllData
@@ -546,24 +471,42 @@ pub mod gll {
         // [5] Time as hhmmss.ss,
         // [6] A
         // [7] A
+        GllData::from(with_fields(&args, sentences::parse_gll))
+    }
+}
 
-        // Parse Latitude.
+pub mod zda {
+    //! # Time and Date
+    //!
+    //! Gives UTC with the full date, including the four digit year.
+    use super::parse_nmea::with_fields;
+    use crate::nmea_core::sentences;
+    use serde::{Serialize, Deserialize};
 
-        let latitude: Option<f32> = _parse_degrees(args.get(1).unwrap(), args.get(2).unwrap());
-        let longitude: Option<f32> = _parse_degrees(args.get(3).unwrap(), args.get(4).unwrap());
-        // Parse time
-        let utc = args.get(5).unwrap_or(&"0").parse::<f64>().ok();
-        let is_valid = match args.get(6).unwrap_or(&"") {
-            &"A" => true,
-            &"V" => false,
-            _ => false,
-        };
-        return GllData {
-            latitude,
-            longitude,
-            utc,
-            is_valid,
-        };
+    /// # ZdaData
+    /// - utc: UTC as hhmmss.sss
+    /// - day: 1-31
+    /// - month: 1-12
+    /// - year: four digit year
+    /// - local_zone_hours: Local time zone offset from UTC in hours, -13 to 13.
+    /// - local_zone_minutes: Local time zone offset minutes.
+    #[derive(PartialEq, Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct ZdaData {
+        pub utc: f64,
+        pub day: Option<u8>,
+        pub month: Option<u8>,
+        pub year: Option<u16>,
+        pub local_zone_hours: Option<i8>,
+        pub local_zone_minutes: Option<u8>,
+    }
+
+    pub fn parse_zda(args: Vec<&str>) -> ZdaData {
+        //! Format
+        //!
+        //! $GPZDA, UTC, day, month, year, local zone hours, local zone minutes *checksum
+        //!
+        //! The MTK chips always give 00 for the local zone.
+        ZdaData::from(with_fields(&args, sentences::parse_zda))
     }
 }
 
@@ -573,6 +516,15 @@ mod nmea_tests {
     mod parse_nmea {
         use crate::nmea::parse_nmea;
 
+        #[test]
+        fn talker() {
+            assert_eq!(parse_nmea::Talker::from_header("$GPGGA"), parse_nmea::Talker::Gps);
+            assert_eq!(parse_nmea::Talker::from_header("$GNRMC"), parse_nmea::Talker::Multi);
+            assert_eq!(parse_nmea::Talker::from_header("$BDGSV"), parse_nmea::Talker::Beidou);
+            assert_eq!(parse_nmea::Talker::from_header("$PMTK001"), parse_nmea::Talker::Unknown);
+            assert_eq!(parse_nmea::Talker::from_header("$"), parse_nmea::Talker::Unknown);
+        }
+
         #[test]
         fn parse_degrees() {
             assert_eq!(parse_nmea::_parse_degrees("1020.12345", "N").unwrap(),
@@ -620,6 +572,13 @@ mod nmea_tests {
             );
         }
 
+        #[test]
+        fn gga_bad_fields() {
+            let gga = gga::parse_gga(vec!["$GPGGA", "x", "51x2.7314", "Q", "", "W", "1", "many"]);
+            assert_eq!((gga.utc, gga.lat, gga.long), (0.0, None, None));
+            assert_eq!((gga.sat_fix, gga.satellites_used, gga.hdop), (gga::SatFix::GpsFix, 0, None));
+        }
+
         #[test]
         #[should_panic]
         fn gga_incorrect_header() {
@@ -672,6 +631,19 @@ mod nmea_tests {
                 }
             )
         }
+        #[test]
+        fn used_satellites_change() {
+            let before = vec![
+                gsa::GsaData { sat1: Some(2), sat2: Some(5), sat3: Some(12), ..gsa::GsaData::default() },
+                gsa::GsaData { sat1: Some(70), ..gsa::GsaData::default() },
+            ];
+            let after = vec![gsa::GsaData { sat1: Some(12), sat2: Some(2), sat3: Some(31), ..gsa::GsaData::default() }];
+            assert_eq!(
+                gsa::compare_used_satellites(&before, &after),
+                gsa::UsedSatellitesChange { removed: vec![5, 70], added: vec![31], kept: vec![2, 12] }
+            );
+        }
+
         #[test]
         #[should_panic]
         fn gsa_incorrect_header() {
@@ -681,7 +653,33 @@ mod nmea_tests {
             ]);
         }
     }
-    mod gsv {}
+    mod gsv {
+        use crate::nmea::gsv;
+
+        #[test]
+        fn gsv_incorrect_header() {
+            assert_eq!(gsv::parse_gsv(vec!["$GNGGA", "131613.000", "5132.7314", "N"]), vec![]);
+            assert_eq!(gsv::parse_gsv(vec!["$G"]), vec![]);
+        }
+    }
     mod rmc {}
     mod vtg {}
+    mod zda {
+        use crate::nmea::zda;
+
+        #[test]
+        fn zda_normal() {
+            assert_eq!(
+                zda::parse_zda(vec!["$GPZDA", "172809.456", "12", "07", "1996", "00", "00"]),
+                zda::ZdaData {
+                    utc: 172809.456,
+                    day: Some(12),
+                    month: Some(7),
+                    year: Some(1996),
+                    local_zone_hours: Some(0),
+                    local_zone_minutes: Some(0),
+                }
+            )
+        }
+    }
 }
@@ -0,0 +1,861 @@
+//! # No_std core
+//! The parts of the crate that need neither std nor an allocator, for reading the gps from a
+//! microcontroller with the same parsing code as on a pi.
+//!
+//! - checksum: NMEA checksums.
+//! - fields: Splits a checked sentence into its fields, without a Vec.
+//! - sentences: Fixed size versions of the nmea structs and their parsers.
+//! - pmtk_encoder: Writes a PMTK command with its checksum into a byte buffer.
+//! - serial_reader: Reads sentences from an embedded-hal serial port. Needs the `embedded-hal`
+//!   feature.
+//!
+//! Build with `default-features = false` to leave out std, the serial port and everything else.
+//! ```ignore
+//! let mut buf = [0u8; 64];
+//! let cmd = pmtk_encoder::encode("PMTK220,1000", &mut buf)?; // b"$PMTK220,1000*1F\r\n"
+//! match sentences::parse(line) {
+//!     Some(Sentence::Gga(gga)) => ...,
+//!     _ => ...,
+//! }
+//! ```
+
+pub mod checksum {
+    //! NMEA checksums: the xor of every byte between the $ and the *.
+
+    /// The checksum of body, the sentence without the $ or the *XY.
+    pub fn checksum(body: &[u8]) -> u8 {
+        body.iter().fold(0, |sum, byte| sum ^ byte)
+    }
+
+    /// The sentence between the $ and the *, if line is $...*XY with the right checksum.
+    /// Whitespace around line, such as the \r\n, is ignored.
+    pub fn verified_body(line: &str) -> Option<&str> {
+        let line = line.trim();
+        let (body, given) = line.strip_prefix('$')?.rsplit_once('*')?;
+        if given.len() != 2 {
+            return None;
+        }
+        let given = u8::from_str_radix(given, 16).ok()?;
+        if given == checksum(body.as_bytes()) {
+            Some(body)
+        } else {
+            None
+        }
+    }
+
+    /// True if line is $...*XY with the right checksum.
+    pub fn is_valid(line: &str) -> bool {
+        verified_body(line).is_some()
+    }
+}
+
+pub mod fields {
+    //! Field tokeniser. Borrows from the line, so nothing is allocated.
+    use core::str::Split;
+
+    use super::checksum::verified_body;
+
+    /// A sentence with a valid checksum, split into its header and fields.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Fields<'a> {
+        header: &'a str,
+        fields: Option<&'a str>,
+    }
+
+    impl<'a> Fields<'a> {
+        /// None if line doesn't have a valid checksum.
+        pub fn parse(line: &'a str) -> Option<Fields<'a>> {
+            let body = verified_body(line)?;
+            Some(match body.split_once(',') {
+                Some((header, fields)) => Fields { header, fields: Some(fields) },
+                None => Fields { header: body, fields: None },
+            })
+        }
+
+        /// Fields from a sentence that has already been checked and split at its first comma, such
+        /// as by parse_nmea::parse_sentence. fields is None if there is nothing after the header.
+        pub fn from_parts(header: &'a str, fields: Option<&'a str>) -> Fields<'a> {
+            Fields { header: header.trim_start_matches('$'), fields }
+        }
+
+        /// The header without the $, such as GNGGA or PMTK001.
+        pub fn header(&self) -> &'a str {
+            self.header
+        }
+
+        /// The talker, such as GN. Empty for proprietary sentences, like PMTK.
+        pub fn talker(&self) -> &'a str {
+            if self.header.starts_with('P') || self.header.len() != 5 {
+                ""
+            } else {
+                self.header.get(..2).unwrap_or("")
+            }
+        }
+
+        /// The sentence type, such as GGA. The whole header for proprietary sentences.
+        pub fn kind(&self) -> &'a str {
+            &self.header[self.talker().len()..]
+        }
+
+        /// The fields after the header.
+        pub fn iter(&self) -> Split<'a, char> {
+            self.fields.unwrap_or("").split(',')
+        }
+
+        /// Field n after the header, counting from 0. Empty if the sentence is shorter.
+        pub fn get(&self, n: usize) -> &'a str {
+            self.fields.and_then(|fields| fields.split(',').nth(n)).unwrap_or("")
+        }
+
+        /// Number of fields after the header.
+        pub fn len(&self) -> usize {
+            self.fields.map_or(0, |fields| fields.split(',').count())
+        }
+
+        /// True if there is nothing after the header.
+        pub fn is_empty(&self) -> bool {
+            self.fields.is_none()
+        }
+    }
+}
+
+pub mod sentences {
+    //! Fixed size sentence structs. These hold the same values as the structs in the nmea modules
+    //! (and convert into them with the std feature) but never allocate, and the parsers give None
+    //! rather than panic on a bad field. The nmea parse_* functions and parse_line use these parsers.
+    use super::fields::Fields;
+
+    /// A parsed sentence.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum Sentence {
+        Gga(Gga),
+        Rmc(Rmc),
+        Gsa(Gsa),
+        Gsv(Gsv),
+        Vtg(Vtg),
+        Gll(Gll),
+        Zda(Zda),
+        /// A sentence with a valid checksum of any other type, such as PMTK001.
+        Other,
+    }
+
+    /// Parses a line. None if the checksum is wrong.
+    pub fn parse(line: &str) -> Option<Sentence> {
+        let fields = Fields::parse(line)?;
+        Some(match fields.kind() {
+            "GGA" => Sentence::Gga(parse_gga(&fields)),
+            "RMC" => Sentence::Rmc(parse_rmc(&fields)),
+            "GSA" => Sentence::Gsa(parse_gsa(&fields)),
+            "GSV" => Sentence::Gsv(parse_gsv(&fields)),
+            "VTG" => Sentence::Vtg(parse_vtg(&fields)),
+            "GLL" => Sentence::Gll(parse_gll(&fields)),
+            "ZDA" => Sentence::Zda(parse_zda(&fields)),
+            _ => Sentence::Other,
+        })
+    }
+
+    /// Degrees from ddmm.mmmm or dddmm.mmmm and N/S/E/W, rounded to 6 decimal places.
+    pub fn parse_degrees(degrees: &str, compass_direction: &str) -> Option<f32> {
+        let split = degrees.find('.').unwrap_or(degrees.len());
+        let deg_len = if split == 4 { 2 } else { 3 };
+        let deg: f32 = degrees.get(..deg_len)?.parse().ok()?;
+        let minutes: f32 = degrees.get(deg_len..)?.parse().ok()?;
+        let r = round_6dp(deg + minutes / 60.0);
+        match compass_direction {
+            "N" | "E" => Some(r),
+            "S" | "W" => Some(-r),
+            _ => None,
+        }
+    }
+
+    fn round_6dp(value: f32) -> f32 {
+        let scaled = value as f64 * 1_000_000.0;
+        let rounded = if scaled < 0.0 { scaled - 0.5 } else { scaled + 0.5 } as i64;
+        (rounded as f64 / 1_000_000.0) as f32
+    }
+
+    /// GGA: UTC, position, fix quality and satellites used.
+    /// - fix_quality: 0 no fix, 1 gps fix, 2 dgps fix.
+    #[derive(Debug, PartialEq, Default, Clone, Copy)]
+    pub struct Gga {
+        pub utc: f64,
+        pub lat: Option<f32>,
+        pub long: Option<f32>,
+        pub fix_quality: u8,
+        pub satellites_used: u8,
+        pub hdop: Option<f32>,
+        pub msl_alt: Option<f32>,
+        pub geoidal_sep: Option<f32>,
+        pub age_diff_corr: Option<f32>,
+    }
+
+    pub fn parse_gga(fields: &Fields) -> Gga {
+        Gga {
+            utc: fields.get(0).parse().unwrap_or(0.0),
+            lat: parse_degrees(fields.get(1), fields.get(2)),
+            long: parse_degrees(fields.get(3), fields.get(4)),
+            fix_quality: fields.get(5).parse().unwrap_or(0),
+            satellites_used: fields.get(6).parse().unwrap_or(0),
+            hdop: fields.get(7).parse().ok(),
+            msl_alt: fields.get(8).parse().ok(),
+            geoidal_sep: fields.get(10).parse().ok(),
+            age_diff_corr: fields.get(12).parse().ok(),
+        }
+    }
+
+    /// A ddmmyy date, with the two digit year.
+    #[derive(Debug, PartialEq, Default, Clone, Copy)]
+    pub struct Date {
+        pub day: u8,
+        pub month: u8,
+        pub year: u8,
+    }
+
+    /// RMC: UTC, position, speed in knots, course, date and magnetic variation (east positive).
+    #[derive(Debug, PartialEq, Default, Clone, Copy)]
+    pub struct Rmc {
+        pub utc: f64,
+        pub fix_status: bool,
+        pub latitude: Option<f32>,
+        pub longitude: Option<f32>,
+        pub speed: Option<f32>,
+        pub course: Option<f32>,
+        pub date: Option<Date>,
+        pub mag_var: Option<f32>,
+    }
+
+    pub fn parse_rmc(fields: &Fields) -> Rmc {
+        let date = fields.get(8);
+        let date = match (date.len(), date.get(0..2), date.get(2..4), date.get(4..6)) {
+            (6, Some(day), Some(month), Some(year)) => match (day.parse(), month.parse(), year.parse()) {
+                (Ok(day), Ok(month), Ok(year)) => Some(Date { day, month, year }),
+                _ => None,
+            },
+            _ => None,
+        };
+        let mag_var: Option<f32> = fields.get(9).parse().ok();
+        Rmc {
+            utc: fields.get(0).parse().unwrap_or(0.0),
+            fix_status: fields.get(1) == "A",
+            latitude: parse_degrees(fields.get(2), fields.get(3)),
+            longitude: parse_degrees(fields.get(4), fields.get(5)),
+            speed: fields.get(6).parse().ok(),
+            course: fields.get(7).parse().ok(),
+            date,
+            mag_var: match fields.get(10) {
+                "E" => mag_var,
+                "W" => mag_var.map(|var| -var),
+                _ => None,
+            },
+        }
+    }
+
+    /// GSA: the satellites used and the DOPs.
+    /// - mode: 'M' manual or 'A' automatic.
+    /// - dimension_fix: 1 no fix, 2 2D, 3 3D.
+    #[derive(Debug, PartialEq, Default, Clone, Copy)]
+    pub struct Gsa {
+        pub mode: char,
+        pub dimension_fix: u8,
+        pub satellites: [Option<u16>; 12],
+        pub pdop: Option<f32>,
+        pub hdop: Option<f32>,
+        pub vdop: Option<f32>,
+    }
+
+    pub fn parse_gsa(fields: &Fields) -> Gsa {
+        let mut satellites = [None; 12];
+        for (n, sat) in satellites.iter_mut().enumerate() {
+            *sat = fields.get(n + 2).parse().ok();
+        }
+        Gsa {
+            mode: fields.get(0).chars().next().unwrap_or('M'),
+            dimension_fix: fields.get(1).parse().unwrap_or(1),
+            satellites,
+            pdop: fields.get(14).parse().ok(),
+            hdop: fields.get(15).parse().ok(),
+            vdop: fields.get(16).parse().ok(),
+        }
+    }
+
+    /// One satellite in view.
+    #[derive(Debug, PartialEq, Default, Clone, Copy)]
+    pub struct Satellite {
+        pub id: Option<u16>,
+        pub elevation: Option<f32>,
+        pub azimuth: Option<f32>,
+        pub snr: Option<f32>,
+    }
+
+    /// One GSV sentence: message number of messages, and up to 4 of the satellites in view.
+    #[derive(Debug, PartialEq, Default, Clone, Copy)]
+    pub struct Gsv {
+        pub messages: u8,
+        pub number: u8,
+        pub in_view: u8,
+        pub satellites: [Option<Satellite>; 4],
+    }
+
+    pub fn parse_gsv(fields: &Fields) -> Gsv {
+        let mut satellites = [None; 4];
+        for (n, sat) in satellites.iter_mut().enumerate() {
+            let first = 3 + n * 4;
+            if fields.len() > first {
+                *sat = Some(Satellite {
+                    id: fields.get(first).parse().ok(),
+                    elevation: fields.get(first + 1).parse().ok(),
+                    azimuth: fields.get(first + 2).parse().ok(),
+                    snr: fields.get(first + 3).parse().ok(),
+                });
+            }
+        }
+        Gsv {
+            messages: fields.get(0).parse().unwrap_or(0),
+            number: fields.get(1).parse().unwrap_or(0),
+            in_view: fields.get(2).parse().unwrap_or(0),
+            satellites,
+        }
+    }
+
+    /// VTG: courses and speed.
+    /// - mode: 'A' autonomous, 'D' differential, 'E' estimated. None if not given.
+    #[derive(Debug, PartialEq, Default, Clone, Copy)]
+    pub struct Vtg {
+        pub true_course: Option<f32>,
+        pub magnetic_course: Option<f32>,
+        pub speed_knots: Option<f32>,
+        pub speed_kph: Option<f32>,
+        pub mode: Option<char>,
+    }
+
+    pub fn parse_vtg(fields: &Fields) -> Vtg {
+        Vtg {
+            true_course: fields.get(0).parse().ok(),
+            magnetic_course: fields.get(2).parse().ok(),
+            speed_knots: fields.get(4).parse().ok(),
+            speed_kph: fields.get(6).parse().ok(),
+            mode: fields.get(8).chars().next(),
+        }
+    }
+
+    /// GLL: position only.
+    #[derive(Debug, PartialEq, Default, Clone, Copy)]
+    pub struct Gll {
+        pub latitude: Option<f32>,
+        pub longitude: Option<f32>,
+        pub utc: Option<f64>,
+        pub is_valid: bool,
+    }
+
+    pub fn parse_gll(fields: &Fields) -> Gll {
+        Gll {
+            latitude: parse_degrees(fields.get(0), fields.get(1)),
+            longitude: parse_degrees(fields.get(2), fields.get(3)),
+            utc: fields.get(4).parse().ok(),
+            is_valid: fields.get(5) == "A",
+        }
+    }
+
+    /// ZDA: UTC and the full date.
+    #[derive(Debug, PartialEq, Default, Clone, Copy)]
+    pub struct Zda {
+        pub utc: f64,
+        pub day: Option<u8>,
+        pub month: Option<u8>,
+        pub year: Option<u16>,
+        pub local_zone_hours: Option<i8>,
+        pub local_zone_minutes: Option<u8>,
+    }
+
+    pub fn parse_zda(fields: &Fields) -> Zda {
+        Zda {
+            utc: fields.get(0).parse().unwrap_or(0.0),
+            day: fields.get(1).parse().ok(),
+            month: fields.get(2).parse().ok(),
+            year: fields.get(3).parse().ok(),
+            local_zone_hours: fields.get(4).parse().ok(),
+            local_zone_minutes: fields.get(5).parse().ok(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    mod to_std {
+        //! Conversions into the nmea structs.
+        use super::*;
+        use crate::nmea::{gga, gll, gsa, gsv, rmc, vtg, zda};
+
+        impl From<Gga> for gga::GgaData {
+            fn from(gga: Gga) -> Self {
+                gga::GgaData {
+                    utc: gga.utc,
+                    lat: gga.lat,
+                    long: gga.long,
+                    sat_fix: match gga.fix_quality {
+                        1 => gga::SatFix::GpsFix,
+                        2 => gga::SatFix::DgpsFix,
+                        _ => gga::SatFix::NoFix,
+                    },
+                    satellites_used: gga.satellites_used as i32,
+                    hdop: gga.hdop,
+                    msl_alt: gga.msl_alt,
+                    geoidal_sep: gga.geoidal_sep,
+                    age_diff_corr: gga.age_diff_corr,
+                }
+            }
+        }
+
+        impl From<Rmc> for rmc::RmcData {
+            fn from(rmc: Rmc) -> Self {
+                rmc::RmcData {
+                    utc: rmc.utc,
+                    fix_status: rmc.fix_status,
+                    latitude: rmc.latitude,
+                    longitude: rmc.longitude,
+                    speed: rmc.speed,
+                    course: rmc.course,
+                    date: rmc.date.map_or(String::new(), |d| format!("{:02}{:02}{:02}", d.day, d.month, d.year)),
+                    mag_var: rmc.mag_var,
+                }
+            }
+        }
+
+        impl From<Gsa> for gsa::GsaData {
+            fn from(gsa: Gsa) -> Self {
+                let sat = |n: usize| gsa.satellites[n].map(i32::from);
+                gsa::GsaData {
+                    mode: if gsa.mode == 'A' { gsa::Mode::Automatic } else { gsa::Mode::Manual },
+                    dimension_fix: match gsa.dimension_fix {
+                        2 => gsa::DimensionFix::Dimension2d,
+                        3 => gsa::DimensionFix::Dimension3d,
+                        _ => gsa::DimensionFix::NotAvailable,
+                    },
+                    sat1: sat(0),
+                    sat2: sat(1),
+                    sat3: sat(2),
+                    sat4: sat(3),
+                    sat5: sat(4),
+                    sat6: sat(5),
+                    sat7: sat(6),
+                    sat8: sat(7),
+                    sat9: sat(8),
+                    sat10: sat(9),
+                    sat11: sat(10),
+                    sat12: sat(11),
+                    pdop: gsa.pdop,
+                    hdop: gsa.hdop,
+                    vdop: gsa.vdop,
+                }
+            }
+        }
+
+        impl From<Satellite> for gsv::Satellites {
+            fn from(sat: Satellite) -> Self {
+                gsv::Satellites {
+                    id: sat.id.map(i32::from),
+                    elevation: sat.elevation,
+                    azimuth: sat.azimuth,
+                    snr: sat.snr,
+                }
+            }
+        }
+
+        impl From<Gsv> for Vec<gsv::Satellites> {
+            fn from(gsv: Gsv) -> Self {
+                gsv.satellites.iter().flatten().map(|sat| gsv::Satellites::from(*sat)).collect()
+            }
+        }
+
+        impl From<Vtg> for vtg::VtgData {
+            fn from(vtg: Vtg) -> Self {
+                vtg::VtgData {
+                    true_course: vtg.true_course,
+                    magnetic_course: vtg.magnetic_course,
+                    speed_knots: vtg.speed_knots,
+                    speed_kph: vtg.speed_kph,
+                    mode: match vtg.mode {
+                        Some('A') => vtg::Mode::Autonomous,
+                        Some('D') => vtg::Mode::Differential,
+                        Some('E') => vtg::Mode::Estimated,
+                        _ => vtg::Mode::Unknown,
+                    },
+                }
+            }
+        }
+
+        impl From<Gll> for gll::GllData {
+            fn from(gll: Gll) -> Self {
+                gll::GllData {
+                    latitude: gll.latitude,
+                    longitude: gll.longitude,
+                    utc: gll.utc,
+                    is_valid: gll.is_valid,
+                }
+            }
+        }
+
+        impl From<Zda> for zda::ZdaData {
+            fn from(zda: Zda) -> Self {
+                zda::ZdaData {
+                    utc: zda.utc,
+                    day: zda.day,
+                    month: zda.month,
+                    year: zda.year,
+                    local_zone_hours: zda.local_zone_hours,
+                    local_zone_minutes: zda.local_zone_minutes,
+                }
+            }
+        }
+    }
+}
+
+pub mod pmtk_encoder {
+    //! Writes $command*XY\r\n into a byte buffer, ready to write to the gps.
+    use core::fmt::{self, Write};
+
+    use super::checksum::checksum;
+
+    /// The buffer was too small for the command.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub struct BufferTooSmall;
+
+    /// Longest command the MTK chips take, with the $, checksum and \r\n.
+    pub const MAX_COMMAND_LEN: usize = 255;
+
+    struct SliceWriter<'b> {
+        buf: &'b mut [u8],
+        len: usize,
+    }
+
+    impl Write for SliceWriter<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.buf.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    /// Encodes command, without the $ or checksum, such as "PMTK220,1000".
+    pub fn encode<'b>(command: &str, buf: &'b mut [u8]) -> Result<&'b [u8], BufferTooSmall> {
+        encode_fmt(format_args!("{}", command), buf)
+    }
+
+    /// Encodes a formatted command, such as format_args!("PMTK220,{}", rate), without a String.
+    pub fn encode_fmt<'b>(command: fmt::Arguments, buf: &'b mut [u8]) -> Result<&'b [u8], BufferTooSmall> {
+        let mut writer = SliceWriter { buf, len: 0 };
+        writer.write_char('$').map_err(|_| BufferTooSmall)?;
+        writer.write_fmt(command).map_err(|_| BufferTooSmall)?;
+        let body_end = writer.len;
+        let sum = checksum(&writer.buf[1..body_end]);
+        write!(writer, "*{:02X}\r\n", sum).map_err(|_| BufferTooSmall)?;
+        let len = writer.len;
+        Ok(&writer.buf[..len])
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+pub mod serial_reader {
+    //! Reads sentences from an embedded-hal 0.2 serial port, one byte at a time, into a fixed
+    //! buffer of N bytes.
+    //! ```ignore
+    //! let mut gps: NmeaReader<_, 128> = NmeaReader::new(uart_rx);
+    //! loop {
+    //!     match gps.read() {
+    //!         Ok(Sentence::Rmc(rmc)) => ...,
+    //!         Ok(_) => {},
+    //!         Err(nb::Error::WouldBlock) => {}, // Do something else until more bytes arrive.
+    //!         Err(nb::Error::Other(e)) => ...,
+    //!     }
+    //! }
+    //! ```
+    use embedded_hal::serial::{Read, Write};
+
+    use super::pmtk_encoder::{encode, BufferTooSmall, MAX_COMMAND_LEN};
+    use super::sentences::{parse, Sentence};
+
+    /// Why a line was not given as a sentence.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum ReadError<E> {
+        /// The serial port gave an error.
+        Serial(E),
+        /// The line was longer than the buffer, and was dropped.
+        Overflow,
+        /// The line was not utf8, or had a bad checksum.
+        InvalidSentence,
+    }
+
+    /// Gathers bytes from serial until a full line arrives. A $ starts a new line, so a line cut
+    /// short by noise is dropped rather than joined to the next.
+    pub struct NmeaReader<S, const N: usize> {
+        serial: S,
+        buf: [u8; N],
+        len: usize,
+        line_len: usize,
+        overflowed: bool,
+    }
+
+    impl<S: Read<u8>, const N: usize> NmeaReader<S, N> {
+        pub fn new(serial: S) -> Self {
+            NmeaReader { serial, buf: [0; N], len: 0, line_len: 0, overflowed: false }
+        }
+
+        /// Gives back the serial port.
+        pub fn release(self) -> S {
+            self.serial
+        }
+
+        /// The last full line, without the \r\n. Kept until the next call to read.
+        pub fn line(&self) -> &str {
+            core::str::from_utf8(&self.buf[..self.line_len]).unwrap_or("")
+        }
+
+        /// Reads the bytes waiting on serial. A sentence once a full line has arrived,
+        /// WouldBlock until then.
+        pub fn read(&mut self) -> nb::Result<Sentence, ReadError<S::Error>> {
+            if self.line_len > 0 {
+                self.line_len = 0;
+                self.len = 0;
+            }
+            loop {
+                let byte = self.serial.read().map_err(|e| e.map(ReadError::Serial))?;
+                match byte {
+                    b'$' => {
+                        self.len = 0;
+                        self.overflowed = false;
+                        self.push(byte);
+                    }
+                    b'\n' => {
+                        if self.overflowed {
+                            self.overflowed = false;
+                            self.len = 0;
+                            return Err(nb::Error::Other(ReadError::Overflow));
+                        }
+                        if self.len == 0 {
+                            continue;
+                        }
+                        let line = &self.buf[..self.len];
+                        let line = line.strip_suffix(b"\r").unwrap_or(line);
+                        let sentence = core::str::from_utf8(line).ok().and_then(parse);
+                        match sentence {
+                            Some(sentence) => {
+                                self.line_len = line.len();
+                                return Ok(sentence);
+                            }
+                            None => {
+                                self.len = 0;
+                                return Err(nb::Error::Other(ReadError::InvalidSentence));
+                            }
+                        }
+                    }
+                    _ => self.push(byte),
+                }
+            }
+        }
+
+        fn push(&mut self, byte: u8) {
+            if self.len < N {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            } else {
+                self.overflowed = true;
+            }
+        }
+    }
+
+    /// Why a command wasn't sent.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum SendError<E> {
+        Serial(E),
+        TooLong(BufferTooSmall),
+    }
+
+    /// Writes command, such as "PMTK220,1000", with its checksum to serial, blocking until sent.
+    pub fn send_command<W: Write<u8>>(serial: &mut W, command: &str) -> Result<(), SendError<W::Error>> {
+        let mut buf = [0u8; MAX_COMMAND_LEN];
+        let bytes = encode(command, &mut buf).map_err(SendError::TooLong)?;
+        for byte in bytes {
+            nb::block!(serial.write(*byte)).map_err(SendError::Serial)?;
+        }
+        nb::block!(serial.flush()).map_err(SendError::Serial)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod nmea_core_test {
+    use super::checksum::{checksum, is_valid, verified_body};
+    use super::fields::Fields;
+    use super::pmtk_encoder::{encode, encode_fmt, BufferTooSmall};
+    use super::sentences::{parse, Sentence};
+    use crate::nmea::{gga, gll, gsa, gsv, rmc, vtg, zda};
+    use crate::nmea::parse_nmea::parse_sentence;
+
+    const GGA: &str = "$GNGGA,131613.000,5132.7314,N,00005.9099,W,1,9,1.17,42.4,M,47.0,M,,*60\r\n";
+    const GSA: &str = "$GPGSA,A,3,29,02,26,25,31,14,,,,,,,1.42,1.17,0.80*07\r\n";
+    const GSV: &str = "$GPGSV,4,3,14,12,12,100,,04,11,331,,16,06,282,,05,05,074,22*75";
+    const VTG: &str = "$GNVTG,0.00,T,,M,0.00,N,0.00,K,A*23\r\n";
+
+    fn with_checksum(body: &str) -> String {
+        format!("${}*{:02X}\r\n", body, checksum(body.as_bytes()))
+    }
+
+    #[test]
+    fn checksums() {
+        assert!(is_valid(GGA));
+        assert!(is_valid(GSA));
+        assert!(!is_valid("$GPGSA,A,3,29,02,26,25,31,14,,,,,,,1.42,1.17,0.80*A7\r\n"));
+        assert!(!is_valid("$"));
+        assert!(!is_valid("*00"));
+        assert!(!is_valid(""));
+        assert_eq!(verified_body("$PMTK220,100*2F"), Some("PMTK220,100"));
+    }
+
+    #[test]
+    fn tokeniser() {
+        let fields = Fields::parse(VTG).unwrap();
+        assert_eq!(fields.header(), "GNVTG");
+        assert_eq!(fields.talker(), "GN");
+        assert_eq!(fields.kind(), "VTG");
+        assert_eq!(fields.len(), 9);
+        assert_eq!(fields.get(0), "0.00");
+        assert_eq!(fields.get(2), "");
+        assert_eq!(fields.get(3), "M");
+        assert_eq!(fields.get(20), "");
+        assert_eq!(fields.iter().last(), Some("A"));
+
+        let fields = Fields::parse("$PMTK001,220,3*30").unwrap();
+        assert_eq!(fields.kind(), "PMTK001");
+        assert_eq!(fields.talker(), "");
+        assert!(Fields::parse(&with_checksum("PMTK000")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sentences_match_the_std_parsers() {
+        let args = || parse_sentence(GGA).unwrap();
+        match parse(GGA).unwrap() {
+            Sentence::Gga(core) => assert_eq!(gga::GgaData::from(core), gga::parse_gga(args())),
+            other => panic!("{:?}", other),
+        }
+        match parse(GSA).unwrap() {
+            Sentence::Gsa(core) => assert_eq!(gsa::GsaData::from(core), gsa::parse_gsa(parse_sentence(GSA).unwrap())),
+            other => panic!("{:?}", other),
+        }
+        match parse(GSV).unwrap() {
+            Sentence::Gsv(core) => {
+                assert_eq!((core.messages, core.number, core.in_view), (4, 3, 14));
+                assert_eq!(Vec::<gsv::Satellites>::from(core), gsv::parse_gsv(parse_sentence(GSV).unwrap()));
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse(VTG).unwrap() {
+            Sentence::Vtg(core) => assert_eq!(vtg::VtgData::from(core), vtg::parse_vtg(parse_sentence(VTG).unwrap())),
+            other => panic!("{:?}", other),
+        }
+
+        let rmc_line = with_checksum("GNRMC,110942.000,A,5132.7394,N,00005.9165,W,0.34,280.60,200520,,,A");
+        match parse(&rmc_line).unwrap() {
+            Sentence::Rmc(core) => {
+                assert_eq!(core.date.unwrap().month, 5);
+                assert_eq!(rmc::RmcData::from(core), rmc::parse_rmc(parse_sentence(&rmc_line).unwrap()));
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse(&with_checksum("GNRMC,110942.000,A,5132.7394,N,00005.9165,W,0.34,280.60,200520,3.1,W,A")) {
+            Some(Sentence::Rmc(core)) => assert_eq!(core.mag_var, Some(-3.1)),
+            other => panic!("{:?}", other),
+        }
+        let gll_line = with_checksum("GNGLL,5132.7394,N,00005.9165,W,110942.000,A,A");
+        match parse(&gll_line).unwrap() {
+            Sentence::Gll(core) => assert_eq!(gll::GllData::from(core), gll::parse_gll(parse_sentence(&gll_line).unwrap())),
+            other => panic!("{:?}", other),
+        }
+        let zda_line = with_checksum("GNZDA,110942.000,20,05,2020,,");
+        match parse(&zda_line).unwrap() {
+            Sentence::Zda(core) => assert_eq!(zda::ZdaData::from(core), zda::parse_zda(parse_sentence(&zda_line).unwrap())),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_fields_do_not_panic() {
+        let line = with_checksum("GPGGA,x,51x2.7314,Q,,W,9,many,,,,,,");
+        match parse(&line).unwrap() {
+            Sentence::Gga(gga) => {
+                assert_eq!(gga.lat, None);
+                assert_eq!(gga.long, None);
+                assert_eq!(gga.satellites_used, 0);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse(&with_checksum("PMTK001,220,3")), Some(Sentence::Other));
+        assert_eq!(parse("$GPGGA,1*00"), None);
+        // Non-ASCII bytes in the date or the header.
+        match parse(&with_checksum("GPRMC,131613.000,A,,,,,,,1é234,,")).unwrap() {
+            Sentence::Rmc(rmc) => assert_eq!(rmc.date, None),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(parse(&with_checksum("Géxy,1")), Some(Sentence::Other));
+    }
+
+    #[test]
+    fn pmtk_encoder() {
+        let mut buf = [0u8; 32];
+        assert_eq!(encode("PMTK220,1000", &mut buf), Ok(&b"$PMTK220,1000*1F\r\n"[..]));
+        let rate = 200;
+        assert_eq!(encode_fmt(format_args!("PMTK220,{}", rate), &mut buf), Ok(&b"$PMTK220,200*2C\r\n"[..]));
+        // Checksums below 0x10 keep both digits.
+        let cmd = encode("PMTK001,220,3", &mut buf).unwrap();
+        assert!(cmd.ends_with(b"*30\r\n"));
+        let mut small = [0u8; 10];
+        assert_eq!(encode("PMTK220,1000", &mut small), Err(BufferTooSmall));
+    }
+
+    #[cfg(feature = "embedded-hal")]
+    mod serial_reader {
+        use embedded_hal_mock::eh0::serial::{Mock, Transaction};
+        use embedded_hal_mock::eh0::MockError;
+        use std::io::ErrorKind;
+
+        use super::super::sentences::Sentence;
+        use super::super::serial_reader::{send_command, NmeaReader, ReadError};
+        use super::{GGA, VTG};
+
+        #[test]
+        fn reads_sentences_across_would_block() {
+            let (first, rest) = GGA.as_bytes().split_at(20);
+            let mut serial = Mock::new(&[
+                Transaction::read_many(b"ted,garbage\r\n"),
+                Transaction::read_many(first),
+                Transaction::read_error(nb::Error::WouldBlock),
+                Transaction::read_many(rest),
+                Transaction::read_many(VTG.as_bytes()),
+            ]);
+            let mut reader: NmeaReader<_, 96> = NmeaReader::new(serial.clone());
+
+            assert_eq!(reader.read(), Err(nb::Error::Other(ReadError::InvalidSentence)));
+            assert_eq!(reader.read(), Err(nb::Error::WouldBlock));
+            assert!(matches!(reader.read(), Ok(Sentence::Gga(gga)) if gga.satellites_used == 9));
+            assert_eq!(reader.line(), GGA.trim_end());
+            assert!(matches!(reader.read(), Ok(Sentence::Vtg(_))));
+            serial.done();
+        }
+
+        #[test]
+        fn long_lines_and_errors() {
+            let mut serial = Mock::new(&[
+                Transaction::read_many(b"$GPGSV,1,1,00,0123456789012345678901234567890123456789\r\n"),
+                Transaction::read_error(nb::Error::Other(MockError::Io(ErrorKind::Other))),
+            ]);
+            let mut reader: NmeaReader<_, 16> = NmeaReader::new(serial.clone());
+            assert_eq!(reader.read(), Err(nb::Error::Other(ReadError::Overflow)));
+            assert!(matches!(reader.read(), Err(nb::Error::Other(ReadError::Serial(_)))));
+            serial.done();
+        }
+
+        #[test]
+        fn sends_commands() {
+            let mut serial = Mock::new(&[Transaction::write_many(b"$PMTK220,1000*1F\r\n"), Transaction::flush()]);
+            send_command(&mut serial, "PMTK220,1000").unwrap();
+            serial.done();
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! # NTP shared memory
+//! Serves the gps time to chrony or ntpd without gpsd, through the SHM refclock: a System V shared
+//! memory segment at key 0x4E545030 + unit that the time server polls.
+//!
+//! Each RMC with a fix and a date is written as a sample: the gps UTC and the host time the RMC
+//! arrived, from gps.update_timed(). The RMC arrives a while after the second it gives starts, so
+//! set the fudge to that delay, or leave it at 0 and set the offset in the time server instead.
+//!
+//! Units 0 and 1 can only be used by root, higher units by anyone. For chrony:
+//! ```text
+//! refclock SHM 2 refid GPS precision 1e-1 offset 0.0 delay 0.2
+//! ```
+//! ```ignore
+//! let mut shm = NtpShm::attach(2)?.with_fudge(0.120);
+//! loop {
+//!     shm.update(&gps.update_timed());
+//! }
+//! ```
+
+pub mod ntp_shm {
+    //! NtpShm: writing, and reading back, the SHM refclock segment.
+    use std::io;
+    use std::mem::size_of;
+    use std::os::raw::{c_int, c_uint};
+    use std::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+    use std::sync::atomic::{fence, Ordering};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::open_gps::gps::GpsSentence;
+    use crate::timed::timed_sentence::TimedSentence;
+    use crate::timing::utc::UtcDateTime;
+
+    /// Key of unit 0, "NTP0". Unit n is at NTPD_BASE + n.
+    pub const NTPD_BASE: i32 = 0x4E54_5030;
+
+    /// The segment, as laid out by ntpd's refclock_shm.c.
+    #[repr(C)]
+    struct ShmTime {
+        mode: c_int,
+        count: c_int,
+        clock_time_stamp_sec: libc::time_t,
+        clock_time_stamp_usec: c_int,
+        receive_time_stamp_sec: libc::time_t,
+        receive_time_stamp_usec: c_int,
+        leap: c_int,
+        precision: c_int,
+        nsamples: c_int,
+        valid: c_int,
+        clock_time_stamp_nsec: c_uint,
+        receive_time_stamp_nsec: c_uint,
+        dummy: [c_int; 8],
+    }
+
+    /// A sample in the segment.
+    /// - clock: The gps UTC.
+    /// - receive: Host time the gps UTC was received, less the fudge.
+    /// - leap: 0 no warning, 1 a leap second will be added, 2 taken away, 3 not synchronised.
+    /// - precision: log2 seconds.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub struct ShmSample {
+        pub clock: SystemTime,
+        pub receive: SystemTime,
+        pub leap: i32,
+        pub precision: i32,
+    }
+
+    /// A SHM refclock segment. See the [module docs](index.html).
+    ///
+    /// The segment is left in place when dropped, as the time server may still have it open.
+    pub struct NtpShm {
+        pub unit: u8,
+        /// Seconds taken off the receive time: how long after the second starts its RMC arrives.
+        pub fudge: f64,
+        /// log2 seconds. -4, about 60ms, is the jitter of NMEA timing.
+        pub precision: i32,
+        id: c_int,
+        segment: *mut ShmTime,
+    }
+
+    // The segment is shared memory made to be written from any process, so any thread.
+    unsafe impl Send for NtpShm {}
+
+    fn split(time: SystemTime) -> (libc::time_t, u32) {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        (since_epoch.as_secs() as libc::time_t, since_epoch.subsec_nanos())
+    }
+
+    fn join(seconds: libc::time_t, nanos: c_uint) -> SystemTime {
+        UNIX_EPOCH + Duration::new(seconds.max(0) as u64, nanos)
+    }
+
+    impl NtpShm {
+        /// Attaches to the segment of unit, creating it if the time server hasn't.
+        pub fn attach(unit: u8) -> io::Result<NtpShm> {
+            let permissions = if unit < 2 { 0o600 } else { 0o666 };
+            // Safe: shmget and shmat only map memory, and the size is that of the struct.
+            unsafe {
+                let id = libc::shmget(NTPD_BASE + unit as i32, size_of::<ShmTime>(), libc::IPC_CREAT | permissions);
+                if id == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                let segment = libc::shmat(id, std::ptr::null(), 0);
+                if segment as isize == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                let segment = segment as *mut ShmTime;
+                write_volatile(addr_of_mut!((*segment).mode), 1);
+                write_volatile(addr_of_mut!((*segment).nsamples), 3);
+                Ok(NtpShm { unit, fudge: 0.0, precision: -4, id, segment })
+            }
+        }
+
+        /// Takes fudge seconds off each receive time.
+        pub fn with_fudge(mut self, fudge: f64) -> NtpShm {
+            self.fudge = fudge;
+            self
+        }
+
+        /// Writes a sample with the mode 1 handshake: valid is cleared and count is odd while it
+        /// is being written, so a reader can tell a torn sample.
+        pub fn put(&mut self, clock: SystemTime, received: SystemTime) {
+            let fudge = Duration::from_secs_f64(self.fudge.abs());
+            let receive = if self.fudge >= 0.0 { received - fudge } else { received + fudge };
+            let (clock_sec, clock_nsec) = split(clock);
+            let (receive_sec, receive_nsec) = split(receive);
+            let segment = self.segment;
+            // Safe: the segment is mapped until drop, and is only written with volatile writes.
+            unsafe {
+                write_volatile(addr_of_mut!((*segment).valid), 0);
+                let count = read_volatile(addr_of!((*segment).count));
+                write_volatile(addr_of_mut!((*segment).count), count.wrapping_add(1));
+                fence(Ordering::SeqCst);
+                write_volatile(addr_of_mut!((*segment).mode), 1);
+                write_volatile(addr_of_mut!((*segment).clock_time_stamp_sec), clock_sec);
+                write_volatile(addr_of_mut!((*segment).clock_time_stamp_usec), (clock_nsec / 1000) as c_int);
+                write_volatile(addr_of_mut!((*segment).clock_time_stamp_nsec), clock_nsec);
+                write_volatile(addr_of_mut!((*segment).receive_time_stamp_sec), receive_sec);
+                write_volatile(addr_of_mut!((*segment).receive_time_stamp_usec), (receive_nsec / 1000) as c_int);
+                write_volatile(addr_of_mut!((*segment).receive_time_stamp_nsec), receive_nsec);
+                write_volatile(addr_of_mut!((*segment).leap), 0);
+                write_volatile(addr_of_mut!((*segment).precision), self.precision);
+                fence(Ordering::SeqCst);
+                write_volatile(addr_of_mut!((*segment).count), count.wrapping_add(2));
+                write_volatile(addr_of_mut!((*segment).valid), 1);
+            }
+        }
+
+        /// Writes a sample for an RMC with a fix and a date. False for anything else.
+        pub fn update(&mut self, timed: &TimedSentence) -> bool {
+            let utc = match &timed.sentence {
+                GpsSentence::RMC(rmc) if rmc.fix_status => UtcDateTime::from_rmc(rmc),
+                _ => None,
+            };
+            let unix = match utc {
+                Some(utc) => utc.to_unix(),
+                None => return false,
+            };
+            // RMC gives milliseconds, so round off the f64 error.
+            let micros = (unix * 1_000_000.0).round() as u64;
+            self.put(UNIX_EPOCH + Duration::from_micros(micros), timed.received);
+            true
+        }
+
+        /// Reads the sample as the time server does: None if there is no new sample or it was
+        /// being written, otherwise the sample, clearing valid so it is only read once.
+        pub fn read(&mut self) -> Option<ShmSample> {
+            let segment = self.segment;
+            // Safe: as in put.
+            unsafe {
+                let count = read_volatile(addr_of!((*segment).count));
+                fence(Ordering::SeqCst);
+                if read_volatile(addr_of!((*segment).valid)) == 0 {
+                    return None;
+                }
+                let sample = ShmSample {
+                    clock: join(read_volatile(addr_of!((*segment).clock_time_stamp_sec)),
+                                read_volatile(addr_of!((*segment).clock_time_stamp_nsec))),
+                    receive: join(read_volatile(addr_of!((*segment).receive_time_stamp_sec)),
+                                  read_volatile(addr_of!((*segment).receive_time_stamp_nsec))),
+                    leap: read_volatile(addr_of!((*segment).leap)),
+                    precision: read_volatile(addr_of!((*segment).precision)),
+                };
+                fence(Ordering::SeqCst);
+                if read_volatile(addr_of!((*segment).count)) != count {
+                    return None;
+                }
+                write_volatile(addr_of_mut!((*segment).valid), 0);
+                Some(sample)
+            }
+        }
+
+        /// Number of times the segment has been written to, twice per sample.
+        pub fn count(&self) -> i32 {
+            // Safe: as in put.
+            unsafe { read_volatile(addr_of!((*self.segment).count)) }
+        }
+
+        /// Detaches and marks the segment to be removed once every process has detached it.
+        pub fn remove(self) -> io::Result<()> {
+            // Safe: IPC_RMID takes no buffer.
+            if unsafe { libc::shmctl(self.id, libc::IPC_RMID, std::ptr::null_mut()) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for NtpShm {
+        fn drop(&mut self) {
+            // Safe: segment was given by shmat and is not used after this.
+            unsafe {
+                libc::shmdt(self.segment as *const libc::c_void);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod ntp_shm_test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::open_gps::mock_port::mock_gps;
+    use crate::pmtk::send_pmtk::add_checksum;
+
+    use super::ntp_shm::NtpShm;
+
+    /// Far from the units time servers are set up with.
+    const UNIT: u8 = 117;
+
+    #[test]
+    fn writes_rmc_time() {
+        let rmc = add_checksum("GNRMC,184000.250,A,5132.7394,N,00005.9165,W,0.00,0.00,200520,,,A".to_string());
+        let no_fix = add_checksum("GNRMC,184001.000,V,,,,,,,200520,,,N".to_string());
+        let (mut gps, _written) = mock_gps(&[rmc.as_str(), no_fix.as_str()]);
+        let mut writer = NtpShm::attach(UNIT).unwrap().with_fudge(0.1);
+        let mut reader = NtpShm::attach(UNIT).unwrap();
+        while reader.read().is_some() {}
+
+        let count = writer.count();
+        let timed = gps.update_timed();
+        assert!(writer.update(&timed));
+        assert_eq!(reader.count(), count.wrapping_add(2));
+        let sample = reader.read().unwrap();
+        assert_eq!(sample.clock, UNIX_EPOCH + Duration::from_millis(1_590_000_000_250));
+        assert_eq!(sample.receive, timed.received - Duration::from_millis(100));
+        assert_eq!((sample.leap, sample.precision), (0, -4));
+        assert_eq!(reader.read(), None);
+
+        assert!(!writer.update(&gps.update_timed()));
+        assert_eq!(reader.read(), None);
+        drop(reader);
+        writer.remove().unwrap();
+    }
+}
@@ -6,22 +6,30 @@
 pub mod gps {
     //! This is the main module around which all other modules interact.
     //! It contains the Gps structure, open port and GpsData that are central to using this module.
+    use std::collections::VecDeque;
     use std::fs::{File, OpenOptions};
-    use std::io::{Read, Write};
+    use std::io::{self, ErrorKind, Read, Write};
     use std::str;
-    use std::time::{Duration, SystemTime};
+    use std::time::{Duration, Instant, SystemTime};
 
-    use bincode::serialize;
+    use bincode::{serialize, Options};
     use serde::{Deserialize, Serialize};
     use serialport::prelude::*;
 
-    use crate::nmea::gga::{GgaData, parse_gga};
-    use crate::nmea::gll::{GllData, parse_gll};
-    use crate::nmea::gsa::{GsaData, parse_gsa};
-    use crate::nmea::gsv::{parse_gsv, Satellites};
-    use crate::nmea::parse_nmea::parse_sentence;
-    use crate::nmea::rmc::{parse_rmc, RmcData};
-    use crate::nmea::vtg::{parse_vtg, VtgData};
+    use crate::config::device_info::DeviceInfo;
+    use crate::framing::line_framer::LineFramer;
+    use crate::timed::timed_sentence::{monotonic_start, Receipt, TimedSentence};
+    use crate::nmea::gga::GgaData;
+    use crate::nmea::gll::GllData;
+    use crate::nmea::gsa::GsaData;
+    use crate::nmea::gsv::Satellites;
+    use crate::nmea::parse_nmea::{parse_sentence, Talker};
+    use crate::nmea::rmc::RmcData;
+    use crate::nmea::vtg::VtgData;
+    use crate::nmea::zda::ZdaData;
+    use crate::nmea_core::sentences::{self, Sentence};
+    use crate::pmtk::send_pmtk::{Chip, CommandPolicy, PmtkAck, PowerMode};
+    use crate::trace::wire_trace::{Direction, TraceSink};
 
     /// Opens the port to the GPS, probably /dev/serial0
         /// Default baud rate is 9600
@@ -41,36 +49,12 @@ pub mod gps {
     /// the given checksum. Returns true for valid sentence, false for invalid.
     /// The format of the sentence should be $sentence*checksum
     pub fn is_valid_checksum(s: &str) -> bool {
-        let s = s.trim();
-        // String should be: $..., *XY
-
-        let star = &s[s.len() - 3..s.len() - 2];
-        let checksum = &s[s.len() - 2..s.len()];
-        let body = &s[0..s.len() - 3];
-
-        if star != "*" {
-            // Check third last item is a *
-            return false;
-        }
-
-        match u8::from_str_radix(&checksum, 16) {
-            // Convert to base 16.
-            Ok(expected_checksum) => {
-                let mut actual: u8 = 0;
-                for i in body[1..].as_bytes() {
-                    // Skip $ sign. bitwise xor for each i in body
-                    actual ^= *i;
-                }
-                if actual == expected_checksum {
-                    return true;
-                } else {
-                    return false;
-                }
-            }
-            Err(_e) => return false,
-        }
+        crate::nmea_core::checksum::is_valid(s)
     }
 
+    /// Bytes read from the port at a time: a second of 9600 baud.
+    const READ_CHUNK: usize = 1024;
+
     /// Enum for if the port connection to the gps is valid, gave invalid bytes, or is not connected
     #[derive(PartialEq, Debug)]
     pub enum PortConnection {
@@ -91,6 +75,69 @@ pub mod gps {
         NoConnection,
         InvalidBytes,
         InvalidSentence,
+        // New sentence types go after InvalidSentence so files written by append_to stay readable.
+        ZDA(ZdaData),
+        Sleeping,
+        DeviceInfo(DeviceInfo),
+        Timed(Box<TimedSentence>),
+    }
+
+    /// What power state the gps was last put in, see pmtk_161_cmd_standby_mode and
+    /// pmtk_225_cmd_periodic_mode.
+    /// - Running -> Normal output. Default.
+    /// - Standby -> No output until woken with gps.wake().
+    /// - Periodic(PowerMode) -> Output only while running to get a fix, silent while asleep.
+    /// - Backup -> No output until woken by the FORCE_ON pin.
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum PowerState {
+        Running,
+        Standby,
+        Periodic(PowerMode),
+        Backup,
+    }
+
+    /// False if the line is a GGA, RMC, VTG or GLL from a talker other than expected. None expects any.
+    pub(crate) fn is_expected_talker(expected: Option<Talker>, line: &str) -> bool {
+        let expected = match expected {
+            Some(talker) => talker,
+            None => return true,
+        };
+        let header = line.trim().split(',').next().unwrap_or("");
+        match header.get(3..6) {
+            Some("GGA") | Some("RMC") | Some("VTG") | Some("GLL") => Talker::from_header(header) == expected,
+            _ => true,
+        }
+    }
+
+    /// Parses a single line given by the gps into a GpsSentence.
+    ///
+    /// GSV sentences only give the satellites in that one message, use gps.update() to get all of
+    /// the messages in a GSV sequence.
+    pub fn parse_line(line: &str) -> GpsSentence {
+        match sentences::parse(line) {
+            Some(Sentence::Gga(gga)) => GpsSentence::GGA(gga.into()),
+            Some(Sentence::Vtg(vtg)) => GpsSentence::VTG(vtg.into()),
+            Some(Sentence::Gsa(gsa)) => GpsSentence::GSA(gsa.into()),
+            Some(Sentence::Gll(gll)) => GpsSentence::GLL(gll.into()),
+            Some(Sentence::Rmc(rmc)) => GpsSentence::RMC(rmc.into()),
+            Some(Sentence::Gsv(gsv)) => GpsSentence::GSV(gsv.into()),
+            Some(Sentence::Zda(zda)) => GpsSentence::ZDA(zda.into()),
+            Some(Sentence::Other) | None => GpsSentence::InvalidSentence,
+        }
+    }
+
+    /// Most replies kept in gps.stray_acks and gps.stray_replies.
+    pub const MAX_STRAY: usize = 32;
+
+    /// Most sentences kept in gps.pending: over 10 seconds of every sentence at 10 Hz.
+    pub const MAX_PENDING: usize = 1024;
+
+    /// Pushes to the back of the queue, dropping the oldest if it already has max in it.
+    pub(crate) fn push_capped<T>(queue: &mut VecDeque<T>, item: T, max: usize) {
+        if queue.len() >= max {
+            queue.pop_front();
+        }
+        queue.push_back(item);
     }
 
     /// This is the main struct around which all commands are centered. It allows for communication
@@ -98,100 +145,242 @@ pub mod gps {
     ///
     /// Satellite data: true if you want the individual satellite data
     /// Navigation data: true if you want the navigation data (lat, long, etc)
+    ///
+    /// talker: The talker expected for GGA, RMC, VTG and GLL sentences. Any other talker gives
+    /// InvalidSentence. None (default) accepts any talker. Set by the GNSS search mode commands.
+    ///
+    /// power_state: Tracked by the power saving commands, see [PowerState](enum.PowerState.html).
+    ///
+    /// stray_acks: PMTK001 replies read while waiting for the reply to a different command. Keeps
+    /// the last MAX_STRAY.
+    ///
+    /// stray_replies: PMTK500 style replies read while waiting for the reply to a different query.
+    /// Keeps the last MAX_STRAY.
+    ///
+    /// command_policy: How long to wait for command replies and how often to resend, see
+    /// [CommandPolicy](crate::CommandPolicy).
+    ///
+    /// last_attempt: Which send of the last command got a reply, 0 if none did.
+    ///
+    /// chip: The chip the gps is built on, set by gps.detect_chip(). Commands the chip doesn't support
+    /// are not sent. None (default) sends everything.
+    ///
+    /// pending: Sentences read while waiting for a command reply. update() gives these first, so
+    /// sending commands doesn't lose any data. Keeps the last MAX_PENDING, so commands sent without
+    /// calling update() in between drop the oldest.
+    ///
+    /// trace: Given every command sent and line read, see gps.set_trace(). None (default) traces
+    /// nothing.
+    ///
+    /// framer: Cuts what is read from the port into lines and keeps the bytes after the last full
+    /// line. framer.stats() counts the garbage dropped.
+    ///
+    /// port_error: The error the port gave on the last read, other than timing out. Set when the
+    /// device has gone away, such as a USB gps being unplugged.
     pub struct Gps {
         pub port: Box<dyn SerialPort>,
+        pub talker: Option<Talker>,
+        pub power_state: PowerState,
+        pub stray_acks: VecDeque<PmtkAck>,
+        pub stray_replies: VecDeque<String>,
+        pub command_policy: CommandPolicy,
+        pub last_attempt: u32,
+        pub pending: VecDeque<String>,
+        pub chip: Option<Chip>,
+        pub trace: Option<Box<dyn TraceSink>>,
+        pub framer: LineFramer,
+        pub port_error: Option<ErrorKind>,
+        /// When the line just read arrived, see gps.update_timed().
+        pub(crate) line_receipt: Option<Receipt>,
+        /// When each line in pending arrived.
+        pub(crate) pending_receipts: VecDeque<Receipt>,
+        pub(crate) update_receipt: Option<Receipt>,
     }
 
     impl Gps {
+        /// Opens port. An InvalidInput error if baud_rate isn't a number.
         pub fn new(port: &str, baud_rate: &str) -> serialport::Result<Gps> {
-            Ok(Gps { port: open_port(port, baud_rate.parse().unwrap())? })
+            let baud_rate = baud_rate.parse().map_err(|_| {
+                serialport::Error::new(serialport::ErrorKind::InvalidInput, format!("Invalid baud rate: {}", baud_rate))
+            })?;
+            Ok(Gps::from_port(open_port(port, baud_rate)?))
         }
 
-        /// Reads a full sentence from the serial buffer, returns a String.
-        /// "Invalid bytes given" when there are no bytes given.
+        /// Gps from an already open port.
+        pub fn from_port(port: Box<dyn SerialPort>) -> Gps {
+            monotonic_start();
+            Gps {
+                port,
+                talker: None,
+                power_state: PowerState::Running,
+                stray_acks: VecDeque::new(),
+                stray_replies: VecDeque::new(),
+                command_policy: CommandPolicy::default(),
+                last_attempt: 0,
+                pending: VecDeque::new(),
+                chip: None,
+                trace: None,
+                framer: LineFramer::new(),
+                port_error: None,
+                line_receipt: None,
+                pending_receipts: VecDeque::new(),
+                update_receipt: None,
+            }
+        }
+
+        /// False if the line is a GGA, RMC, VTG or GLL from a talker other than the expected one.
+        fn is_expected_talker(&self, line: &str) -> bool {
+            is_expected_talker(self.talker, line)
+        }
+
+        /// Reads a full line from the port, with gps.framer. See the [framing docs](../../framing/index.html).
+        ///
+        /// NoConnection once no bytes have arrived for a second. InvalidBytes, with what was
+        /// dropped, if bytes arrived for a second without a full line, or for a line that isn't
+        /// utf8.
         pub fn read_line(&mut self) -> PortConnection {
-            // Maximum port buffer size is 4095.
-            // Returns whatever is in the port.
-            // Start of a line is $ (36) and end is \n (10).
-            // The serial buffer reads from bottom to top. New data is added to the top. The amount read
-            // from the serial buffer is the size of the buffer vec.
-
-            // 127 is the maximum valid utf8 number.
-            let mut buffer: Vec<u8> = vec![0; 1]; // Reads what is in the buffer, be it nothing or max.
-            let mut output: Vec<u8> = Vec::new();
-            let p = &mut self.port;
-            let mut cont = true;
-            let start = SystemTime::now();
-            while cont {
-                // If there is no connection, this match statement is looped over with a 1 second time out
-                // as given by the port open.
-                if start.elapsed().unwrap() > Duration::from_secs(1) {
+            let start = Instant::now();
+            let mut buffer = [0u8; READ_CHUNK];
+            self.framer.take_garbage();
+            self.line_receipt = None;
+            loop {
+                if let Some(line) = self.framer.next_line() {
+                    self.set_line_receipt(line.as_ref().map_or_else(|bytes| bytes.len(), |line| line.len()));
+                    if self.trace.is_some() {
+                        let traced = match &line {
+                            Ok(line) => line.clone(),
+                            Err(bytes) => String::from_utf8_lossy(bytes).to_string(),
+                        };
+                        self.trace_line(Direction::Received, traced.as_str());
+                    }
+                    return match line {
+                        Ok(line) => PortConnection::Valid(line),
+                        Err(bytes) => PortConnection::InvalidBytes(bytes),
+                    };
+                }
+                // Reading waits for up to the port timeout when there is nothing to read.
+                match self.port.read(&mut buffer) {
+                    Ok(read) => {
+                        self.framer.push(&buffer[..read]);
+                        self.port_error = None;
+                    }
+                    Err(e) => match e.kind() {
+                        ErrorKind::TimedOut | ErrorKind::WouldBlock | ErrorKind::Interrupted => (),
+                        kind => self.port_error = Some(kind),
+                    },
+                }
+                let last_input = self.framer.last_input().map_or(start, |last| last.max(start));
+                if last_input.elapsed() > Duration::from_secs(1) {
                     return PortConnection::NoConnection;
                 }
-                match p.read(buffer.as_mut_slice()) {
-                    Ok(buffer_size) => {
-                        output.extend_from_slice(&buffer[..buffer_size]);
-
-                        if output.get(output.len() - 1).unwrap() == &10u8 || output.len() > 255 {
-                            cont = false;
-                        }
+                if start.elapsed() > Duration::from_secs(1) {
+                    if let Some(garbage) = self.framer.take_garbage() {
+                        self.set_line_receipt(garbage.len());
+                        return PortConnection::InvalidBytes(garbage);
                     }
-                    Err(_e) => (),
                 }
             }
-            let string = str::from_utf8(&output);
-            return if string.is_ok() {
-                PortConnection::Valid(string.unwrap().to_string())
-            } else {
-                PortConnection::InvalidBytes(output)
-            };
+        }
+
+        /// The next sentence read while waiting for a command reply, or else the next line from the port.
+        fn next_line(&mut self) -> PortConnection {
+            let line = self.take_line();
+            let receipt = self.line_receipt.take();
+            self.add_to_update_receipt(receipt);
+            line
+        }
+
+        /// As next_line(), but leaves the line's receipt in line_receipt.
+        fn take_line(&mut self) -> PortConnection {
+            match self.pending.pop_front() {
+                Some(line) => {
+                    // Lines put in pending by hand have no receipt.
+                    self.line_receipt = if self.pending_receipts.len() > self.pending.len() {
+                        self.pending_receipts.pop_front()
+                    } else {
+                        None
+                    };
+                    PortConnection::Valid(line)
+                }
+                None => self.read_line(),
+            }
+        }
+
+        /// Puts back the line just given by take_line(), so next_line() gives it again.
+        fn put_back_line(&mut self, line: String) {
+            // A line with a receipt came from the port or the back of pending, where every line has one.
+            if let Some(receipt) = self.line_receipt.take() {
+                self.pending_receipts.push_front(receipt);
+            }
+            self.pending.push_front(line);
+        }
+
+        /// Sets line_receipt for a line of bytes just given by the framer.
+        fn set_line_receipt(&mut self, bytes: usize) {
+            self.line_receipt = Some(Receipt {
+                received: self.framer.last_input_time().unwrap_or_else(SystemTime::now),
+                monotonic: self.framer.last_input().unwrap_or_else(Instant::now),
+                bytes,
+            });
         }
 
         /// Keeps reading sentences until all the required sentences are read.
         /// Returns GpsData.
+        ///
+        /// Gives Sleeping instead of NoConnection while the gps is in a power saving mode.
+        ///
+        /// Sentences read while a command was waiting for its reply are given first, in order.
         pub fn update(&mut self) -> GpsSentence {
-            let port_output = self.read_line();
+            self.update_receipt = None;
+            let port_output = self.next_line();
+
+            if let PortConnection::Valid(_) = port_output {
+                if self.power_state == PowerState::Standby {
+                    // Any command wakes it from standby, so output means something woke it up.
+                    self.power_state = PowerState::Running;
+                }
+            }
 
             return match port_output {
+                PortConnection::NoConnection if self.power_state != PowerState::Running => GpsSentence::Sleeping,
                 PortConnection::NoConnection => GpsSentence::NoConnection,
                 PortConnection::InvalidBytes(_vector) => GpsSentence::InvalidBytes,
                 PortConnection::Valid(string) => {
-                    let sentence: Option<Vec<&str>> = parse_sentence(string.as_str());
-                    if sentence.is_some() {
-                        let sentence = sentence.unwrap();
-                        let header = sentence.get(0).unwrap();
-                        // At this point sentences needs to be is_valid str.
-                        if &header[3..5] == "GG" {
-                            return GpsSentence::GGA(parse_gga(sentence));
-                        } else if &header[3..6] == "VTG" {
-                            return GpsSentence::VTG(parse_vtg(sentence));
-                        } else if &header[3..6] == "GSA" {
-                            return GpsSentence::GSA(parse_gsa(sentence));
-                        } else if &header[3..6] == "GLL" {
-                            return GpsSentence::GLL(parse_gll(sentence));
-                        } else if &header[3..6] == "RMC" {
-                            return GpsSentence::RMC(parse_rmc(sentence));
-                        } else if &header[3..6] == "GSV" {
+                    if !self.is_expected_talker(string.as_str()) {
+                        return GpsSentence::InvalidSentence;
+                    }
+                    match parse_line(string.as_str()) {
+                        GpsSentence::GSV(mut gsv_values) => {
                             // Assumes that each GSV sentence if given in exact sequence, and not out of order.
-                            let number_of_messages: i32 = sentence.get(1).unwrap().parse().unwrap();
+                            let number_of_messages: i32 = parse_sentence(string.as_str())
+                                .and_then(|s| s.get(1).and_then(|n| n.parse().ok()))
+                                .unwrap_or(1);
 
-                            let mut gsv_values: Vec<Satellites> = parse_gsv(sentence); // First sentence
                             for _message in 1..number_of_messages { // If number of messages is 1, this is all skipped.
                                 // Read lines and add it for each message.
-                                let line = self.read_line();
-                                match line {
-                                    PortConnection::Valid(line) => {
-                                        let sentence = parse_sentence(line.as_str());
-                                        let sentence = sentence.unwrap();
-                                        gsv_values.append(parse_gsv(sentence).as_mut())
+                                match self.take_line() {
+                                    PortConnection::Valid(line) => match parse_line(line.as_str()) {
+                                        GpsSentence::GSV(mut more) => {
+                                            let receipt = self.line_receipt.take();
+                                            self.add_to_update_receipt(receipt);
+                                            gsv_values.append(&mut more)
+                                        }
+                                        _ => {
+                                            // A GSV message was lost: give this line as its own sentence.
+                                            self.put_back_line(line);
+                                            break;
+                                        }
+                                    },
+                                    _ => {
+                                        let receipt = self.line_receipt.take();
+                                        self.add_to_update_receipt(receipt);
                                     }
-                                    _ => ()
                                 };
                             }
-                            return GpsSentence::GSV(gsv_values);
+                            GpsSentence::GSV(gsv_values)
                         }
+                        sentence => sentence,
                     }
-                    GpsSentence::InvalidSentence
                 }
             };
         }
@@ -202,23 +391,27 @@ pub mod gps {
     impl GpsSentence {
         /// Reads a bytes file of structs to a vector.
         ///
+        /// Panics if the file can't be opened. Everything from an entry that can't be read onwards
+        /// is left out, use try_read_from() to get an error for it instead.
+        ///
         /// Benches at 263,860ns to read a 1,000 long vec.
         pub fn read_from(file: &str) -> Vec<GpsSentence> {
             let mut f = File::open(file).expect("No file found");
             let mut buffer = Vec::new();
             let _ = f.read_to_end(&mut buffer);
-            let split = buffer.split(|num| num == &10);
             let mut struct_vec: Vec<GpsSentence> = Vec::new();
-            for item in split {
-                match bincode::deserialize(item) {
-                    Ok(t) => {
-                        struct_vec.push(t)
-                    }
-                    _ => {}
-                }
-            }
+            let _ = read_entries(&buffer, &mut struct_vec);
+            struct_vec
+        }
 
-            return struct_vec;
+        /// As read_from(), but gives an InvalidData error for an entry that can't be read. An entry
+        /// that runs past the end of the file, such as from a power cut mid write, is left out.
+        pub fn try_read_from(file: &str) -> io::Result<Vec<GpsSentence>> {
+            let mut buffer = Vec::new();
+            File::open(file)?.read_to_end(&mut buffer)?;
+            let mut struct_vec: Vec<GpsSentence> = Vec::new();
+            read_entries(&buffer, &mut struct_vec)?;
+            Ok(struct_vec)
         }
 
         /// Append a GpsSentence struct to a file.
@@ -246,12 +439,151 @@ pub mod gps {
             let _ = f.write(&breakline);
         }
     }
+
+    /// Reads each entry in turn, rather than splitting at the \n (10) bytes, as an entry can
+    /// have a 10 byte in it.
+    fn read_entries(buffer: &[u8], sentences: &mut Vec<GpsSentence>) -> io::Result<()> {
+        let mut rest = buffer;
+        while !rest.is_empty() {
+            // The limit stops a bad length allocating more than is left in the file.
+            let options = bincode::DefaultOptions::new().with_fixint_encoding().with_limit(rest.len() as u64);
+            match options.deserialize_from(&mut rest) {
+                Ok(sentence) => sentences.push(sentence),
+                Err(e) => {
+                    return match *e {
+                        bincode::ErrorKind::SizeLimit => Ok(()),
+                        bincode::ErrorKind::Io(ref io_error) if io_error.kind() == ErrorKind::UnexpectedEof => Ok(()),
+                        _ => Err(io::Error::new(ErrorKind::InvalidData, e)),
+                    };
+                }
+            }
+            match rest.split_first() {
+                Some((10, after)) => rest = after,
+                Some(_) => return Err(io::Error::new(ErrorKind::InvalidData, "Log entry not followed by \\n")),
+                None => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock_port {
+    //! A fake serial port for testing without a gps.
+    use std::collections::VecDeque;
+    use std::io::{self, Read, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use serialport::prelude::*;
+    use serialport::{ClearBuffer, Error, ErrorKind};
+
+    use super::gps::Gps;
+
+    /// A GGA sentence with a fix, for tests to read.
+    pub const GGA: &str = "$GNGGA,131613.000,5132.7314,N,00005.9099,W,1,9,1.17,42.4,M,47.0,M,,*60\r\n";
+
+    /// Gives the bytes in input, then times out. Everything written is put in written.
+    ///
+    /// Each write adds the next of replies to input, so "" is a command the gps didn't answer.
+    pub struct MockPort {
+        pub input: Arc<Mutex<VecDeque<u8>>>,
+        pub written: Arc<Mutex<Vec<u8>>>,
+        pub replies: VecDeque<String>,
+    }
+
+    /// A Gps that reads lines, in order, and a handle to what is written to it.
+    pub fn mock_gps(lines: &[&str]) -> (Gps, Arc<Mutex<Vec<u8>>>) {
+        mock_gps_with_replies(lines, &[])
+    }
+
+    /// A Gps that reads lines, then answers each write with the next reply.
+    pub fn mock_gps_with_replies(lines: &[&str], replies: &[&str]) -> (Gps, Arc<Mutex<Vec<u8>>>) {
+        let input: VecDeque<u8> = lines.concat().into_bytes().into_iter().collect();
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let port = MockPort {
+            input: Arc::new(Mutex::new(input)),
+            written: written.clone(),
+            replies: replies.iter().map(|r| r.to_string()).collect(),
+        };
+        (Gps::from_port(Box::new(port)), written)
+    }
+
+    impl Read for MockPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut input = self.input.lock().unwrap();
+            if input.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "No bytes"));
+            }
+            let mut read = 0;
+            while read < buf.len() {
+                match input.pop_front() {
+                    Some(byte) => buf[read] = byte,
+                    None => break,
+                }
+                read += 1;
+            }
+            Ok(read)
+        }
+    }
+
+    impl Write for MockPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.lock().unwrap().extend_from_slice(buf);
+            if let Some(reply) = self.replies.pop_front() {
+                self.input.lock().unwrap().extend(reply.into_bytes());
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SerialPort for MockPort {
+        fn name(&self) -> Option<String> { Some("mock".to_string()) }
+        fn settings(&self) -> SerialPortSettings { SerialPortSettings::default() }
+        fn baud_rate(&self) -> serialport::Result<u32> { Ok(9600) }
+        fn data_bits(&self) -> serialport::Result<DataBits> { Ok(DataBits::Eight) }
+        fn flow_control(&self) -> serialport::Result<FlowControl> { Ok(FlowControl::None) }
+        fn parity(&self) -> serialport::Result<Parity> { Ok(Parity::None) }
+        fn stop_bits(&self) -> serialport::Result<StopBits> { Ok(StopBits::One) }
+        fn timeout(&self) -> Duration { Duration::from_millis(1) }
+        fn set_all(&mut self, _: &SerialPortSettings) -> serialport::Result<()> { Ok(()) }
+        fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> { Ok(()) }
+        fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> { Ok(()) }
+        fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> { Ok(()) }
+        fn set_parity(&mut self, _: Parity) -> serialport::Result<()> { Ok(()) }
+        fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> { Ok(()) }
+        fn set_timeout(&mut self, _: Duration) -> serialport::Result<()> { Ok(()) }
+        fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> { Ok(()) }
+        fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> { Ok(()) }
+        fn read_clear_to_send(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_data_set_ready(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_ring_indicator(&mut self) -> serialport::Result<bool> { Ok(false) }
+        fn read_carrier_detect(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn bytes_to_read(&self) -> serialport::Result<u32> { Ok(self.input.lock().unwrap().len() as u32) }
+        fn bytes_to_write(&self) -> serialport::Result<u32> { Ok(0) }
+        fn clear(&self, _: ClearBuffer) -> serialport::Result<()> { Ok(()) }
+        fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+            Err(Error::new(ErrorKind::Unknown, "Mock port can't be cloned"))
+        }
+    }
 }
 
 #[cfg(test)]
 mod gps_test {
     use super::gps;
 
+    #[test]
+    fn invalid_baud_rate() {
+        match gps::Gps::new("/dev/serial0", "fast") {
+            Err(e) => assert_eq!(e.kind(), serialport::ErrorKind::InvalidInput),
+            Ok(_) => panic!("opened with a baud rate of fast"),
+        }
+    }
+
     #[test]
     fn is_valid_sentence() {
         assert_eq!(gps::is_valid_checksum("$PMTK220,100*2F"), true);
@@ -289,6 +621,7 @@ mod test_read_write {
 
     use crate::GpsSentence;
     use crate::nmea::gga::{GgaData, SatFix};
+    use crate::nmea::rmc::RmcData;
 
     const SENTENCE: GpsSentence = GpsSentence::GGA(GgaData {
         utc: 100.0,
@@ -333,4 +666,15 @@ mod test_read_write {
         let _ = remove_file("loop_test");
         assert_eq!(read, check_vec);
     }
+
+    #[test]
+    fn entry_with_a_10_byte() {
+        let _ = remove_file("ten_byte_test");
+        let rmc = GpsSentence::RMC(RmcData { date: "\n".to_string(), ..RmcData::default() });
+        rmc.clone().append_to("ten_byte_test");
+        SENTENCE.append_to("ten_byte_test");
+        let read = GpsSentence::try_read_from("ten_byte_test").unwrap();
+        let _ = remove_file("ten_byte_test");
+        assert_eq!(read, vec![rmc, SENTENCE]);
+    }
 }
@@ -3,8 +3,8 @@
 //! To give a PMTK command send it via the Gps struct: gps.pmtk....(args). See simple.rs for example.
 //!
 //! Note:
-//! All commands will be destructive for all sentences in the buffer. You will not be able to read data given by
-//! the gps after sending it a command.
+//! Sentences the gps gives while a command waits for its reply are kept, and given by the next
+//! gps.update() calls, so sending commands doesn't lose any data.
 //!
 //! ## Important commands
 //! - pmtk_220_set_nmea_updaterate -> Hz for the gps update rate.
@@ -15,26 +15,42 @@
 //! ## Changing the baudrate
 //! Given it's a special function, it's a stand alone method in the send_pmtk module.
 //!
+//! ## Timeouts and resending
+//! Each command waits for its reply for gps.command_policy.timeout and is resent if there is none.
+//! See [CommandPolicy](send_pmtk/struct.CommandPolicy.html).
+//!
 //! ## PMTK return formats
 //! Depending on the command given, the return values change.
 //!
 
 pub mod send_pmtk {
     //! Contains all the pmtk commands that can be sent.
+    use std::collections::HashMap;
+    use std::ops::RangeInclusive;
     use std::str;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
 
-    use serialport::{self, ClearBuffer};
+    use serde::{Deserialize, Serialize};
 
-    use super::super::open_gps::gps::{Gps,GpsSentence, is_valid_checksum, open_port, PortConnection};
+    use super::super::open_gps::gps::{Gps,GpsSentence, is_valid_checksum, parse_line, open_port, PortConnection, PowerState,
+                                      push_capped, MAX_PENDING, MAX_STRAY};
+    use crate::nmea::parse_nmea::Talker;
+    use crate::datum::datums::Datum;
+    use crate::timing::utc::UtcDateTime;
+    use crate::trace::wire_trace::Direction;
 
-    #[derive(Debug, PartialEq)]
+    use super::chip_support::is_supported;
+
+    #[derive(Debug, PartialEq, Clone)]
     /// # PMTK001 return values
     ///
     /// - Invalid (No such command)
     /// - Unsupported (Chip type does not support this command)
     /// - Falied (Chip failed to do the command for some reason)
     /// - Success (Command implimented)
-    /// - NoPacket (After 10 read lines, no command found)
+    /// - NoPacket (No reply before the command timed out)
+    /// - NotOnChip (gps.chip doesn't support this command, so it wasn't sent. See chip_support)
     pub enum Pmtk001Ack {
         // format: $PMTK001,cmd,flag*checksum\r\n
         //flag: 0
@@ -46,9 +62,171 @@ pub mod send_pmtk {
         //flag: 3
         Success,
         NoPacket,
+        NotOnChip(Chip),
+    }
+
+    /// # PMTK001 reply to a command
+    ///
+    /// - cmd: The command number that was acknowledged, eg 220 for PMTK220.
+    /// - flag: [Pmtk001Ack](enum.Pmtk001Ack.html)
+    /// - value: Anything given after the flag, eg "1,0,1" for $PMTK001,355,3,1,0,1
+    /// - attempt: Which send of the command got this reply, 1 for the first. 0 if there was no reply
+    ///   or the command was never sent. See [CommandPolicy](struct.CommandPolicy.html)
+    ///
+    /// Can be compared straight to a Pmtk001Ack: `gps.pmtk_220_set_nmea_updaterate("1000") == Pmtk001Ack::Success`
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct PmtkAck {
+        pub cmd: u16,
+        pub flag: Pmtk001Ack,
+        pub value: Option<String>,
+        pub attempt: u32,
+    }
+
+    impl PmtkAck {
+        pub fn new(cmd: u16, flag: Pmtk001Ack) -> PmtkAck {
+            PmtkAck { cmd, flag, value: None, attempt: 0 }
+        }
+
+        /// Parses a $PMTK001,cmd,flag,value*checksum line. None if it isn't a valid PMTK001.
+        pub fn parse(line: &str) -> Option<PmtkAck> {
+            let line = line.trim();
+            if !line.starts_with("$PMTK001,") || !is_valid_checksum(line) {
+                return None;
+            }
+            let line = &line[..line.len() - 3]; // Remove checksum.
+            let args: Vec<&str> = line.splitn(4, ',').collect();
+            let cmd: u16 = args.get(1)?.parse().ok()?;
+            let flag = match *args.get(2)? {
+                "0" => Pmtk001Ack::Invalid,
+                "1" => Pmtk001Ack::Unsupported,
+                "2" => Pmtk001Ack::Failed,
+                "3" => Pmtk001Ack::Success,
+                _ => return None,
+            };
+            Some(PmtkAck { cmd, flag, value: args.get(3).map(|v| v.to_string()), attempt: 0 })
+        }
+    }
+
+    impl PartialEq<Pmtk001Ack> for PmtkAck {
+        fn eq(&self, other: &Pmtk001Ack) -> bool {
+            &self.flag == other
+        }
+    }
+
+    /// # Why send_query gave no reply
+    ///
+    /// - NotOnChip: gps.chip doesn't support the query, so it wasn't sent. See chip_support.
+    /// - NoReply: The reply packet didn't come before the query timed out, on every send.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum QueryError {
+        NotOnChip(Chip),
+        NoReply,
+    }
+
+    /// # How long to wait for command replies, and how often to resend
+    ///
+    /// - timeout: How long to wait for a reply to each send. Default 1 second.
+    /// - retries: How many times to resend a command that got no reply. Default 2.
+    /// - backoff: Wait before the first resend. Doubled for each resend after that. Default 100 ms.
+    /// - command_timeouts: Timeouts for commands that take longer, by command number. Default 10 s
+    ///   for 101-104 (restarts) and 127 (clear EPO).
+    ///
+    /// Set it on the gps before sending commands: `gps.command_policy.retries = 0;`
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct CommandPolicy {
+        pub timeout: Duration,
+        pub retries: u32,
+        pub backoff: Duration,
+        pub command_timeouts: HashMap<u16, Duration>,
+    }
+
+    impl Default for CommandPolicy {
+        fn default() -> CommandPolicy {
+            let mut command_timeouts = HashMap::new();
+            for cmd in &[101, 102, 103, 104, 127] {
+                command_timeouts.insert(*cmd, Duration::from_secs(10));
+            }
+            CommandPolicy {
+                timeout: Duration::from_secs(1),
+                retries: 2,
+                backoff: Duration::from_millis(100),
+                command_timeouts,
+            }
+        }
+    }
+
+    impl CommandPolicy {
+        /// How long to wait for the reply to command cmd.
+        pub fn timeout_for(&self, cmd: u16) -> Duration {
+            *self.command_timeouts.get(&cmd).unwrap_or(&self.timeout)
+        }
+
+        /// How long to wait before the given attempt. Zero for the first attempt.
+        pub fn backoff_for(&self, attempt: u32) -> Duration {
+            if attempt <= 1 {
+                return Duration::from_secs(0);
+            }
+            self.backoff * 2u32.saturating_pow(attempt - 2)
+        }
+    }
+
+    /// The command number of a PMTK command, eg 220 for "PMTK220,1000". 0 if there isn't one.
+    pub(crate) fn command_number(cmd: &str) -> u16 {
+        cmd.get(4..7).and_then(|n| n.parse().ok()).unwrap_or(0)
+    }
+
+    /// The packet number of a reply, such as 501 for $PMTK501,2. 0 if there isn't one.
+    pub(crate) fn reply_number(reply: &str) -> u16 {
+        reply.strip_prefix('$').map_or(0, command_number)
+    }
+
+    /// What a command waits for after it is sent: its PMTK001, its reply packet (any reply if None)
+    /// or the startup message.
+    pub(crate) enum Wait {
+        Ack(u16),
+        Reply(Option<u16>),
+        Startup,
     }
 
+    /// A line read while waiting, as Wait::check sorts it. Reply and StrayReply are without the
+    /// checksum, and Other is anything that isn't a PMTK reply, such as a sentence.
     #[derive(Debug, PartialEq)]
+    pub(crate) enum Waited {
+        Ack(PmtkAck),
+        Reply(String),
+        Startup,
+        StrayAck(PmtkAck),
+        StrayReply(String),
+        Other,
+    }
+
+    impl Wait {
+        /// Sorts a line read while waiting. Gps and AsyncGps both wait with this, so they match
+        /// replies the same way.
+        pub(crate) fn check(&self, line: &str) -> Waited {
+            if let Some(ack) = PmtkAck::parse(line) {
+                return match self {
+                    Wait::Ack(cmd) if ack.cmd == *cmd => Waited::Ack(ack),
+                    _ => Waited::StrayAck(ack),
+                };
+            }
+            if !line.starts_with("$PMTK") || !is_valid_checksum(line) {
+                return Waited::Other;
+            }
+            let reply = line.trim().split('*').next().unwrap_or("").to_string();
+            let number = reply_number(reply.as_str());
+            // PMTK010 and PMTK011 are system messages, not replies.
+            if number == 10 || number == 11 {
+                return if number == 11 && matches!(self, Wait::Startup) { Waited::Startup } else { Waited::Other };
+            }
+            match self {
+                Wait::Reply(expected) if expected.unwrap_or(number) == number => Waited::Reply(reply),
+                _ => Waited::StrayReply(reply),
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
     /// Dgps (Differential GPS) mode is the usage of ground stations to aid in the accuracy of position.
     /// - NoDGPS: Default
     /// - RTCM
@@ -60,7 +238,7 @@ pub mod send_pmtk {
         Unknown,
     }
 
-    #[derive(Debug, PartialEq)]
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
     /// SBAS (Satellite-based augmentation systems) uses ground stations broadcasting
     /// satellite messages to aid in navigation and accuracy.
     pub enum Sbas {
@@ -69,14 +247,14 @@ pub mod send_pmtk {
         Unknown,
     }
 
-    #[derive(Debug, PartialEq)]
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
     pub enum SbasMode {
         Testing,
         Integrity,
         Unknown,
     }
 
-    #[derive(Debug, PartialEq)]
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
     pub struct NmeaOutput {
         pub gll: i8,
         pub rmc: i8,
@@ -87,26 +265,584 @@ pub mod send_pmtk {
         pub pmtkchn_interval: i8,
     }
 
-    #[derive(Debug, PartialEq)]
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
     pub struct EpoData {
-        pub set: i8,
-        pub fwn_ftow_week_number: i8,
-        pub fwn_ftow_tow: i8,
-        pub lwn_ltow_week_number: i8,
-        pub lwn_ltow_tow: i8,
-        pub fcwn_fctow_week_number: i8,
-        pub fcwn_fctow_tow: i8,
-        pub lcwn_lctow_week_number: i8,
-        pub lcwn_lctow_tow: i8,
+        pub set: i32,
+        pub fwn_ftow_week_number: i32,
+        pub fwn_ftow_tow: i32,
+        pub lwn_ltow_week_number: i32,
+        pub lwn_ltow_tow: i32,
+        pub fcwn_fctow_week_number: i32,
+        pub fcwn_fctow_tow: i32,
+        pub lcwn_lctow_week_number: i32,
+        pub lcwn_lctow_tow: i32,
+    }
+
+    /// Argument i (0 is the first after the header) of a query reply, with or without its
+    /// checksum. None if the reply isn't the given packet, or the argument can't be read.
+    pub fn reply_arg<T: str::FromStr>(reply: &str, packet: u16, i: usize) -> Option<T> {
+        let reply = reply.trim().split('*').next()?;
+        if reply_number(reply) != packet {
+            return None;
+        }
+        reply.split(',').nth(i + 1)?.parse().ok()
+    }
+
+    /// EASY enabled, from a $PMTK869,2,{0,1},{days of extension} reply. None if it isn't one.
+    pub fn easy_from_q_reply(reply: &str) -> Option<bool> {
+        if reply_arg::<u8>(reply, 869, 0)? != 2 {
+            return None;
+        }
+        reply_arg::<u8>(reply, 869, 1).map(|enabled| enabled != 0)
+    }
+
+    impl DgpsMode {
+        /// The argument given to PMTK301. None for Unknown.
+        pub fn to_args(&self) -> Option<&'static str> {
+            match self {
+                DgpsMode::NoDgps => Some("0"),
+                DgpsMode::RTCM => Some("1"),
+                DgpsMode::WAAS => Some("2"),
+                DgpsMode::Unknown => None,
+            }
+        }
+
+        /// From a $PMTK501,{0,1,2} reply. None if it isn't one.
+        pub fn from_q_reply(reply: &str) -> Option<DgpsMode> {
+            match reply_arg::<u8>(reply, 501, 0)? {
+                0 => Some(DgpsMode::NoDgps),
+                1 => Some(DgpsMode::RTCM),
+                2 => Some(DgpsMode::WAAS),
+                _ => None,
+            }
+        }
+    }
+
+    impl Sbas {
+        /// The argument given to PMTK313. None for Unknown.
+        pub fn to_args(&self) -> Option<&'static str> {
+            match self {
+                Sbas::Disabled => Some("0"),
+                Sbas::Enabled => Some("1"),
+                Sbas::Unknown => None,
+            }
+        }
+
+        /// From a $PMTK513,{0,1} reply. None if it isn't one.
+        pub fn from_q_reply(reply: &str) -> Option<Sbas> {
+            match reply_arg::<u8>(reply, 513, 0)? {
+                0 => Some(Sbas::Disabled),
+                1 => Some(Sbas::Enabled),
+                _ => None,
+            }
+        }
+    }
+
+    impl SbasMode {
+        /// The argument given to PMTK319. None for Unknown.
+        pub fn to_args(&self) -> Option<&'static str> {
+            match self {
+                SbasMode::Testing => Some("0"),
+                SbasMode::Integrity => Some("1"),
+                SbasMode::Unknown => None,
+            }
+        }
+
+        /// From a $PMTK519,{0,1} reply. None if it isn't one.
+        pub fn from_q_reply(reply: &str) -> Option<SbasMode> {
+            match reply_arg::<u8>(reply, 519, 0)? {
+                0 => Some(SbasMode::Testing),
+                1 => Some(SbasMode::Integrity),
+                _ => None,
+            }
+        }
+    }
+
+    impl NmeaOutput {
+        /// The arguments given to PMTK314.
+        pub fn to_args(&self) -> String {
+            format!("{},{},{},{},{},{},0,0,0,0,0,0,0,{}",
+                    self.gll, self.rmc, self.vtg, self.gga, self.gsa, self.gsv, self.pmtkchn_interval)
+        }
+
+        /// From a $PMTK514 reply, in the PMTK314 order. None if it isn't one.
+        pub fn from_q_reply(reply: &str) -> Option<NmeaOutput> {
+            let period = |i: usize| reply_arg::<i8>(reply, 514, i);
+            Some(NmeaOutput {
+                gll: period(0)?,
+                rmc: period(1)?,
+                vtg: period(2)?,
+                gga: period(3)?,
+                gsa: period(4)?,
+                gsv: period(5)?,
+                pmtkchn_interval: period(17)?,
+            })
+        }
+    }
+
+    impl EpoData {
+        /// From a $PMTK707 reply. None if it isn't one.
+        pub fn from_q_reply(reply: &str) -> Option<EpoData> {
+            let field = |i: usize| reply_arg::<i32>(reply, 707, i);
+            Some(EpoData {
+                set: field(0)?,
+                fwn_ftow_week_number: field(1)?,
+                fwn_ftow_tow: field(2)?,
+                lwn_ltow_week_number: field(3)?,
+                lwn_ltow_tow: field(4)?,
+                fcwn_fctow_week_number: field(5)?,
+                fcwn_fctow_tow: field(6)?,
+                lcwn_lctow_week_number: field(7)?,
+                lcwn_lctow_tow: field(8)?,
+            })
+        }
+    }
+
+    /// MediaTek chip the gps module is built on.
+    /// - MT3318, MT3329 -> Older modules, such as the PA6B.
+    /// - MT3339 -> Ultimate GPS (PA6C, PA6H).
+    /// - MT3333 -> Mini GPS PA1010D. Also searches GLONASS, Galileo and BeiDou.
+    /// - Other -> Any other chip number given in the firmware release.
+    #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+    pub enum Chip {
+        MT3318,
+        MT3329,
+        MT3339,
+        MT3333,
+        Other(u16),
+    }
+
+    impl Chip {
+        /// Chip from its number, eg 3339.
+        pub fn from_number(number: u16) -> Chip {
+            match number {
+                3318 => Chip::MT3318,
+                3329 => Chip::MT3329,
+                3339 => Chip::MT3339,
+                3333 => Chip::MT3333,
+                number => Chip::Other(number),
+            }
+        }
+    }
+
+    /// Date the firmware was built, and the build number of that day.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct BuildDate {
+        pub year: u16,
+        pub month: u8,
+        pub day: u8,
+        pub build: u8,
+    }
+
+    impl BuildDate {
+        /// Parses yymmddnn (19020118) or yyyymmddnn (2012072601).
+        fn parse(date: &str) -> Option<BuildDate> {
+            if !date.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let (year, rest) = match date.len() {
+                8 => (2000 + date[0..2].parse::<u16>().ok()?, &date[2..]),
+                10 => (date[0..4].parse::<u16>().ok()?, &date[4..]),
+                _ => return None,
+            };
+            let build_date = BuildDate {
+                year,
+                month: rest[0..2].parse().ok()?,
+                day: rest[2..4].parse().ok()?,
+                build: rest[4..6].parse().ok()?,
+            };
+            if (1..=12).contains(&build_date.month) && (1..=31).contains(&build_date.day) {
+                Some(build_date)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// # Firmware release, from the PMTK705 reply
+    /// $PMTK705,AXN_5.1.7_3333_19020118,0027,PA1010D,1.0*76
+    ///
+    /// - family: Firmware family, AXN.
+    /// - version: Firmware version, 5.1.7.
+    /// - chip: [Chip](enum.Chip.html), from 3333. None if there isn't a chip number.
+    /// - build_date: 2019-02-01, build 18. None if it isn't a date.
+    /// - build_id: 0027.
+    /// - module: PA1010D.
+    /// - sdk_version: 1.0.
+    /// - raw: The whole release string, AXN_5.1.7_3333_19020118,0027,PA1010D,1.0.
+    ///
+    /// Fields the reply doesn't have are left blank.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct FirmwareRelease {
+        pub family: String,
+        pub version: String,
+        pub chip: Option<Chip>,
+        pub build_date: Option<BuildDate>,
+        pub build_id: String,
+        pub module: String,
+        pub sdk_version: String,
+        pub raw: String,
+    }
+
+    impl FirmwareRelease {
+        /// Parses the release with or without the $PMTK705 header and checksum. None if it is empty.
+        pub fn parse(release: &str) -> Option<FirmwareRelease> {
+            let release = release.trim();
+            let release = release.split('*').next().unwrap_or("");
+            let release = release.trim_start_matches('$').trim_start_matches("PMTK705").trim_start_matches(',');
+            if release.is_empty() {
+                return None;
+            }
+            let args: Vec<&str> = release.split(',').collect();
+            let main: Vec<&str> = args[0].split('_').collect();
+            let arg = |list: &Vec<&str>, i: usize| list.get(i).unwrap_or(&"").to_string();
+            Some(FirmwareRelease {
+                family: arg(&main, 0),
+                version: arg(&main, 1),
+                chip: main.get(2).and_then(|c| c.parse().ok()).map(Chip::from_number),
+                build_date: main.get(3).and_then(|d| BuildDate::parse(d)),
+                build_id: arg(&args, 1),
+                module: arg(&args, 2),
+                sdk_version: arg(&args, 3),
+                raw: release.to_string(),
+            })
+        }
+    }
+
+    /// 1PPS output mode for pmtk_285_set_pps_config.
+    /// - Disabled -> No pulse output.
+    /// - AfterFirstFix -> Pulse once a fix has been found, and keep pulsing after the fix is lost.
+    /// - Fix3dOnly -> Pulse only while there is a 3D fix.
+    /// - Fix2d3dOnly -> Pulse only while there is a 2D or 3D fix.
+    /// - Always -> Always pulse, fix or not.
+    #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+    pub enum PpsMode {
+        Disabled,
+        AfterFirstFix,
+        Fix3dOnly,
+        Fix2d3dOnly,
+        Always,
+    }
+
+    /// 1PPS settings. pulse_width is in milliseconds, 2 to 998. Default is 100.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct PpsConfig {
+        pub mode: PpsMode,
+        pub pulse_width: u16,
+    }
+
+    impl PpsConfig {
+        /// The argument given to PMTK285, or None if the pulse width is out of range.
+        pub fn to_args(&self) -> Option<String> {
+            if self.pulse_width < 2 || self.pulse_width > 998 {
+                return None;
+            }
+            let mode = match self.mode {
+                PpsMode::Disabled => 0,
+                PpsMode::AfterFirstFix => 1,
+                PpsMode::Fix3dOnly => 2,
+                PpsMode::Fix2d3dOnly => 3,
+                PpsMode::Always => 4,
+            };
+            Some(format!("{},{}", mode, self.pulse_width))
+        }
+    }
+
+    /// Elevation masks, in degrees, for pmtk_311_api_set_elev_mask. From PMTK_API_SET_ELEV_MASK in
+    /// the MTK NMEA Packet User Manual.
+    pub const ELEVATION_MASK_RANGE: RangeInclusive<u8> = 0..=90;
+
+    /// Minimum SNRs, in dB-Hz, for pmtk_306_api_set_min_snr. From PMTK_API_SET_MIN_SNR in the MTK
+    /// NMEA Packet User Manual.
+    pub const MIN_SNR_RANGE: RangeInclusive<u8> = 9..=37;
+
+    /// Run and sleep times for the periodic power modes. Each time is 0 (disabled) or 1 to 518,400
+    /// seconds, at millisecond resolution.
+    /// - run_time: Time to try to get a fix for before going back to sleep. Can't be 0.
+    /// - sleep_time: Time to sleep for before trying to get a new fix. Can't be 0.
+    /// - second_run_time, second_sleep_time: Used instead while there isn't enough ephemeris data,
+    ///   after a cold start. second_run_time must be longer than run_time if it is used.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct PeriodicTimes {
+        pub run_time: Duration,
+        pub sleep_time: Duration,
+        pub second_run_time: Duration,
+        pub second_sleep_time: Duration,
+    }
+
+    impl PeriodicTimes {
+        /// The times as milliseconds, or None if any are out of range.
+        fn to_millis(&self) -> Option<[u128; 4]> {
+            let times = [
+                self.run_time.as_millis(),
+                self.sleep_time.as_millis(),
+                self.second_run_time.as_millis(),
+                self.second_sleep_time.as_millis(),
+            ];
+            let in_range = times.iter().all(|t| *t == 0 || (1_000..=518_400_000).contains(t));
+            let second_run_longer = times[2] == 0 || times[2] > times[0];
+            if in_range && times[0] != 0 && times[1] != 0 && second_run_longer {
+                Some(times)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Power saving modes for pmtk_225_cmd_periodic_mode. For MT3339 chips.
+    /// - Normal -> Always on. Default.
+    /// - PeriodicBackup -> Turn on to get a fix, then go into backup mode (lowest power) for the sleep time.
+    /// - PeriodicStandby -> Turn on to get a fix, then go into standby mode for the sleep time.
+    /// - PerpetualBackup -> Backup mode until woken by the FORCE_ON pin.
+    /// - AlwaysLocateStandby -> The chip chooses when to be on or in standby depending on how it's moving.
+    /// - AlwaysLocateBackup -> The chip chooses when to be on or in backup depending on how it's moving.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub enum PowerMode {
+        Normal,
+        PeriodicBackup(PeriodicTimes),
+        PeriodicStandby(PeriodicTimes),
+        PerpetualBackup,
+        AlwaysLocateStandby,
+        AlwaysLocateBackup,
+    }
+
+    impl PowerMode {
+        /// gps.power_state once the mode is set.
+        pub(crate) fn power_state(&self) -> PowerState {
+            match self {
+                PowerMode::Normal => PowerState::Running,
+                PowerMode::PerpetualBackup => PowerState::Backup,
+                mode => PowerState::Periodic(mode.clone()),
+            }
+        }
+
+        /// The arguments given to PMTK225, or None if the times are out of range.
+        pub fn to_args(&self) -> Option<String> {
+            let periodic = |run_type: u8, times: &PeriodicTimes| {
+                let t = times.to_millis()?;
+                Some(format!("{},{},{},{},{}", run_type, t[0], t[1], t[2], t[3]))
+            };
+            match self {
+                PowerMode::Normal => Some("0".to_string()),
+                PowerMode::PeriodicBackup(times) => periodic(1, times),
+                PowerMode::PeriodicStandby(times) => periodic(2, times),
+                PowerMode::PerpetualBackup => Some("4".to_string()),
+                PowerMode::AlwaysLocateStandby => Some("8".to_string()),
+                PowerMode::AlwaysLocateBackup => Some("9".to_string()),
+            }
+        }
+    }
+
+    /// Satellite systems the gps searches, for MT3333 chips (PA1010D).
+    ///
+    /// QZSS is set separately with pmtk_352_api_set_stop_qzss.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct GnssSearchMode {
+        pub gps: bool,
+        pub glonass: bool,
+        pub galileo: bool,
+        pub beidou: bool,
+    }
+
+    impl GnssSearchMode {
+        /// The talker GGA, RMC, VTG and GLL sentences are given with. GN if more than one system is
+        /// searched. None if no system is searched.
+        pub fn expected_talker(&self) -> Option<Talker> {
+            let systems = [
+                (self.gps, Talker::Gps),
+                (self.glonass, Talker::Glonass),
+                (self.galileo, Talker::Galileo),
+                (self.beidou, Talker::Beidou),
+            ];
+            let enabled: Vec<Talker> = systems.iter().filter(|s| s.0).map(|s| s.1).collect();
+            match enabled.len() {
+                0 => None,
+                1 => Some(enabled[0]),
+                _ => Some(Talker::Multi),
+            }
+        }
+
+        /// The arguments given to PMTK353: GPS,GLONASS,GALILEO,GALILEO_FULL,BEIDOU
+        pub fn to_args(&self) -> String {
+            format!("{},{},{},0,{}", self.gps as u8, self.glonass as u8, self.galileo as u8, self.beidou as u8)
+        }
+
+        /// Parses the value of the PMTK355 reply, $PMTK001,355,3,GLONASS,BEIDOU,GALILEO.
+        /// GPS is always searched.
+        pub fn from_q_value(value: &str) -> Option<GnssSearchMode> {
+            let args: Vec<&str> = value.split(',').collect();
+            if args.len() < 3 {
+                return None;
+            }
+            Some(GnssSearchMode {
+                gps: true,
+                glonass: args[0] == "1",
+                beidou: args[1] == "1",
+                galileo: args[2] == "1",
+            })
+        }
+    }
+
+    /// Baud rates the gps can be set to, for PMTK251 and the user option.
+    pub const BAUD_RATES: [u32; 7] = [4800, 9600, 14400, 19200, 38400, 57600, 115200];
+
+    /// Defaults kept in the gps flash with pmtk_390_api_set_user_option. A full cold start, or
+    /// power without a backup battery, goes back to these rather than the factory settings.
+    ///
+    /// - update_rate: Fixes a second, 1, 2, 4, 5 or 10.
+    /// - baud_rate: One of BAUD_RATES.
+    /// - nmea_output: As for pmtk_314_api_set_nmea_output, 0-5 for each sentence.
+    /// - zda: ZDA output, 0-5.
+    /// - datum: See pmtk_330_api_set_datum.
+    /// - dgps_mode, sbas, sbas_mode: As set by pmtk_301, pmtk_313 and pmtk_319.
+    /// - nmea_v31: NMEA 0183 v3.01 output, rather than v2.3.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct UserOption {
+        pub update_rate: u8,
+        pub baud_rate: u32,
+        pub nmea_output: NmeaOutput,
+        pub zda: i8,
+        pub datum: Datum,
+        pub dgps_mode: DgpsMode,
+        pub sbas: Sbas,
+        pub sbas_mode: SbasMode,
+        pub nmea_v31: bool,
+    }
+
+    impl UserOption {
+        /// The arguments given to PMTK390, or None if any setting is out of range or Unknown.
+        ///
+        /// Lock,Update_Rate,Baud_Rate,GLL,RMC,VTG,GSA,GSV,GGA,ZDA,MCHN,Datum,DGPS_Mode,SBAS,
+        /// NMEA_V31,SBAS_Integrity_Mode
+        ///
+        /// Lock is always 0. A locked user option can never be changed again, so it isn't offered.
+        pub fn to_args(&self) -> Option<String> {
+            let output = &self.nmea_output;
+            let periods = [output.gll, output.rmc, output.vtg, output.gsa, output.gsv, output.gga, self.zda,
+                output.pmtkchn_interval];
+            if ![1, 2, 4, 5, 10].contains(&self.update_rate)
+                || !BAUD_RATES.contains(&self.baud_rate)
+                || !periods.iter().all(|p| (0..=5).contains(p))
+                || self.datum.0 >= Datum::COUNT {
+                return None;
+            }
+            let dgps_mode = match self.dgps_mode {
+                DgpsMode::NoDgps => 0,
+                DgpsMode::RTCM => 1,
+                DgpsMode::WAAS => 2,
+                DgpsMode::Unknown => return None,
+            };
+            let sbas = match self.sbas {
+                Sbas::Disabled => 0,
+                Sbas::Enabled => 1,
+                Sbas::Unknown => return None,
+            };
+            let sbas_mode = match self.sbas_mode {
+                SbasMode::Testing => 0,
+                SbasMode::Integrity => 1,
+                SbasMode::Unknown => return None,
+            };
+            let periods: Vec<String> = periods.iter().map(|p| p.to_string()).collect();
+            Some(format!("0,{},{},{},{},{},{},{},{}", self.update_rate, self.baud_rate, periods.join(","),
+                         self.datum.0, dgps_mode, sbas, self.nmea_v31 as u8, sbas_mode))
+        }
+
+        /// Parses the PMTK590 reply to PMTK490, in the same order as the PMTK390 arguments.
+        pub fn from_q_reply(reply: &str) -> Option<UserOption> {
+            let reply = reply.trim().split('*').next()?;
+            let args: Vec<&str> = reply.split(',').collect();
+            if args.len() < 17 || !args[0].ends_with("PMTK590") {
+                return None;
+            }
+            let period = |i: usize| args[i].parse::<i8>().ok();
+            Some(UserOption {
+                update_rate: args[2].parse().ok()?,
+                baud_rate: args[3].parse().ok()?,
+                nmea_output: NmeaOutput {
+                    gll: period(4)?,
+                    rmc: period(5)?,
+                    vtg: period(6)?,
+                    gsa: period(7)?,
+                    gsv: period(8)?,
+                    gga: period(9)?,
+                    pmtkchn_interval: period(11)?,
+                },
+                zda: period(10)?,
+                datum: Datum(args[12].parse().ok()?),
+                dgps_mode: match args[13] {
+                    "0" => DgpsMode::NoDgps,
+                    "1" => DgpsMode::RTCM,
+                    "2" => DgpsMode::WAAS,
+                    _ => DgpsMode::Unknown,
+                },
+                sbas: match args[14] {
+                    "0" => Sbas::Disabled,
+                    "1" => Sbas::Enabled,
+                    _ => Sbas::Unknown,
+                },
+                nmea_v31: args[15] == "1",
+                sbas_mode: match args[16] {
+                    "0" => SbasMode::Testing,
+                    "1" => SbasMode::Integrity,
+                    _ => SbasMode::Unknown,
+                },
+            })
+        }
+    }
+
+    /// A known position given to the gps to speed up the time to first fix.
+    /// - latitude, longitude: degrees, as given by GgaData.
+    /// - altitude: metres above mean sea level.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct AidingPosition {
+        pub latitude: f32,
+        pub longitude: f32,
+        pub altitude: f32,
+    }
+
+    impl AidingPosition {
+        /// Position from a GGA, RMC or GLL sentence. None if there is no lat/long in it.
+        ///
+        /// RMC and GLL have no altitude so 0 is used, which is close enough for aiding.
+        pub fn from_sentence(sentence: &GpsSentence) -> Option<AidingPosition> {
+            let (latitude, longitude, altitude) = match sentence {
+                GpsSentence::GGA(gga) => (gga.lat?, gga.long?, gga.msl_alt.unwrap_or(0.0)),
+                GpsSentence::RMC(rmc) => (rmc.latitude?, rmc.longitude?, 0.0),
+                GpsSentence::GLL(gll) => (gll.latitude?, gll.longitude?, 0.0),
+                _ => return None,
+            };
+            Some(AidingPosition { latitude, longitude, altitude })
+        }
+
+        /// The last position in a log, such as one from GpsSentence::read_from.
+        pub fn from_log(sentences: &[GpsSentence]) -> Option<AidingPosition> {
+            sentences.iter().rev().find_map(AidingPosition::from_sentence)
+        }
+
+        /// The arguments given to PMTK741: Lat,Long,Alt,YYYY,MM,DD,hh,mm,ss
+        pub fn to_args(&self, utc: &UtcDateTime) -> String {
+            format!("{:.6},{:.6},{:.1},{}", self.latitude, self.longitude, self.altitude, aiding_time_args(utc))
+        }
+    }
+
+    /// The arguments given to PMTK740: YYYY,MM,DD,hh,mm,ss
+    pub fn aiding_time_args(utc: &UtcDateTime) -> String {
+        format!("{},{:02},{:02},{:02},{:02},{:02}", utc.year, utc.month, utc.day, utc.hour, utc.minute, utc.second as u8)
+    }
+
+    /// Result of warm_start_with_aiding.
+    /// - restarted: the gps gave its startup message after the warm start.
+    /// - aiding: 001 response to the time (740) or position and time (741) aiding.
+    #[derive(Debug, PartialEq)]
+    pub struct AidingResult {
+        pub restarted: bool,
+        pub aiding: PmtkAck,
     }
 
     /// Adds a $ and a checksum to a given string.
     pub fn add_checksum(sentence: String) -> String {
-        let mut checksum = 0;
-        for char in sentence.as_bytes() {
-            checksum ^= *char;
-        }
-        let checksum = format!("{:X}", checksum); //Format as hexidecimal.
+        let checksum = crate::nmea_core::checksum::checksum(sentence.as_bytes());
+        let checksum = format!("{:02X}", checksum); //Format as hexidecimal.
         let checksumed_sentence = format!("${}*{}\r\n", sentence, checksum)
             .as_str()
             .to_ascii_uppercase();
@@ -132,14 +868,13 @@ pub mod send_pmtk {
         // stty -F /dev/serial0 9600 clocal cread cs8 -cstopb -parenb
 
         // Get current baud rate
-        let possible_baud_rates: [u32; 7] = [4800, 9600, 14400, 19200, 38400, 57600, 115200];
 
         // For each port, open it in that baud rate, see if you get garbage.
         // For some reason there are invalid bytes in front of what should be the correct baud rate.
         // So read 200 bytes, and ditch the first 100.
-        for rate in possible_baud_rates.iter() {
+        for rate in BAUD_RATES.iter() {
             if let Ok(port) = open_port(port_name, *rate) {
-                let mut gps = Gps { port };
+                let mut gps = Gps::from_port(port);
                 // Try reading 5 lines.
                 for _ in 0..5 {
                     let line = gps.update();
@@ -149,10 +884,9 @@ pub mod send_pmtk {
                         GpsSentence::InvalidBytes => {}
                         _ => {
                             gps.pmtk_220_set_nmea_updaterate("1000");
-                            let cmd = add_checksum(format!("PMTK251,{}", baud_rate));
-                            let cmd = cmd.as_bytes();
-                            let _ = gps.port.clear(ClearBuffer::Output);
-                            let _ = gps.port.write(cmd);
+                            if !gps.send_if_supported(format!("PMTK251,{}", baud_rate).as_str()) {
+                                return BaudRateResults::Fail;
+                            }
                             return BaudRateResults::Success(*rate);
                         }
                     }
@@ -164,214 +898,289 @@ pub mod send_pmtk {
 
     /// This implies all the traits to do with sending commands to the gps.
     impl Gps {
-        #[allow(unused_must_use)] // self.port.write is not used
         /// Send the PMTK command.
-        pub fn send_command(&mut self, cmd: &str) {
+        pub fn send_command(&mut self, cmd: &str) -> std::io::Result<()> {
             //! Input: no $ and no *checksum.
             let cmd = add_checksum(cmd.to_string());
+            self.trace_line(Direction::Sent, cmd.as_str());
             let byte_cmd = cmd.as_bytes();
-            self.port.clear(serialport::ClearBuffer::Output);
-            self.port.write(byte_cmd);
+            let _ = self.port.clear(serialport::ClearBuffer::Output);
+            self.port.write_all(byte_cmd)
         }
 
-        /// Check for a PMTK001 return.
-        pub fn pmtk_001(&mut self, search_depth: i32) -> Pmtk001Ack {
-            //! Format: $pmtk{cmd},{flag},{value}*{checksum}
-            for _i in 0..search_depth {
-                // Check 10 lines before giving up.
-                let line = self.read_line();
-                match line {
+        /// Sends the command without waiting for a reply. False if gps.chip doesn't support it or
+        /// it couldn't be written.
+        fn send_if_supported(&mut self, cmd: &str) -> bool {
+            self.check_supported(command_number(cmd)).is_ok() && self.send_command(cmd).is_ok()
+        }
+
+        /// Sends cmd until wait gives a reply, following gps.command_policy.
+        ///
+        /// Sets gps.last_attempt to the attempt that got the reply, 0 if none did.
+        fn send_with_retries<T>(&mut self, cmd: &str, wait: impl Fn(&mut Gps, Duration) -> Option<T>) -> Option<T> {
+            let timeout = self.command_policy.timeout_for(command_number(cmd));
+            for attempt in 1..=self.command_policy.retries + 1 {
+                sleep(self.command_policy.backoff_for(attempt));
+                if self.send_command(cmd).is_err() {
+                    continue;
+                }
+                if let Some(reply) = wait(self, timeout) {
+                    self.last_attempt = attempt;
+                    return Some(reply);
+                }
+            }
+            self.last_attempt = 0;
+            None
+        }
+
+        /// Reads lines until check gives Some or the timeout is up. Gives up early if the gps stops
+        /// sending anything.
+        ///
+        /// Sentences that aren't the reply are kept in gps.pending for update() to give later.
+        fn wait_for_line<T>(&mut self, timeout: Duration, check: impl Fn(&mut Gps, &str) -> Option<T>) -> Option<T> {
+            let start = Instant::now();
+            while start.elapsed() < timeout {
+                match self.read_line() {
                     PortConnection::Valid(line) => {
-                        if is_valid_checksum(line.as_str()) {
-                            if &line[0..8] == "$PMTK001" {
-                                let line = line.trim();
-                                // Remove checksum.
-                                let line: Vec<&str> = line.split("*").collect();
-                                let line: &str = line.get(0).unwrap();
-
-                                let args: Vec<&str> = line.split(",").collect();
-                                // args: $PMTK001, cmd, flag,
-                                // let cmd: &str = args.get(1).expect("pmtk001 format not correct");
-                                let flag: &str = args.get(2).expect("pmtk001 format not correct");
-                                // let value: &str = args.get(3).unwrap_or(&"");
-
-                                return if flag == "0" {
-                                    Pmtk001Ack::Invalid
-                                } else if flag == "1" {
-                                    Pmtk001Ack::Unsupported
-                                } else if flag == "2" {
-                                    Pmtk001Ack::Failed
-                                } else if flag == "3" {
-                                    Pmtk001Ack::Success
-                                } else {
-                                    Pmtk001Ack::NoPacket
-                                };
-                            } else {
-                                continue;
-                            }
+                        if let Some(reply) = check(self, line.as_str()) {
+                            return Some(reply);
                         }
+                        self.keep_sentence(line);
                     }
-                    PortConnection::NoConnection => {
-                        return Pmtk001Ack::NoPacket;
-                    }
-                    PortConnection::InvalidBytes(_) => {
-                        return Pmtk001Ack::NoPacket;
-                    }
-                };
+                    PortConnection::NoConnection => return None,
+                    PortConnection::InvalidBytes(_) => (),
+                }
+            }
+            None
+        }
+
+        /// Keeps a line read while waiting for a reply for update() to give later, if it is a sentence.
+        fn keep_sentence(&mut self, line: String) {
+            let receipt = self.line_receipt.take();
+            if !matches!(parse_line(line.as_str()), GpsSentence::InvalidSentence) {
+                // The receipts are for the newest lines, so the oldest line only has one if every
+                // line does.
+                if self.pending.len() >= MAX_PENDING && self.pending_receipts.len() >= self.pending.len() {
+                    self.pending_receipts.pop_front();
+                }
+                push_capped(&mut self.pending, line, MAX_PENDING);
+                self.pending_receipts.extend(receipt);
             }
-            return Pmtk001Ack::NoPacket;
+        }
+
+        /// Reads lines until the one wait is for. Replies to other commands are kept in
+        /// gps.stray_acks and gps.stray_replies.
+        fn wait_for(&mut self, wait: &Wait, timeout: Duration) -> Option<Waited> {
+            self.wait_for_line(timeout, |gps, line| match wait.check(line) {
+                Waited::StrayAck(ack) => {
+                    push_capped(&mut gps.stray_acks, ack, MAX_STRAY);
+                    None
+                }
+                Waited::StrayReply(reply) => {
+                    push_capped(&mut gps.stray_replies, reply, MAX_STRAY);
+                    None
+                }
+                Waited::Other => None,
+                waited => Some(waited),
+            })
+        }
+
+        fn wait_for_ack(&mut self, cmd: u16, timeout: Duration) -> Option<PmtkAck> {
+            match self.wait_for(&Wait::Ack(cmd), timeout)? {
+                Waited::Ack(ack) => Some(ack),
+                _ => None,
+            }
+        }
+
+        /// Waits for the reply packet, any PMTK reply if None.
+        fn wait_for_reply(&mut self, reply: Option<u16>, timeout: Duration) -> Option<String> {
+            match self.wait_for(&Wait::Reply(reply), timeout)? {
+                Waited::Reply(reply) => Some(reply),
+                _ => None,
+            }
+        }
+
+        fn wait_for_startup(&mut self, timeout: Duration) -> Option<()> {
+            match self.wait_for(&Wait::Startup, timeout)? {
+                Waited::Startup => Some(()),
+                _ => None,
+            }
+        }
+
+        /// Ok if gps.chip supports the packet, or the chip isn't known. Err gives the chip.
+        pub fn check_supported(&self, packet: u16) -> Result<(), Chip> {
+            match self.chip {
+                Some(chip) if !is_supported(chip, packet) => Err(chip),
+                _ => Ok(()),
+            }
+        }
+
+        /// Finds the chip from the firmware release and sets gps.chip, so commands it doesn't support
+        /// are not sent. See [chip_support](crate::chip_support).
+        pub fn detect_chip(&mut self) -> Option<Chip> {
+            self.pmtk_605_q_release()?;
+            self.chip
+        }
+
+        /// Sends a command and waits for its PMTK001 reply, resending it if there is no reply.
+        ///
+        /// The ack's attempt is the send that got the reply. See gps.command_policy.
+        pub fn send_with_ack(&mut self, cmd: &str) -> PmtkAck {
+            let number = command_number(cmd);
+            if let Err(chip) = self.check_supported(number) {
+                return PmtkAck::new(number, Pmtk001Ack::NotOnChip(chip));
+            }
+            match self.send_with_retries(cmd, |gps, timeout| gps.wait_for_ack(number, timeout)) {
+                Some(mut ack) => {
+                    ack.attempt = self.last_attempt;
+                    ack
+                }
+                None => PmtkAck::new(number, Pmtk001Ack::NoPacket),
+            }
+        }
+
+        /// Sends a query and waits for its PMTK500 style reply, the reply packet (such as 501 for
+        /// PMTK401), resending it if there is no reply.
+        ///
+        /// gps.last_attempt is the send that got the reply. See gps.command_policy.
+        pub fn send_query(&mut self, cmd: &str, reply: u16) -> Result<String, QueryError> {
+            //! QueryError::NotOnChip, without sending anything, if gps.chip doesn't support the query.
+            //!
+            //! Replies to other queries are kept in gps.stray_replies rather than being taken as
+            //! the reply to this one.
+            self.check_supported(command_number(cmd)).map_err(QueryError::NotOnChip)?;
+            self.send_with_retries(cmd, |gps, timeout| gps.wait_for_reply(Some(reply), timeout))
+                .ok_or(QueryError::NoReply)
+        }
+
+        /// Sends a restart command and waits for the startup message, resending it if there is none.
+        pub(crate) fn send_with_startup(&mut self, cmd: &str) -> bool {
+            if self.check_supported(command_number(cmd)).is_err() {
+                return false;
+            }
+            self.send_with_retries(cmd, |gps, timeout| gps.wait_for_startup(timeout)).is_some()
+        }
+
+        /// Check for the PMTK001 reply to command cmd, waiting as long as gps.command_policy gives
+        /// for that command.
+        ///
+        /// Replies to other commands, such as a late reply to an earlier command, are kept in
+        /// gps.stray_acks rather than being taken as the reply to this one.
+        pub fn wait_for_ack_to(&mut self, cmd: u16) -> PmtkAck {
+            //! Format: $PMTK001,{cmd},{flag},{value}*{checksum}
+            let timeout = self.command_policy.timeout_for(cmd);
+            self.wait_for_ack(cmd, timeout).unwrap_or_else(|| PmtkAck::new(cmd, Pmtk001Ack::NoPacket))
         }
 
         /// Check for PMTK500 style return.
         pub fn pmtk_500(&mut self) -> Option<String> {
             //! Return the string without checksum.
-            for _i in 0..10 {
-                // Check 10 lines before giving up.
-                let line = self.read_line();
-                match line {
-                    PortConnection::Valid(line) => {
-                        if (&line[0..5] == "$PMTK") && (is_valid_checksum(&line)) {
-                            let line = line.trim();
-                            // Remove checksum.
-                            let line: Vec<&str> = line.split("*").collect();
-                            let line: &str = line.get(0).unwrap();
-                            return Some(line.to_string());
-                        }
-                    }
-                    PortConnection::NoConnection => {
-                        return None;
-                    }
-                    PortConnection::InvalidBytes(_) => {
-                        return None;
-                    }
-                }
-            }
-            return None;
+            //!
+            //! PMTK001 replies are not a PMTK500 style return, they are kept in gps.stray_acks. Any
+            //! other reply is given, use send_query to wait for a given one.
+            let timeout = self.command_policy.timeout;
+            self.wait_for_reply(None, timeout)
+        }
+
+        /// Takes the PMTK001 replies that didn't match the command being waited for.
+        pub fn take_stray_acks(&mut self) -> Vec<PmtkAck> {
+            self.stray_acks.drain(..).collect()
+        }
+
+        /// Takes the PMTK500 style replies that didn't match the query being waited for, without
+        /// the checksum.
+        pub fn take_stray_replies(&mut self) -> Vec<String> {
+            self.stray_replies.drain(..).collect()
         }
 
         /// Checks if the GPS rebooted.
         pub fn pmtk_startup(&mut self) -> bool {
-            for _i in 0..10 {
-                let line = self.read_line();
-                match line {
-                    PortConnection::Valid(line) => {
-                        if (&line[0..8] == "$PMTK011") && (is_valid_checksum(&line)) {
-                            return true;
-                        }
-                    }
-                    PortConnection::NoConnection => {
-                        return false;
-                    }
-                    PortConnection::InvalidBytes(_) => {
-                        return false;
-                    }
-                }
-            }
-            false
+            let timeout = self.command_policy.timeout_for(101);
+            self.wait_for_startup(timeout).is_some()
         }
 
         /// Restart with all data intact.
         pub fn pmtk_101_cmd_hot_start(&mut self) -> bool {
-            self.send_command("PMTK101");
-            self.pmtk_startup()
+            self.send_with_startup("PMTK101")
         }
 
         /// Hot Restart without using Ephemeris data.
         pub fn pmtk_102_cmd_warm_start(&mut self) -> bool {
-            self.send_command("PMTK102");
-            self.pmtk_startup()
+            self.send_with_startup("PMTK102")
         }
 
         /// Restart with current settings, but no navigation data.
         pub fn pmtk_103_cmd_cold_start(&mut self) -> bool {
-            self.send_command("PMTK103");
-            self.pmtk_startup()
+            self.send_with_startup("PMTK103")
         }
 
         /// Full cold start resets all setting to default.
         pub fn pmtk_104_cmd_full_cold_start(&mut self) -> bool {
-            self.send_command("PMTK104");
-            self.pmtk_startup()
+            self.send_with_startup("PMTK104")
+        }
+
+        /// pmtk_104_cmd_full_cold_start for a gps that comes back up at another baud rate, such as
+        /// the one in a stored UserOption.
+        pub fn pmtk_104_cmd_full_cold_start_at(&mut self, baud_rate: u32) -> bool {
+            //! The port is switched to baud_rate after each send, before waiting for the startup
+            //! message, and back to the rate the command was sent at if there isn't one.
+            if self.check_supported(104).is_err() {
+                return false;
+            }
+            let sent_at = self.port.baud_rate().unwrap_or(baud_rate);
+            self.send_with_retries("PMTK104", |gps, timeout| {
+                let _ = gps.port.set_baud_rate(baud_rate);
+                gps.framer.clear();
+                let startup = gps.wait_for_startup(timeout);
+                if startup.is_none() {
+                    let _ = gps.port.set_baud_rate(sent_at);
+                }
+                startup
+            }).is_some()
         }
 
         /// Set the update rate, as miliseconds from 100 (100Hz) to 10_000 (0.1Hz). 1000 is default.
-        pub fn pmtk_220_set_nmea_updaterate(&mut self, update_rate: &str) -> Pmtk001Ack {
-            self.send_command(format!("PMTK220,{}", update_rate).as_str());
-            self.pmtk_001(10)
+        pub fn pmtk_220_set_nmea_updaterate(&mut self, update_rate: &str) -> PmtkAck {
+            self.send_with_ack(format!("PMTK220,{}", update_rate).as_str())
         }
 
         /// Set Differental Gps mode
-        pub fn pmtk_301_api_set_dgps_mode(&mut self, dgps_mode: DgpsMode) -> Pmtk001Ack {
-            match dgps_mode {
-                DgpsMode::NoDgps => self.send_command("PMTK301,0"),
-                DgpsMode::RTCM => self.send_command("PMTK301,1"),
-                DgpsMode::WAAS => self.send_command("PMTK301,2"),
-                DgpsMode::Unknown => (),
+        pub fn pmtk_301_api_set_dgps_mode(&mut self, dgps_mode: DgpsMode) -> PmtkAck {
+            match dgps_mode.to_args() {
+                Some(args) => self.send_with_ack(format!("PMTK301,{}", args).as_str()),
+                None => PmtkAck::new(301, Pmtk001Ack::Invalid),
             }
-
-            self.pmtk_001(10)
         }
 
-        /// Check what the current Differential Gps mode is.
+        /// Check what the current Differential Gps mode is. Unknown if there is no reply.
         pub fn pmtk_401_api_q_dgps_mode(&mut self) -> DgpsMode {
-            self.send_command("PMTK401");
-
-            // Should be just one arg.
-            return match self.pmtk_500() {
-                Some(args) => {
-                    if args.len() != 10 {
-                        // $PM TK5 01, {0,1,2}
-                        return DgpsMode::Unknown;
-                    }
-                    let mode: String = args.chars().nth_back(0).unwrap().to_string();
-                    let mode: &str = mode.as_str();
-                    if mode == "0" {
-                        return DgpsMode::NoDgps;
-                    } else if mode == "1" {
-                        DgpsMode::RTCM
-                    } else if mode == "2" {
-                        DgpsMode::WAAS
-                    } else {
-                        DgpsMode::Unknown
-                    }
-                }
-                None => DgpsMode::Unknown,
-            };
+            self.query_dgps_mode().unwrap_or(DgpsMode::Unknown)
+        }
+
+        /// pmtk_401_api_q_dgps_mode, but None if there is no $PMTK501 reply or it can't be read.
+        pub fn query_dgps_mode(&mut self) -> Option<DgpsMode> {
+            DgpsMode::from_q_reply(self.send_query("PMTK401", 501).ok()?.as_str())
         }
 
         /// Set SBAS (Satellite-based augmentation systems) enabled or disabled.
-        pub fn pmtk_313_api_set_sbas_enabled(&mut self, sbas: Sbas) -> Pmtk001Ack {
+        pub fn pmtk_313_api_set_sbas_enabled(&mut self, sbas: Sbas) -> PmtkAck {
             //! Enable = 1 -> Default.
             //!
             //! Disabled = 0
-            match sbas {
-                Sbas::Enabled => self.send_command("PMTK313,1"),
-                Sbas::Disabled => self.send_command("PMTK313,0"),
-                Sbas::Unknown => (),
+            match sbas.to_args() {
+                Some(args) => self.send_with_ack(format!("PMTK313,{}", args).as_str()),
+                None => PmtkAck::new(313, Pmtk001Ack::Invalid),
             }
-            self.pmtk_001(10)
         }
 
-        /// Check if SBAS is enabled
+        /// Check if SBAS is enabled. Unknown if there is no reply.
         pub fn pmtk_413_api_q_sbas_enabled(&mut self) -> Sbas {
-            self.send_command("PMTK413");
-            return match self.pmtk_500() {
-                Some(args) => {
-                    if args.len() != 10 {
-                        return Sbas::Unknown;
-                    }
-                    let mode = args.chars().nth_back(0).unwrap().to_string();
-                    let mode = mode.as_str();
-                    if mode == "0" {
-                        Sbas::Disabled
-                    } else if mode == "1" {
-                        Sbas::Enabled
-                    } else {
-                        Sbas::Unknown
-                    }
-                }
-                None => Sbas::Unknown,
-            };
+            self.query_sbas_enabled().unwrap_or(Sbas::Unknown)
+        }
+
+        /// pmtk_413_api_q_sbas_enabled, but None if there is no $PMTK513 reply or it can't be read.
+        pub fn query_sbas_enabled(&mut self) -> Option<Sbas> {
+            Sbas::from_q_reply(self.send_query("PMTK413", 513).ok()?.as_str())
         }
 
         /// Set what NMEA sentences are to be outputted as frequency.
@@ -382,7 +1191,7 @@ pub mod send_pmtk {
         /// - 2 -> Every second output
         /// ...
         /// - 5 -> Every 5th output
-        pub fn pmtk_314_api_set_nmea_output(&mut self, output: NmeaOutput) -> Pmtk001Ack {
+        pub fn pmtk_314_api_set_nmea_output(&mut self, output: NmeaOutput) -> PmtkAck {
             //! 19 fields can be parsed to this one.
             //!
             //! $PMTK314,{GPGLL},{GPRMC},{GPTVG},{GPGGA},{GPGAS},{GPGSV},{R}..6-17,{PMTKCHN interval}
@@ -392,52 +1201,29 @@ pub mod send_pmtk {
             //!
             //! Default is PMTK314,-1* (Default: 0,1,1,1,1,5,0..0)
 
-            self.send_command(
-                format!(
-                    "PMTK314,{},{},{},{},{},{},0,0,0,0,0,0,0,{}",
-                    output.gll, output.rmc, output.vtg, output.gga, output.gsa, output.gsv, output.pmtkchn_interval
-                )
-                .as_str(),
-            );
-            self.pmtk_001(10)
+            self.send_with_ack(format!("PMTK314,{}", output.to_args()).as_str())
         }
 
         /// Gets current NMEA output frequency.
         pub fn pmtk_414_api_q_nmea_output(&mut self) -> NmeaOutput {
             //! Return 514: PMTK514, the nmea outputs that are valid (see pmtk_314_api_set_nmea_output
             //! for the fields).
-            self.send_command("PMTK414");
-            return match self.pmtk_500() {
-                Some(args) => {
-                    let args: Vec<&str> = args.split(",").collect();
-                    let gll: &str = args.get(1).unwrap_or(&"-1");
-                    let rmc: &str = args.get(2).unwrap_or(&"-1");
-                    let vtg: &str = args.get(3).unwrap_or(&"-1");
-                    let gga: &str = args.get(4).unwrap_or(&"-1");
-                    let gsa: &str = args.get(5).unwrap_or(&"-1");
-                    let gsv: &str = args.get(6).unwrap_or(&"-1");
-                    let pmtkchn_interval: &str = args.get(18).unwrap_or(&"-1");
-
-                    NmeaOutput {
-                        gll: gll.parse::<i8>().unwrap(),
-                        rmc: rmc.parse::<i8>().unwrap(),
-                        vtg: vtg.parse::<i8>().unwrap(),
-                        gga: gga.parse::<i8>().unwrap(),
-                        gsa: gsa.parse::<i8>().unwrap(),
-                        gsv: gsv.parse::<i8>().unwrap(),
-                        pmtkchn_interval: pmtkchn_interval.parse::<i8>().unwrap(),
-                    }
-                }
-                None => NmeaOutput {
-                    gll: -1,
-                    rmc: -1,
-                    vtg: -1,
-                    gga: -1,
-                    gsa: -1,
-                    gsv: -1,
-                    pmtkchn_interval: -1,
-                },
-            };
+            //!
+            //! Every field is -1 if there is no reply.
+            self.query_nmea_output().unwrap_or(NmeaOutput {
+                gll: -1,
+                rmc: -1,
+                vtg: -1,
+                gga: -1,
+                gsa: -1,
+                gsv: -1,
+                pmtkchn_interval: -1,
+            })
+        }
+
+        /// pmtk_414_api_q_nmea_output, but None if there is no $PMTK514 reply or it can't be read.
+        pub fn query_nmea_output(&mut self) -> Option<NmeaOutput> {
+            NmeaOutput::from_q_reply(self.send_query("PMTK414", 514).ok()?.as_str())
         }
 
         /// Set SBAS mode
@@ -447,46 +1233,37 @@ pub mod send_pmtk {
             //!
             //! Get's reboot code.
             //!
-            match sbas_mode {
-                SbasMode::Integrity => self.send_command("PMTK391,1"),
-                SbasMode::Testing => self.send_command("PMTK391,0"),
-                SbasMode::Unknown => (),
+            match sbas_mode.to_args() {
+                Some(args) => self.send_with_startup(format!("PMTK319,{}", args).as_str()),
+                None => false,
             }
-            self.pmtk_startup()
         }
 
         /// Check SBAS mode
         pub fn pmtk_419_api_q_sbas_mode(&mut self) -> SbasMode {
             //! 519 response, PMTK519,{0,1} for {testing mode, integrity mode}, set by 319.
-            //! false: testing mode, true: integrity mode.
             //!
-            self.send_command("PMTK419");
-            return match self.pmtk_500() {
-                Some(args) => {
-                    let arg = args.chars().nth_back(0).unwrap().to_string();
-                    let arg = arg.as_str();
-                    if arg == "0" {
-                        SbasMode::Testing
-                    } else if arg == "1" {
-                        SbasMode::Integrity
-                    } else {
-                        SbasMode::Unknown
-                    }
-                }
-                None => SbasMode::Unknown,
-            };
+            //! Unknown if there is no reply.
+            self.query_sbas_mode().unwrap_or(SbasMode::Unknown)
+        }
+
+        /// pmtk_419_api_q_sbas_mode, but None if there is no $PMTK519 reply or it can't be read.
+        pub fn query_sbas_mode(&mut self) -> Option<SbasMode> {
+            SbasMode::from_q_reply(self.send_query("PMTK419", 519).ok()?.as_str())
         }
 
         /// Gives GPS firmware release info.
-        pub fn pmtk_605_q_release(&mut self) -> String {
+        pub fn pmtk_605_q_release(&mut self) -> Option<FirmwareRelease> {
             //! Return example: $PMTK705,AXN_5.1.7_3333_19020118,0027,PA1010D,1.0*76
             //!
-            //! Return blank string if no info found.
-            self.send_command("PMTK605");
-            return match self.pmtk_500() {
-                Some(args) => args[9..args.len()].to_string(),
-                None => "".to_string(),
-            };
+            //! See [FirmwareRelease](struct.FirmwareRelease.html). None if no info found.
+            //!
+            //! Sets gps.chip from the release.
+            let release = FirmwareRelease::parse(self.send_query("PMTK605", 705).ok()?.as_str())?;
+            if release.chip.is_some() {
+                self.chip = release.chip;
+            }
+            Some(release)
         }
 
         /// Get EPO data: Extended Prediction Orbit tries to predict where satellites will be in the future.
@@ -506,28 +1283,28 @@ pub mod send_pmtk {
             //! - 7 LCWN & LCTOW : GPS week number
             //! - 8 LCWN & LCTOW : TOW of the last set of EPO data that are currently used respectively
 
-            let args = self
-                .pmtk_500()
-                .unwrap_or("PMTK,-1,-1,-1,-1,-1,-1,-1,-1,-1".to_string());
-            let args: Vec<&str> = args.split(",").collect();
-            return EpoData {
-                set: args.get(1).unwrap_or(&"-1").parse::<i8>().unwrap(),
-                fwn_ftow_week_number: args.get(2).unwrap_or(&"-1").parse::<i8>().unwrap(),
-                fwn_ftow_tow: args.get(3).unwrap_or(&"-1").parse::<i8>().unwrap(),
-                lwn_ltow_week_number: args.get(4).unwrap_or(&"-1").parse::<i8>().unwrap(),
-                lwn_ltow_tow: args.get(5).unwrap_or(&"-1").parse::<i8>().unwrap(),
-                fcwn_fctow_week_number: args.get(6).unwrap_or(&"-1").parse::<i8>().unwrap(),
-                fcwn_fctow_tow: args.get(7).unwrap_or(&"-1").parse::<i8>().unwrap(),
-                lcwn_lctow_week_number: args.get(8).unwrap_or(&"-1").parse::<i8>().unwrap(),
-                lcwn_lctow_tow: args.get(9).unwrap_or(&"-1").parse::<i8>().unwrap(),
-            };
+            self.query_epo_info().unwrap_or(EpoData {
+                set: -1,
+                fwn_ftow_week_number: -1,
+                fwn_ftow_tow: -1,
+                lwn_ltow_week_number: -1,
+                lwn_ltow_tow: -1,
+                fcwn_fctow_week_number: -1,
+                fcwn_fctow_tow: -1,
+                lcwn_lctow_week_number: -1,
+                lcwn_lctow_tow: -1,
+            })
+        }
+
+        /// pmtk_607_q_epo_info, but None if there is no $PMTK707 reply or it can't be read.
+        pub fn query_epo_info(&mut self) -> Option<EpoData> {
+            EpoData::from_q_reply(self.send_query("PMTK607", 707).ok()?.as_str())
         }
 
         /// Clear EPO data.
-        pub fn pmtk_127_cmd_clear_epo(&mut self) -> Pmtk001Ack {
+        pub fn pmtk_127_cmd_clear_epo(&mut self) -> PmtkAck {
             //! Multiple $CLR,EPO,{000a8000}*5E lines, ending with a 001 response.
-            self.send_command("PMTK127");
-            self.pmtk_001(50) // 50 should be plenty. Probably.
+            self.send_with_ack("PMTK127")
         }
 
         /// For MT3318 and MT3329 chips.
@@ -535,10 +1312,9 @@ pub mod send_pmtk {
         /// Set the minimum number for which navigation speed is just set to 0
         ///
         /// Speed thresholds: 0/ 0.2/ 0.4/ 0.6/ 0.8/ 1.0/1.5/2.0 (m/s)
-        pub fn pmtk_397_set_nav_speed_threshold(&mut self, nav_threshold: f32) -> Pmtk001Ack {
+        pub fn pmtk_397_set_nav_speed_threshold(&mut self, nav_threshold: f32) -> PmtkAck {
             //! For MT3318 and MT3329 chips.
-            self.send_command(format!("PMTK397,{:.1}", nav_threshold).as_str());
-            self.pmtk_001(10)
+            self.send_with_ack(format!("PMTK397,{:.1}", nav_threshold).as_str())
         }
 
         /// For MT3339 chips.
@@ -546,30 +1322,31 @@ pub mod send_pmtk {
         /// Set the minimum number for which navigation speed is just set to 0
         ///
         /// Speed thresholds: 0/ 0.2/ 0.4/ 0.6/ 0.8/ 1.0/1.5/2.0 (m/s)
-        pub fn pmtk_386_set_nav_speed_threshold(&mut self, nav_threshold: f32) -> Pmtk001Ack {
+        pub fn pmtk_386_set_nav_speed_threshold(&mut self, nav_threshold: f32) -> PmtkAck {
             //! For MT3339 chips.
-            self.send_command(format!("PMTK397,{:.1}", nav_threshold).as_str());
-            self.pmtk_001(10)
+            self.send_with_ack(format!("PMTK386,{:.1}", nav_threshold).as_str())
         }
 
-        /// Gets current nav speed threshold.
+        /// Gets current nav speed threshold. -1 if there is no reply.
         pub fn pmtk_447_q_nav_threshold(&mut self) -> f32 {
             //! $PMTK527,{0.40}*04
-            self.send_command("PMTK447");
-            return match self.pmtk_500() {
-                Some(args) => {
-                    let args: Vec<&str> = args.split(",").collect();
-                    let nav_threshold: f32 = args.get(1).unwrap().parse::<f32>().unwrap();
-                    nav_threshold
-                }
-                None => return -1.0,
-            };
+            self.query_nav_threshold().unwrap_or(-1.0)
+        }
+
+        /// pmtk_447_q_nav_threshold, but None if there is no $PMTK527 reply or it can't be read.
+        pub fn query_nav_threshold(&mut self) -> Option<f32> {
+            reply_arg(self.send_query("PMTK447", 527).ok()?.as_str(), 527, 0)
         }
 
-        /// Puts gps on standby mode for power saving. Send any command to wake it up again.
-        pub fn pmtk_161_cmd_standby_mode(&mut self) -> Pmtk001Ack {
-            self.send_command("PMTK161,0");
-            self.pmtk_001(10)
+        /// Puts gps on standby mode for power saving. Use gps.wake() to wake it up again.
+        pub fn pmtk_161_cmd_standby_mode(&mut self) -> PmtkAck {
+            //! The gps stops giving output until any command is sent to it. gps.update() gives
+            //! Sleeping rather than NoConnection while it is on standby.
+            let ack = self.send_with_ack("PMTK161,0");
+            if ack == Pmtk001Ack::Success {
+                self.power_state = PowerState::Standby;
+            }
+            ack
         }
 
         /// Used with pmtk_225_cmd_periodic_mode to set periodic mode.
@@ -579,7 +1356,7 @@ pub mod send_pmtk {
             snr: i8,
             ext_threshold: i32,
             ext_gap: i32,
-        ) -> Pmtk001Ack {
+        ) -> PmtkAck {
             //! Should be used with the PMTK225 command to set periodic mode.
             //!
             //! SV: Default 1, range 1-4. Increases the time to receive more ephemeris data while the
@@ -594,88 +1371,81 @@ pub mod send_pmtk {
             //! Extention gap: Default 60000, range 0-3_600_000
             //!
             //! Standard 001 response.
-            self.send_command(
+            self.send_with_ack(
                 format!("PMTK223,{},{},{},{}", sv, snr, ext_threshold, ext_gap).as_str(),
-            );
-            self.pmtk_001(10)
+            )
         }
 
-        /// Sets periodic mode settings
-        pub fn pmtk_225_cmd_periodic_mode(
-            &mut self,
-            run_type: u8,
-            run_time: u32,
-            sleep_time: u32,
-            second_run_time: u32,
-            second_sleep_time: u32,
-        ) -> Pmtk001Ack {
+        /// Sets the power saving mode. See PowerMode.
+        pub fn pmtk_225_cmd_periodic_mode(&mut self, mode: PowerMode) -> PmtkAck {
             //! Enter standby or backup mode for power saving.
             //!
             //! PMTK225,Type,Run time,Sleep time, Second run time,Second sleep time
             //!
-            //! run_type: operation mode
-            //!     - ‘0’ = go back to normal mode
-            //!     - ‘1’ = Periodic backup mode
-            //!     - ‘2’ = Periodic standby mode
-            //!     - ‘4’ = Perpetual backup mode
-            //!     - ‘8’ = AlwaysLocateTM standby mode
-            //!     - ‘9’ = AlwaysLocateTM backup mode
-            //!
-            //! Run time (millisecond): Duration to fix for (or attempt to fix for) before switching
-            //! from running modeback to a minimum power sleep mode.
-            //!     - '0’: disable
-            //!     - >=’1,000’: enable Range: 1,000~518400000
-            //!
-            //! Sleep time (millisecond):Interval to come out of a minimum power sleep mode and start
-            //! running in order to get a new position fix.
-            //!     - ‘0’: disable
-            //!     - >=’1,000’: enable Range: 1,000~518400000
-            //!
-            //! Second run time (millisecond): Duration to fix for (or attempt to fix for) before
-            //! switching from running mode back to a minimum power sleep mode.
-            //!     - ‘0’: disable
-            //!     - >=’1,000’: enable Range: 1,000~518400000
-            //!
-            //! Second sleep time (millisecond): Interval to come out of a minimum power sleep mode and
-            //! start running in order to get a new position fix.
-            //!     - ‘0’: disable
-            //!     - >=’1,000’: enable Range: 1,000~518400000
+            //! Gives Invalid without sending anything if the times are out of range.
             //!
             //! Note：
-            //! - 1.The second run time should larger than first run time when non-zero value.
-            //! - 2.The purpose of second run time and sleep time can let module to catch more satellite
-            //!     ephemeris data in cold boot condition. The value of them can be null. Then it will
-            //!     use the first run time and sleep time for ephemeris data receiving.
-            //! - 3.AlwaysLocateTM is an intelligent controller of MT3339 power saving mode. Depending on
-            //!     the environment and motion conditions, MT3339 can adaptive adjust the on/off time
-            //!     to achieve balance of positioning accuracy and power consumption.
-            //! - 4.This command needs to work normal with some hardware circuits.
+            //! - 1.The purpose of second run time and sleep time can let module to catch more satellite
+            //!   ephemeris data in cold boot condition. The value of them can be null. Then it will
+            //!   use the first run time and sleep time for ephemeris data receiving.
+            //! - 2.AlwaysLocateTM is an intelligent controller of MT3339 power saving mode. Depending on
+            //!   the environment and motion conditions, MT3339 can adaptive adjust the on/off time
+            //!   to achieve balance of positioning accuracy and power consumption.
+            //! - 3.This command needs to work normal with some hardware circuits.
             //!
-            self.send_command(
-                format!(
-                    "PMTK223,{},{},{},{},{}",
-                    run_type, run_time, sleep_time, second_run_time, second_sleep_time
-                )
-                .as_str(),
-            );
-            self.pmtk_001(10)
+            //! On success gps.power_state is updated to match.
+            let args = match mode.to_args() {
+                Some(args) => args,
+                None => return PmtkAck::new(225, Pmtk001Ack::Invalid),
+            };
+            let ack = self.send_with_ack(format!("PMTK225,{}", args).as_str());
+            if ack == Pmtk001Ack::Success {
+                self.power_state = mode.power_state();
+            }
+            ack
+        }
+
+        /// Wakes the gps from standby or periodic mode. Returns true if the gps is giving output again.
+        ///
+        /// Standby is woken by sending any command. Periodic modes are turned off, back to normal mode.
+        /// Backup mode can only be woken by the hardware (the FORCE_ON or WAKE UP pin) so this
+        /// just checks for output.
+        pub fn wake(&mut self) -> bool {
+            let cmd = match self.power_state {
+                PowerState::Running => return true,
+                PowerState::Standby => Some("PMTK000"),
+                PowerState::Periodic(_) => Some("PMTK225,0"),
+                PowerState::Backup => None,
+            };
+            if let Some(cmd) = cmd {
+                if !self.send_if_supported(cmd) {
+                    return false;
+                }
+            }
+            match self.read_line() {
+                PortConnection::Valid(line) => {
+                    self.power_state = PowerState::Running;
+                    self.keep_sentence(line);
+                    true
+                }
+                _ => false,
+            }
         }
 
         /// Active Interference Calcellation to counter jamming an enterfearance.
         ///
         /// True: enable, false: disabled.
-        pub fn pmtk_286_cmd_aic_mode(&mut self, aic: bool) -> Pmtk001Ack {
+        pub fn pmtk_286_cmd_aic_mode(&mut self, aic: bool) -> PmtkAck {
             //! true is enable, false is disable.
             if aic {
-                self.send_command("PMTK286,1")
+                self.send_with_ack("PMTK286,1")
             } else {
-                self.send_command("PMTK286,0")
+                self.send_with_ack("PMTK286,0")
             }
-            self.pmtk_001(10)
         }
 
         /// Set EASY status. True: enable, False: disable.
-        pub fn pmtk_869_cmd_easy_enable(&mut self, enable_easy: bool) -> Pmtk001Ack {
+        pub fn pmtk_869_cmd_easy_enable(&mut self, enable_easy: bool) -> PmtkAck {
             //! Enable or disable EASY function.
             //!
             //! Enabled by default.
@@ -693,40 +1463,34 @@ pub mod send_pmtk {
             //! - pmtk,1,0 -> Gives 001 reply.
             //! - pmtk,2,{0,1} -> Gives 001 reply.
             if enable_easy {
-                self.send_command("PMTK869,1,1")
+                self.send_with_ack("PMTK869,1,1")
             } else {
-                self.send_command("PMTK869,1,0")
+                self.send_with_ack("PMTK869,1,0")
             }
-            self.pmtk_001(10)
         }
 
         /// Get current EASY status
         pub fn pmtk_869_cmd_easy_query(&mut self) -> bool {
             //! Query the EASY command status. Return true or false, true is enabled, false it disabled.
-            self.send_command("PMTK869,0");
-            return match self.pmtk_500() {
-                Some(args) => {
-                    let args: Vec<&str> = args.split(",").collect();
-                    if args.get(2).unwrap() == &"0" {
-                        false
-                    } else {
-                        true
-                    }
-                }
-                None => true,
-            };
+            //!
+            //! True if there is no reply.
+            self.query_easy().unwrap_or(true)
+        }
+
+        /// pmtk_869_cmd_easy_query, but None if there is no $PMTK869,2 reply or it can't be read.
+        pub fn query_easy(&mut self) -> Option<bool> {
+            easy_from_q_reply(self.send_query("PMTK869,0", 869).ok()?.as_str())
         }
 
         /// Configure LOCUS interval, seconds.
-        pub fn pmtk_187_locus_config(&mut self, locus_interval: i8) -> Pmtk001Ack {
+        pub fn pmtk_187_locus_config(&mut self, locus_interval: i8) -> PmtkAck {
             //! Locus mode (1 for interval mode) is always on.
             //! Interval, in seconds, is how often to log a data.
-            self.send_command(format!("PMTK187,1,{}", locus_interval).as_str());
-            self.pmtk_001(10)
+            self.send_with_ack(format!("PMTK187,1,{}", locus_interval).as_str())
         }
 
-        /// Get DATUM, 0 = WGS84 (default).
-        pub fn pmtk_330_api_set_datum(&mut self, datum: u16) -> Pmtk001Ack {
+        /// Set DATUM, Datum::WGS84 (default).
+        pub fn pmtk_330_api_set_datum(&mut self, datum: Datum) -> PmtkAck {
             //! Configure Datum. 222 datum options.
             //!
             //! ‘0’ = WGS84
@@ -735,61 +1499,365 @@ pub mod send_pmtk {
             //!
             //! ‘2’ = TOKYO-A
             //!
-            //! A full list is on the GTOP Datum list, but I can't find it.
-            self.send_command(format!("PMTK330,{}", datum).as_str());
-            self.pmtk_001(10)
+            //! See [Datum](crate::Datum) and DATUMS for the others. Gives Invalid
+            //! without sending anything if the number is 222 or more.
+            if datum.0 >= Datum::COUNT {
+                return PmtkAck::new(330, Pmtk001Ack::Invalid);
+            }
+            self.send_with_ack(format!("PMTK330,{}", datum.0).as_str())
         }
 
         /// Query current DATUM
-        pub fn pmtk_430_api_q_datum(&mut self) -> u16 {
+        pub fn pmtk_430_api_q_datum(&mut self) -> Option<Datum> {
             //! Query current datum. Gives PMTK530,datum
             //! See pmtk_330_api_set_datum for more details on datum.
             //!
-            //! 0 is return value if there is an error.
-            self.send_command("PMTK430");
-            return match self.pmtk_500() {
-                Some(args) => {
-                    let args: Vec<&str> = args.split(",").collect();
-                    let datum = args.get(1).unwrap_or(&"0").parse::<u16>().unwrap();
-                    datum
-                }
-                None => 0,
-            };
+            //! None if there is an error.
+            reply_arg(self.send_query("PMTK430", 530).ok()?.as_str(), 530, 0).map(Datum)
+        }
+
+        /// Store default settings in the gps flash. See [UserOption](struct.UserOption.html).
+        pub fn pmtk_390_api_set_user_option(&mut self, option: &UserOption) -> PmtkAck {
+            //! The new defaults are used after the next full cold start, pmtk_104_cmd_full_cold_start,
+            //! or power on without a backup battery. gps.verify_user_option checks they were kept.
+            //!
+            //! Gives Invalid without sending anything if a setting is out of range.
+            match option.to_args() {
+                Some(args) => self.send_with_ack(format!("PMTK390,{}", args).as_str()),
+                None => PmtkAck::new(390, Pmtk001Ack::Invalid),
+            }
+        }
+
+        /// Query the defaults stored in flash. Gives PMTK590, in the PMTK390 order.
+        pub fn pmtk_490_api_q_user_option(&mut self) -> Option<UserOption> {
+            //! None if there is no reply, or it can't be read.
+            let reply = self.send_query("PMTK490", 590).ok()?;
+            UserOption::from_q_reply(reply.as_str())
         }
 
         /// The receiver support new NMEA format for QZSS. The command allow user enable or disable QZSS
         /// NMEA format. Default is disable QZSS NMEA format. (use NMEA 0183 v3.1)
-        pub fn pmtk_351_api_set_support_qzss_nmea(&mut self, enable_qzss: bool) -> Pmtk001Ack {
+        pub fn pmtk_351_api_set_support_qzss_nmea(&mut self, enable_qzss: bool) -> PmtkAck {
             //! Sets the output to be the QZSS NMEA format.
             //!
             //! True is enable, false is disable. Default is disable.
             if enable_qzss {
-                self.send_command("PMTK351,1")
+                self.send_with_ack("PMTK351,1")
             } else {
-                self.send_command("PMTK351,0")
+                self.send_with_ack("PMTK351,0")
             }
-            self.pmtk_001(10)
         }
 
         /// Since QZSS is regional positioning service. The command allow user enable or disable QZSS function.
         /// Default is enable QZSS function
-        pub fn pmtk_352_api_set_stop_qzss(&mut self, enable: bool) -> Pmtk001Ack {
+        pub fn pmtk_352_api_set_stop_qzss(&mut self, enable: bool) -> PmtkAck {
             //! Since QZSS is regional positioning service. The command allow user enable or disable QZSS function.
             //!
             //! Default is enable QZSS function
             //!
             //! Enable is true, disable is false. Default is enable.
             if enable {
-                self.send_command("PMTK352,0")
+                self.send_with_ack("PMTK352,0")
+            } else {
+                self.send_with_ack("PMTK352,1")
+            }
+        }
+
+        /// Set which satellite systems are searched. For MT3333 chips.
+        pub fn pmtk_353_api_set_gnss_search_mode(&mut self, search_mode: GnssSearchMode) -> PmtkAck {
+            //! PMTK353,GPS,GLONASS,GALILEO,GALILEO_FULL,BEIDOU, 1 is enable, 0 is disable.
+            //!
+            //! The gps restarts its search after this command. Gives Invalid without sending anything
+            //! if no system is enabled.
+            //!
+            //! On success, gps.update() expects GGA, RMC, VTG and GLL from the talker for the search
+            //! mode, GN if more than one system. Standard 001 response.
+            let talker = match search_mode.expected_talker() {
+                Some(talker) => talker,
+                None => return PmtkAck::new(353, Pmtk001Ack::Invalid),
+            };
+            let ack = self.send_with_ack(format!("PMTK353,{}", search_mode.to_args()).as_str());
+            if ack == Pmtk001Ack::Success {
+                self.talker = Some(talker);
+            }
+            ack
+        }
+
+        /// Query which satellite systems are searched. For MT3333 chips.
+        pub fn pmtk_355_api_q_gnss_search_mode(&mut self) -> Option<GnssSearchMode> {
+            //! Reply is $PMTK001,355,3,GLONASS,BEIDOU,GALILEO with 1 for enabled.
+            //!
+            //! Also sets the talker that gps.update() expects. None if there is no reply.
+            let ack = self.send_with_ack("PMTK355");
+            if ack.flag != Pmtk001Ack::Success {
+                return None;
+            }
+            let search_mode = GnssSearchMode::from_q_value(ack.value?.as_str())?;
+            self.talker = search_mode.expected_talker();
+            Some(search_mode)
+        }
+
+        /// Set the minimum SNR for a satellite to be used in the fix.
+        pub fn pmtk_306_api_set_min_snr(&mut self, min_snr: u8) -> PmtkAck {
+            //! SNR in dB-Hz, 9-37 (see MIN_SNR_RANGE). Gives Invalid without sending anything if out
+            //! of range.
+            //!
+            //! Raising it stops weak, reflected signals being used. Standard 001 response.
+            if !MIN_SNR_RANGE.contains(&min_snr) {
+                return PmtkAck::new(306, Pmtk001Ack::Invalid);
+            }
+            self.send_with_ack(format!("PMTK306,{}", min_snr).as_str())
+        }
+
+        /// Query the minimum SNR for a satellite to be used in the fix.
+        pub fn pmtk_406_api_q_min_snr(&mut self) -> Option<u8> {
+            //! Reply: $PMTK506,{snr}. None if there is no reply.
+            reply_arg(self.send_query("PMTK406", 506).ok()?.as_str(), 506, 0)
+        }
+
+        /// Set the elevation mask: satellites below this elevation are not used in the fix.
+        pub fn pmtk_311_api_set_elev_mask(&mut self, elevation: u8) -> PmtkAck {
+            //! Elevation in degrees, 0-90 (see ELEVATION_MASK_RANGE). Gives Invalid without sending
+            //! anything if out of range.
+            //!
+            //! Low satellites are the most affected by multipath near buildings. Standard 001 response.
+            if !ELEVATION_MASK_RANGE.contains(&elevation) {
+                return PmtkAck::new(311, Pmtk001Ack::Invalid);
+            }
+            self.send_with_ack(format!("PMTK311,{}", elevation).as_str())
+        }
+
+        /// Query the elevation mask.
+        pub fn pmtk_411_api_q_elev_mask(&mut self) -> Option<u8> {
+            //! Reply: $PMTK511,{degrees}. None if there is no reply.
+            reply_arg(self.send_query("PMTK411", 511).ok()?.as_str(), 511, 0)
+        }
+
+        /// Line up the NMEA output with the 1PPS pulse. True: enable, false: disable (default).
+        pub fn pmtk_255_set_sync_pps_nmea(&mut self, enable: bool) -> PmtkAck {
+            //! When enabled, the sentences for a fix are given straight after the pulse for that
+            //! second, so the UTC in the next RMC (or ZDA) is the time of the pulse.
+            //!
+            //! There is no query packet for this setting. Standard 001 response.
+            if enable {
+                self.send_with_ack("PMTK255,1")
             } else {
-                self.send_command("PMTK352,1")
+                self.send_with_ack("PMTK255,0")
+            }
+        }
+
+        /// Set the 1PPS output mode and pulse width.
+        pub fn pmtk_285_set_pps_config(&mut self, pps_config: PpsConfig) -> PmtkAck {
+            //! PMTK285,Type,Pulse width
+            //!
+            //! Type: 0 disabled, 1 after first fix, 2 3D fix only, 3 2D/3D fix only, 4 always.
+            //!
+            //! Pulse width (millisecond): 2-998. Gives Invalid without sending anything if out of range.
+            //!
+            //! There is no query packet for this setting. Standard 001 response.
+            match pps_config.to_args() {
+                Some(args) => self.send_with_ack(format!("PMTK285,{}", args).as_str()),
+                None => PmtkAck::new(285, Pmtk001Ack::Invalid),
             }
-            self.pmtk_001(10)
+        }
+
+        /// Give the gps the current UTC to speed up the time to first fix.
+        pub fn pmtk_740_set_time(&mut self, utc: &UtcDateTime) -> PmtkAck {
+            //! PMTK740,YYYY,MM,DD,hh,mm,ss
+            //!
+            //! The time has to be UTC, and within a few seconds to be of any use.
+            //!
+            //! Standard 001 response.
+            self.send_with_ack(format!("PMTK740,{}", aiding_time_args(utc)).as_str())
+        }
+
+        /// Give the gps a known position and the current UTC to speed up the time to first fix.
+        pub fn pmtk_741_set_pos_time(&mut self, position: &AidingPosition, utc: &UtcDateTime) -> PmtkAck {
+            //! PMTK741,Lat,Long,Alt,YYYY,MM,DD,hh,mm,ss
+            //!
+            //! Lat and long in degrees, alt in metres. The position should be within a few
+            //! kilometres of the real one for it to help.
+            //!
+            //! Standard 001 response.
+            self.send_with_ack(format!("PMTK741,{}", position.to_args(utc)).as_str())
+        }
+
+        /// Warm start the gps and aid it with the last known fix and the current time.
+        ///
+        /// last_fix is the last known position, for example the last one in a log file:
+        /// ```ignore
+        /// let last_fix = AidingPosition::from_log(&GpsSentence::read_from("gps_log"));
+        /// gps.warm_start_with_aiding(last_fix.as_ref(), &UtcDateTime::now());
+        /// ```
+        /// If there is no fix, only the time is given.
+        pub fn warm_start_with_aiding(&mut self, last_fix: Option<&AidingPosition>, now: &UtcDateTime) -> AidingResult {
+            let restarted = self.pmtk_102_cmd_warm_start();
+            let aiding = match last_fix {
+                Some(position) => self.pmtk_741_set_pos_time(position, now),
+                None => self.pmtk_740_set_time(now),
+            };
+            AidingResult { restarted, aiding }
         }
     }
 }
 
 
+
+pub mod chip_support {
+    //! Which PMTK packets each chip supports, from the table in Resources/specs.md.
+    //!
+    //! gps.chip is set by gps.detect_chip() (or any pmtk_605_q_release). Once it is set, commands the
+    //! chip doesn't support give Pmtk001Ack::NotOnChip without being sent, and queries give no reply.
+    //! Packets that are unknown (? in the table) for the chip are sent anyway.
+    //! ```ignore
+    //! gps.detect_chip();
+    //! if gps.supports(386) { gps.pmtk_386_set_nav_speed_threshold(0.4); }
+    //! let standby_chips = packet_support(161).unwrap().chips();
+    //! ```
+    use super::super::open_gps::gps::Gps;
+    use super::send_pmtk::Chip;
+
+    /// Whether a chip supports a packet: y, n or ? in the table.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum Support {
+        Yes,
+        No,
+        /// No datasheet says either way. The packet is sent, and the PMTK001 reply tells.
+        Unknown,
+    }
+
+    /// One row of the table: the packet number, its name and whether each chip supports it.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct PacketSupport {
+        pub packet: u16,
+        pub name: &'static str,
+        pub mt3318: Support,
+        pub mt3329: Support,
+        pub mt3339: Support,
+        pub mt3333: Support,
+    }
+
+    impl PacketSupport {
+        /// The table entry for the chip. Chip::Other is taken to support everything.
+        pub fn support_on(&self, chip: Chip) -> Support {
+            match chip {
+                Chip::MT3318 => self.mt3318,
+                Chip::MT3329 => self.mt3329,
+                Chip::MT3339 => self.mt3339,
+                Chip::MT3333 => self.mt3333,
+                Chip::Other(_) => Support::Yes,
+            }
+        }
+
+        /// True unless the chip is known not to support the packet, so Unknown gives true.
+        pub fn supported_on(&self, chip: Chip) -> bool {
+            self.support_on(chip) != Support::No
+        }
+
+        /// The chips that are known to support the packet.
+        pub fn chips(&self) -> Vec<Chip> {
+            [Chip::MT3318, Chip::MT3329, Chip::MT3339, Chip::MT3333].iter()
+                .filter(|chip| self.support_on(**chip) == Support::Yes)
+                .cloned()
+                .collect()
+        }
+    }
+
+    const fn row(packet: u16, name: &'static str, chips: [Support; 4]) -> PacketSupport {
+        PacketSupport { packet, name, mt3318: chips[0], mt3329: chips[1], mt3339: chips[2], mt3333: chips[3] }
+    }
+
+    use Support::{No as N, Unknown as U, Yes as Y};
+
+    // No source has been found for the MT3333 column, so it is all unknown.
+    const ALL: [Support; 4] = [Y, Y, Y, U];
+    const NEWER: [Support; 4] = [N, N, Y, U];
+    const UNKNOWN: [Support; 4] = [U, U, U, U];
+
+    /// The table in Resources/specs.md. Columns are MT3318, MT3329, MT3339, MT3333.
+    pub const PACKET_SUPPORT: &[PacketSupport] = &[
+        row(1, "PMTK_ACK", ALL),
+        row(10, "PMTK_SYS_MSG", ALL),
+        row(11, "PMTK_TXT_MSG", ALL),
+        row(101, "PMTK_CMD_HOT_START", ALL),
+        row(102, "PMTK_CMD_WARM_START", ALL),
+        row(103, "PMTK_CMD_COLD_START", ALL),
+        row(104, "PMTK_CMD_FULL_COLD_START", ALL),
+        row(220, "PMTK_SET_NMEA_UPDATERATE", ALL),
+        row(251, "PMTK_SET_NMEA_BAUDRATE", ALL),
+        row(301, "PMTK_API_SET_DGPS_MODE", ALL),
+        row(401, "PMTK_API_Q_DGPS_MODE", ALL),
+        row(501, "PMTK_API_DT_DGPS_MODE", ALL),
+        row(313, "PMTK_API_SET_SBAS_ENABLED", ALL),
+        row(413, "PMTK_API_Q_SBAS_ENABLED", ALL),
+        row(513, "PMTK_DT_SBAS_ENABLED", ALL),
+        row(314, "PMTK_API_SET_NMEA_OUTPUT", ALL),
+        row(414, "PMTK_API_Q_NMEA_OUTPUT", ALL),
+        row(514, "PMTK_API_DT_NMEA_OUTPUT", ALL),
+        row(319, "PMTK_API_SET_SBAS_Mode", ALL),
+        row(419, "PMTK_API_Q_SBAS_Mode", ALL),
+        row(519, "PMTK_API_DT_SBAS_Mode", ALL),
+        row(605, "PMTK_Q_RELEASE", ALL),
+        row(705, "PMTK_DT_RELEASE", ALL),
+        row(607, "PMTK_Q_EPO_INFO", ALL),
+        row(707, "PMTK_DT_EPO_INFO", ALL),
+        row(127, "PMTK_CMD_CLEAR_EPO", ALL),
+        row(397, "PMTK_SET_Nav Speed threshold", [Y, Y, N, U]),
+        row(386, "PMTK_SET_Nav Speed threshold", NEWER),
+        row(447, "PMTK_Q_Nav_Threshold", ALL),
+        row(527, "PMTK_DT_Nav_Threshold", ALL),
+        row(161, "PMTK_CMD_STANDBY_MODE", NEWER),
+        row(223, "PMTK_SET_AL_DEE_CFG", NEWER),
+        row(225, "PMTK_CMD_PERIODIC_MODE", NEWER),
+        row(286, "PMTK_CMD_AIC_MODE", NEWER),
+        row(869, "PMTK_CMD_EASY_ENABLE", NEWER),
+        row(187, "PMTK_LOCUS_CONFIG", NEWER),
+        row(330, "PMTK_API_SET_DATUM", ALL),
+        row(430, "PMTK_API_Q_DATUM", ALL),
+        row(530, "PMTK_API_DT_DATUM", ALL),
+        row(351, "PMTK_API_SET_SUPPORT_QZSS_NMEA", NEWER),
+        row(352, "PMTK_API_SET_STOP_QZSS", NEWER),
+        row(306, "PMTK_API_SET_MIN_SNR", UNKNOWN),
+        row(406, "PMTK_API_Q_MIN_SNR", UNKNOWN),
+        row(506, "PMTK_API_DT_MIN_SNR", UNKNOWN),
+        row(311, "PMTK_API_SET_ELEV_MASK", UNKNOWN),
+        row(411, "PMTK_API_Q_ELEV_MASK", UNKNOWN),
+        row(511, "PMTK_API_DT_ELEV_MASK", UNKNOWN),
+        row(255, "PMTK_SET_SYNC_PPS_NMEA", UNKNOWN),
+        row(285, "PMTK_SET_PPS_CONFIG", UNKNOWN),
+        row(353, "PMTK_API_SET_GNSS_SEARCH_MODE", UNKNOWN),
+        row(355, "PMTK_API_Q_GNSS_SEARCH_MODE", UNKNOWN),
+        row(390, "PMTK_API_SET_USER_OPTION", UNKNOWN),
+        row(490, "PMTK_API_Q_USER_OPTION", UNKNOWN),
+        row(590, "PMTK_API_DT_USER_OPTION", UNKNOWN),
+        row(740, "PMTK_DT_UTC", UNKNOWN),
+        row(741, "PMTK_DT_POS", UNKNOWN),
+    ];
+
+    /// The table row for a packet, None if it isn't in the table.
+    pub fn packet_support(packet: u16) -> Option<&'static PacketSupport> {
+        PACKET_SUPPORT.iter().find(|row| row.packet == packet)
+    }
+
+    /// False if the chip is known not to support the packet. Packets that aren't in the table, or
+    /// are unknown for the chip, are taken to be supported.
+    pub fn is_supported(chip: Chip, packet: u16) -> bool {
+        packet_support(packet).map_or(true, |row| row.supported_on(chip))
+    }
+
+    /// Every packet in the table the chip supports, or may support.
+    pub fn supported_packets(chip: Chip) -> Vec<u16> {
+        PACKET_SUPPORT.iter().filter(|row| row.supported_on(chip)).map(|row| row.packet).collect()
+    }
+
+    impl Gps {
+        /// True if gps.chip supports the packet, or if the chip isn't known yet.
+        pub fn supports(&self, packet: u16) -> bool {
+            self.check_supported(packet).is_ok()
+        }
+    }
+}
+
 #[cfg(test)]
 mod checksum_test {
     use crate::pmtk::send_pmtk::add_checksum;
@@ -807,11 +1875,418 @@ mod checksum_test {
     }
 }
 
+#[cfg(test)]
+mod aiding_test {
+    use crate::GpsSentence;
+    use crate::nmea::gga::GgaData;
+    use crate::pmtk::send_pmtk::{aiding_time_args, AidingPosition};
+    use crate::timing::utc::UtcDateTime;
+
+    #[test]
+    fn aiding_args() {
+        let utc = UtcDateTime { year: 2020, month: 5, day: 2, hour: 8, minute: 4, second: 9.5 };
+        assert_eq!(aiding_time_args(&utc), "2020,05,02,08,04,09");
+        let position = AidingPosition { latitude: 51.55465, longitude: -0.05632, altitude: 42.5 };
+        assert_eq!(position.to_args(&utc), "51.554649,-0.056320,42.5,2020,05,02,08,04,09");
+    }
+
+    #[test]
+    fn last_fix_from_log() {
+        let fix = GgaData { lat: Some(51.5), long: Some(-0.1), msl_alt: Some(40.0), ..GgaData::default() };
+        let log = vec![GpsSentence::GGA(fix), GpsSentence::NoConnection, GpsSentence::GGA(GgaData::default())];
+        assert_eq!(
+            AidingPosition::from_log(&log),
+            Some(AidingPosition { latitude: 51.5, longitude: -0.1, altitude: 40.0 })
+        );
+        assert_eq!(AidingPosition::from_log(&[GpsSentence::InvalidSentence]), None);
+    }
+}
+
+#[cfg(test)]
+mod gnss_search_mode_test {
+    use crate::nmea::parse_nmea::Talker;
+    use crate::pmtk::send_pmtk::GnssSearchMode;
+
+    #[test]
+    fn search_mode() {
+        let gps_only = GnssSearchMode { gps: true, glonass: false, galileo: false, beidou: false };
+        assert_eq!(gps_only.expected_talker(), Some(Talker::Gps));
+        assert_eq!(gps_only.to_args(), "1,0,0,0,0");
+
+        let gps_glonass = GnssSearchMode { gps: true, glonass: true, galileo: false, beidou: false };
+        assert_eq!(gps_glonass.expected_talker(), Some(Talker::Multi));
+
+        let none = GnssSearchMode { gps: false, glonass: false, galileo: false, beidou: false };
+        assert_eq!(none.expected_talker(), None);
+    }
+
+    #[test]
+    fn search_mode_reply() {
+        assert_eq!(
+            GnssSearchMode::from_q_value("1,0,1"),
+            Some(GnssSearchMode { gps: true, glonass: true, galileo: true, beidou: false })
+        );
+        assert_eq!(GnssSearchMode::from_q_value("1"), None);
+    }
+}
+
+#[cfg(test)]
+mod power_mode_test {
+    use std::time::Duration;
+
+    use crate::pmtk::send_pmtk::{PeriodicTimes, PowerMode};
+
+    fn times(run: u64, sleep: u64, second_run: u64, second_sleep: u64) -> PeriodicTimes {
+        PeriodicTimes {
+            run_time: Duration::from_millis(run),
+            sleep_time: Duration::from_millis(sleep),
+            second_run_time: Duration::from_millis(second_run),
+            second_sleep_time: Duration::from_millis(second_sleep),
+        }
+    }
+
+    #[test]
+    fn power_mode_args() {
+        assert_eq!(PowerMode::Normal.to_args(), Some("0".to_string()));
+        assert_eq!(PowerMode::AlwaysLocateBackup.to_args(), Some("9".to_string()));
+        assert_eq!(
+            PowerMode::PeriodicBackup(times(3000, 12000, 18000, 72000)).to_args(),
+            Some("1,3000,12000,18000,72000".to_string())
+        );
+        assert_eq!(
+            PowerMode::PeriodicStandby(times(3000, 12000, 0, 0)).to_args(),
+            Some("2,3000,12000,0,0".to_string())
+        );
+    }
+
+    #[test]
+    fn power_mode_out_of_range() {
+        // Under a second, over 6 days, no run time, second run time shorter than the first.
+        assert_eq!(PowerMode::PeriodicStandby(times(500, 12000, 0, 0)).to_args(), None);
+        assert_eq!(PowerMode::PeriodicStandby(times(3000, 518_400_001, 0, 0)).to_args(), None);
+        assert_eq!(PowerMode::PeriodicBackup(times(0, 12000, 0, 0)).to_args(), None);
+        assert_eq!(PowerMode::PeriodicBackup(times(3000, 12000, 2000, 72000)).to_args(), None);
+    }
+}
+
+#[cfg(test)]
+mod ack_test {
+    use std::time::Duration;
+
+    use crate::open_gps::gps::{GpsSentence, MAX_PENDING, MAX_STRAY};
+    use crate::open_gps::mock_port::{mock_gps, mock_gps_with_replies};
+    use crate::pmtk::send_pmtk::{add_checksum, CommandPolicy, Pmtk001Ack, PmtkAck, Sbas};
+
+    #[test]
+    fn parse_ack() {
+        assert_eq!(
+            PmtkAck::parse(add_checksum("PMTK001,355,3,1,0,1".to_string()).as_str()),
+            Some(PmtkAck { cmd: 355, flag: Pmtk001Ack::Success, value: Some("1,0,1".to_string()), attempt: 0 })
+        );
+        assert_eq!(PmtkAck::parse(add_checksum("PMTK001,220,2".to_string()).as_str()), Some(PmtkAck::new(220, Pmtk001Ack::Failed)));
+        assert_eq!(PmtkAck::parse("$PMTK001,220,3*00\r\n"), None);
+        assert_eq!(PmtkAck::parse("$PMTK001"), None);
+        assert_eq!(PmtkAck::parse(""), None);
+    }
+
+    #[test]
+    fn late_ack_is_not_credited_to_next_command() {
+        let late_314 = add_checksum("PMTK001,314,2".to_string());
+        let ack_220 = add_checksum("PMTK001,220,3".to_string());
+        let (mut gps, written) = mock_gps(&[late_314.as_str(), ack_220.as_str()]);
+
+        let ack = gps.pmtk_220_set_nmea_updaterate("1000");
+        assert_eq!(ack, PmtkAck { cmd: 220, flag: Pmtk001Ack::Success, value: None, attempt: 1 });
+        assert_eq!(ack, Pmtk001Ack::Success);
+        assert_eq!(gps.take_stray_acks(), vec![PmtkAck::new(314, Pmtk001Ack::Failed)]);
+        assert!(gps.take_stray_acks().is_empty());
+        assert_eq!(String::from_utf8(written.lock().unwrap().clone()).unwrap(), add_checksum("PMTK220,1000".to_string()));
+    }
+
+    #[test]
+    fn late_reply_is_not_taken_for_next_query() {
+        let late_530 = add_checksum("PMTK530,0".to_string());
+        let late_705 = add_checksum("PMTK705,AXN_2.31_3339_13101700,5632,PA6H,1.0".to_string());
+        let reply_513 = add_checksum("PMTK513,1".to_string());
+        let reply_527 = add_checksum("PMTK527,0.40".to_string());
+        let (mut gps, _written) = mock_gps(&[late_530.as_str(), reply_513.as_str(), late_705.as_str(), reply_527.as_str()]);
+
+        assert_eq!(gps.pmtk_413_api_q_sbas_enabled(), Sbas::Enabled);
+        assert_eq!(gps.pmtk_447_q_nav_threshold(), 0.4);
+        assert_eq!(gps.take_stray_replies(), vec!["$PMTK530,0", "$PMTK705,AXN_2.31_3339_13101700,5632,PA6H,1.0"]);
+        assert!(gps.take_stray_replies().is_empty());
+    }
+
+    #[test]
+    fn kept_lines_are_capped() {
+        let gga = "$GNGGA,131613.000,5132.7314,N,00005.9099,W,1,9,1.17,42.4,M,47.0,M,,*60\r\n";
+        let strays: Vec<String> = (0..MAX_STRAY + 2).map(|i| add_checksum(format!("PMTK001,{},3", 300 + i))).collect();
+        let mut lines: Vec<&str> = vec![gga; MAX_PENDING + 1];
+        lines.extend(strays.iter().map(|s| s.as_str()));
+        let ack_220 = add_checksum("PMTK001,220,3".to_string());
+        lines.push(ack_220.as_str());
+        let (mut gps, _written) = mock_gps(&lines);
+
+        assert_eq!(gps.pmtk_220_set_nmea_updaterate("1000"), Pmtk001Ack::Success);
+        assert_eq!(gps.pending.len(), MAX_PENDING);
+        let strays = gps.take_stray_acks();
+        assert_eq!(strays.len(), MAX_STRAY);
+        assert_eq!(strays[0].cmd, 302);
+    }
+
+    #[test]
+    fn sentences_read_while_waiting_are_kept() {
+        let gga = "$GNGGA,131613.000,5132.7314,N,00005.9099,W,1,9,1.17,42.4,M,47.0,M,,*60\r\n";
+        let vtg = add_checksum("GNVTG,0.00,T,,M,0.00,N,0.00,K,A".to_string());
+        let ack_220 = add_checksum("PMTK001,220,3".to_string());
+        let (mut gps, _written) = mock_gps(&[gga, ack_220.as_str(), vtg.as_str()]);
+
+        assert_eq!(gps.pmtk_220_set_nmea_updaterate("1000"), Pmtk001Ack::Success);
+        assert_eq!(gps.pending.len(), 1);
+        match gps.update() {
+            GpsSentence::GGA(gga) => assert_eq!(gga.utc, 131613.0),
+            sentence => panic!("Expected the GGA read while waiting, got {:?}", sentence),
+        }
+        assert!(matches!(gps.update(), GpsSentence::VTG(_)));
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let policy = CommandPolicy::default();
+        assert_eq!(policy.backoff_for(1), Duration::from_secs(0));
+        assert_eq!(policy.backoff_for(2), Duration::from_millis(100));
+        assert_eq!(policy.backoff_for(4), Duration::from_millis(400));
+        assert_eq!(policy.timeout_for(127), Duration::from_secs(10));
+        assert_eq!(policy.timeout_for(220), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn unanswered_command_is_resent() {
+        let ack_220 = add_checksum("PMTK001,220,3".to_string());
+        let (mut gps, written) = mock_gps_with_replies(&[], &["", ack_220.as_str()]);
+        gps.command_policy.backoff = Duration::from_millis(0);
+
+        let ack = gps.pmtk_220_set_nmea_updaterate("1000");
+        assert_eq!(ack, Pmtk001Ack::Success);
+        assert_eq!(ack.attempt, 2);
+        assert_eq!(gps.last_attempt, 2);
+        assert_eq!(String::from_utf8(written.lock().unwrap().clone()).unwrap(), add_checksum("PMTK220,1000".to_string()).repeat(2));
+    }
+
+    #[test]
+    fn gives_up_after_retries() {
+        let (mut gps, written) = mock_gps_with_replies(&[], &[]);
+        gps.command_policy.retries = 1;
+        gps.command_policy.backoff = Duration::from_millis(0);
+
+        let ack = gps.pmtk_220_set_nmea_updaterate("1000");
+        assert_eq!(ack, PmtkAck::new(220, Pmtk001Ack::NoPacket));
+        assert_eq!(gps.last_attempt, 0);
+        assert_eq!(String::from_utf8(written.lock().unwrap().clone()).unwrap(), add_checksum("PMTK220,1000".to_string()).repeat(2));
+    }
+}
+
+#[cfg(test)]
+mod query_reply_test {
+    use std::time::Duration;
+
+    use crate::open_gps::mock_port::mock_gps_with_replies;
+    use crate::pmtk::send_pmtk::{add_checksum, reply_arg, DgpsMode, EpoData, NmeaOutput, Sbas, SbasMode};
+
+    #[test]
+    fn replies_are_matched_to_the_packet() {
+        assert_eq!(reply_arg::<f32>("$PMTK527,0.40*04\r\n", 527, 0), Some(0.4));
+        assert_eq!(reply_arg::<f32>("$PMTK527,0.40", 513, 0), None);
+        assert_eq!(reply_arg::<f32>("$PMTK527,", 527, 0), None);
+        assert_eq!(Sbas::from_q_reply("$PMTK513,1"), Some(Sbas::Enabled));
+        assert_eq!(Sbas::from_q_reply("$PMTK530,0"), None);
+        assert_eq!(SbasMode::from_q_reply("$PMTK519,0"), Some(SbasMode::Testing));
+        assert_eq!(DgpsMode::from_q_reply("$PMTK501,2"), Some(DgpsMode::WAAS));
+        assert_eq!(DgpsMode::from_q_reply("$PMTK501,7"), None);
+        assert_eq!(
+            NmeaOutput::from_q_reply("$PMTK514,0,1,1,1,1,5,0,0,0,0,0,0,0,0,0,0,0,0,0"),
+            Some(NmeaOutput { gll: 0, rmc: 1, vtg: 1, gga: 1, gsa: 1, gsv: 5, pmtkchn_interval: 0 })
+        );
+        assert_eq!(NmeaOutput::from_q_reply("$PMTK514,0,1"), None);
+        assert_eq!(EpoData::from_q_reply("$PMTK707,0,0,0,0,0,0,0,0,0").map(|epo| epo.set), Some(0));
+    }
+
+    #[test]
+    fn unreadable_reply_is_none() {
+        let replies = [add_checksum("PMTK527,fast".to_string()), add_checksum("PMTK869,2,1,3".to_string())];
+        let replies: Vec<&str> = replies.iter().map(|r| r.as_str()).collect();
+        let (mut gps, _written) = mock_gps_with_replies(&[], &replies);
+        gps.command_policy.retries = 0;
+        gps.command_policy.timeout = Duration::from_millis(100);
+
+        assert_eq!(gps.query_nav_threshold(), None);
+        assert_eq!(gps.query_easy(), Some(true));
+        assert_eq!(gps.pmtk_447_q_nav_threshold(), -1.0);
+    }
+}
+
+#[cfg(test)]
+mod chip_support_test {
+    use std::fs::read_to_string;
+
+    use crate::open_gps::mock_port::mock_gps;
+    use crate::pmtk::chip_support::{packet_support, supported_packets, Support, PACKET_SUPPORT};
+    use crate::pmtk::send_pmtk::{Chip, Pmtk001Ack, PmtkAck, QueryError};
+
+    #[test]
+    fn table_matches_specs() {
+        let specs = read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/Resources/specs.md")).unwrap();
+        let mut rows = 0;
+        for line in specs.lines() {
+            let cells: Vec<&str> = line.split('|').map(|c| c.trim()).collect();
+            let packet = match cells.get(1).and_then(|c| c.get(0..3)).and_then(|p| p.parse::<u16>().ok()) {
+                Some(packet) if cells.len() == 8 => packet,
+                _ => continue,
+            };
+            let row = packet_support(packet).expect("Packet in specs.md but not PACKET_SUPPORT");
+            let chips: Vec<Support> = cells[2..6].iter().map(|c| match *c {
+                "y" => Support::Yes,
+                "n" => Support::No,
+                _ => Support::Unknown,
+            }).collect();
+            assert_eq!(chips, vec![row.mt3318, row.mt3329, row.mt3339, row.mt3333], "{}", packet);
+            rows += 1;
+        }
+        assert_eq!(rows, PACKET_SUPPORT.len());
+    }
+
+    #[test]
+    fn query_table() {
+        assert_eq!(packet_support(386).unwrap().chips(), vec![Chip::MT3339]);
+        assert!(supported_packets(Chip::MT3329).contains(&397));
+        assert!(!supported_packets(Chip::MT3329).contains(&386));
+        assert!(supported_packets(Chip::Other(3337)).contains(&386));
+        // Unknown is sent anyway.
+        assert_eq!(packet_support(397).unwrap().support_on(Chip::MT3333), Support::Unknown);
+        assert!(supported_packets(Chip::MT3333).contains(&397));
+        assert!(supported_packets(Chip::MT3339).contains(&390));
+    }
+
+    #[test]
+    fn unsupported_command_is_not_sent() {
+        let (mut gps, written) = mock_gps(&[]);
+        gps.chip = Some(Chip::MT3329);
+
+        assert!(gps.supports(397));
+        assert!(!gps.supports(386));
+        assert_eq!(gps.pmtk_386_set_nav_speed_threshold(0.4), PmtkAck::new(386, Pmtk001Ack::NotOnChip(Chip::MT3329)));
+        assert_eq!(gps.send_query("PMTK869,0", 869), Err(QueryError::NotOnChip(Chip::MT3329)));
+        assert!(written.lock().unwrap().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod firmware_release_test {
+    use crate::pmtk::send_pmtk::{BuildDate, Chip, FirmwareRelease};
+
+    #[test]
+    fn pa1010d() {
+        assert_eq!(
+            FirmwareRelease::parse("$PMTK705,AXN_5.1.7_3333_19020118,0027,PA1010D,1.0*76\r\n"),
+            Some(FirmwareRelease {
+                family: "AXN".to_string(),
+                version: "5.1.7".to_string(),
+                chip: Some(Chip::MT3333),
+                build_date: Some(BuildDate { year: 2019, month: 2, day: 1, build: 18 }),
+                build_id: "0027".to_string(),
+                module: "PA1010D".to_string(),
+                sdk_version: "1.0".to_string(),
+                raw: "AXN_5.1.7_3333_19020118,0027,PA1010D,1.0".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn ultimate_gps() {
+        // As given by gps.send_query, without the checksum.
+        let release = FirmwareRelease::parse("$PMTK705,AXN_2.31_3339_13101700,5632,PA6H,1.0").unwrap();
+        assert_eq!(release.family, "AXN");
+        assert_eq!(release.version, "2.31");
+        assert_eq!(release.chip, Some(Chip::MT3339));
+        assert_eq!(release.build_date, Some(BuildDate { year: 2013, month: 10, day: 17, build: 0 }));
+        assert_eq!(release.module, "PA6H");
+
+        let release = FirmwareRelease::parse("AXN_2.10_3339_2012072601,5223,PA6H,1.0").unwrap();
+        assert_eq!(release.build_date, Some(BuildDate { year: 2012, month: 7, day: 26, build: 1 }));
+        assert_eq!(release.build_id, "5223");
+    }
+
+    #[test]
+    fn short_replies() {
+        assert_eq!(FirmwareRelease::parse("$PMTK705"), None);
+        assert_eq!(FirmwareRelease::parse(""), None);
+        let release = FirmwareRelease::parse("$PMTK705,M-core_1.94").unwrap();
+        assert_eq!(release.family, "M-core");
+        assert_eq!(release.version, "1.94");
+        assert_eq!(release.chip, None);
+        assert_eq!(release.build_date, None);
+        assert_eq!(release.module, "");
+    }
+}
+
+#[cfg(test)]
+mod pps_config_test {
+    use crate::pmtk::send_pmtk::{PpsConfig, PpsMode};
+
+    #[test]
+    fn pps_config_args() {
+        assert_eq!(
+            PpsConfig { mode: PpsMode::Fix3dOnly, pulse_width: 100 }.to_args(),
+            Some("2,100".to_string())
+        );
+        assert_eq!(PpsConfig { mode: PpsMode::Always, pulse_width: 1 }.to_args(), None);
+        assert_eq!(PpsConfig { mode: PpsMode::Always, pulse_width: 999 }.to_args(), None);
+    }
+}
+
+#[cfg(test)]
+mod user_option_test {
+    use crate::datum::datums::Datum;
+    use crate::pmtk::send_pmtk::{DgpsMode, NmeaOutput, Sbas, SbasMode, UserOption};
+
+    pub fn option() -> UserOption {
+        UserOption {
+            update_rate: 5,
+            baud_rate: 57600,
+            nmea_output: NmeaOutput { gll: 0, rmc: 1, vtg: 0, gga: 1, gsa: 1, gsv: 5, pmtkchn_interval: 0 },
+            zda: 0,
+            datum: Datum::WGS84,
+            dgps_mode: DgpsMode::WAAS,
+            sbas: Sbas::Enabled,
+            sbas_mode: SbasMode::Integrity,
+            nmea_v31: false,
+        }
+    }
+
+    #[test]
+    fn user_option_args() {
+        assert_eq!(option().to_args(), Some("0,5,57600,0,1,0,1,5,1,0,0,0,2,1,0,1".to_string()));
+        assert_eq!(UserOption { update_rate: 3, ..option() }.to_args(), None);
+        assert_eq!(UserOption { baud_rate: 12345, ..option() }.to_args(), None);
+        assert_eq!(UserOption { zda: 6, ..option() }.to_args(), None);
+        assert_eq!(UserOption { sbas: Sbas::Unknown, ..option() }.to_args(), None);
+    }
+
+    #[test]
+    fn user_option_reply() {
+        let reply = format!("$PMTK590,{}", option().to_args().unwrap());
+        assert_eq!(UserOption::from_q_reply(reply.as_str()), Some(option()));
+        assert_eq!(UserOption::from_q_reply("$PMTK590,0,5,57600"), None);
+        assert_eq!(UserOption::from_q_reply("$PMTK514,0,1,0,1,1,5,0,0,0,0,0,0,0,0,0,0,0,0,0"), None);
+    }
+}
+
 #[cfg(test)]
 mod pmtktests {
     use std::thread::sleep;
     use std::time::Duration;
 
+    use crate::datum::datums::Datum;
     use crate::pmtk::send_pmtk::set_baud_rate;
 
     use super::send_pmtk::{DgpsMode, EpoData, NmeaOutput, Pmtk001Ack, Sbas, SbasMode};
@@ -821,7 +2296,7 @@ mod pmtktests {
         let _ = set_baud_rate("9600", "/dev/serial0");
         sleep(Duration::from_secs(1));
         let port = open_port("/dev/serial0", 9600).unwrap();
-        let mut gps = Gps { port };
+        let mut gps = Gps::from_port(port);
         gps.pmtk_220_set_nmea_updaterate("1000");
         return gps;
     }
@@ -932,7 +2407,7 @@ mod pmtktests {
     #[ignore]
     fn test_pmtk_605_q_release() {
         assert_eq!(
-            port_setup().pmtk_605_q_release(),
+            port_setup().pmtk_605_q_release().unwrap().raw,
             "AXN_5.1.7_3333_19020118,0027,PA1010D,1.0".to_string()
         );
     }
@@ -1004,8 +2479,7 @@ mod pmtktests {
         );
     }
 
-    // fn test_ () {assert_eq!(port_setup().pmtk_225_cmd_periodic_mode(run_type: u8, run_time: u32, sleep_time: u32,}
-    //                                  second_run_time: u32, second_sleep_time: u32), Pmtk001Ack::Success);
+    // fn test_ () {assert_eq!(port_setup().pmtk_225_cmd_periodic_mode(PowerMode::AlwaysLocateStandby), Pmtk001Ack::Success);}
     #[test]
     #[ignore]
     fn test_pmtk_286_cmd_aic_mode() {
@@ -1034,13 +2508,13 @@ mod pmtktests {
     #[test]
     #[ignore]
     fn test_pmtk_330_api_set_datum() {
-        assert_eq!(port_setup().pmtk_330_api_set_datum(0), Pmtk001Ack::Success);
+        assert_eq!(port_setup().pmtk_330_api_set_datum(Datum::WGS84), Pmtk001Ack::Success);
     }
 
     #[test]
     #[ignore]
     fn test_pmtk_430_api_q_datum() {
-        assert_eq!(port_setup().pmtk_430_api_q_datum(), 0);
+        assert_eq!(port_setup().pmtk_430_api_q_datum(), Some(Datum::WGS84));
     }
 
     #[test]
@@ -1060,4 +2534,14 @@ mod pmtktests {
             Pmtk001Ack::Success
         );
     }
+
+    #[test]
+    #[ignore]
+    fn test_pmtk_390_api_set_user_option() {
+        let mut gps = port_setup();
+        let option = super::user_option_test::option();
+        assert_eq!(gps.pmtk_390_api_set_user_option(&option), Pmtk001Ack::Success);
+        assert_eq!(gps.pmtk_490_api_q_user_option(), Some(option.clone()));
+        assert_eq!(gps.verify_user_option(&option), vec![]);
+    }
 }
@@ -0,0 +1,193 @@
+//! # Gps service
+//! GpsService owns the Gps on a background thread, calls gps.update() continuously and gives each
+//! sentence to every subscriber, so a logger, a display and a radio can all use the one gps.
+//!
+//! Commands are run on the same thread between updates, so they never read over each other.
+//! Sentences read while a command waits for its reply are kept in gps.pending as usual, and are
+//! given to the subscribers after it.
+//! ```ignore
+//! let service = GpsService::start(Gps::new("/dev/serial0", "9600")?);
+//! let log = service.subscribe();
+//! let display = service.subscribe();
+//! service.send_with_ack("PMTK220,1000");
+//! let rate = service.with_gps(|gps| gps.pmtk_447_q_nav_threshold());
+//! for sentence in log.iter() { ... }
+//! let gps = service.stop();
+//! ```
+
+pub mod gps_service {
+    //! GpsService and its subscriptions.
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TrySendError};
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, JoinHandle};
+
+    use crate::open_gps::gps::{Gps, GpsSentence};
+    use crate::pmtk::send_pmtk::{command_number, Pmtk001Ack, PmtkAck, QueryError};
+
+    /// Sentences a subscriber can fall behind by before the newest are dropped for it.
+    pub const SUBSCRIBER_BUFFER: usize = 64;
+
+    type Command = Box<dyn FnOnce(&mut Gps) + Send>;
+
+    /// Runs a Gps on a background thread. See the [module docs](index.html).
+    ///
+    /// Subscribers that fall more than their buffer behind miss sentences rather than holding up
+    /// the others. Dropping a Receiver unsubscribes it. Dropping the service stops the thread.
+    pub struct GpsService {
+        commands: Sender<Command>,
+        subscribers: Arc<Mutex<Vec<SyncSender<GpsSentence>>>>,
+        running: Arc<AtomicBool>,
+        thread: Option<JoinHandle<Gps>>,
+    }
+
+    impl GpsService {
+        /// Starts reading the gps on a new thread.
+        pub fn start(mut gps: Gps) -> GpsService {
+            let (commands, command_rx) = channel::<Command>();
+            let subscribers: Arc<Mutex<Vec<SyncSender<GpsSentence>>>> = Arc::new(Mutex::new(Vec::new()));
+            let running = Arc::new(AtomicBool::new(true));
+
+            let thread_subscribers = subscribers.clone();
+            let thread_running = running.clone();
+            let thread = thread::spawn(move || {
+                while thread_running.load(Ordering::SeqCst) {
+                    while let Ok(command) = command_rx.try_recv() {
+                        command(&mut gps);
+                    }
+                    let sentence = gps.update();
+                    thread_subscribers.lock().unwrap().retain(|subscriber| {
+                        !matches!(subscriber.try_send(sentence.clone()), Err(TrySendError::Disconnected(_)))
+                    });
+                }
+                gps
+            });
+            GpsService { commands, subscribers, running, thread: Some(thread) }
+        }
+
+        /// Every sentence from now on, with SUBSCRIBER_BUFFER of buffer.
+        pub fn subscribe(&self) -> Receiver<GpsSentence> {
+            self.subscribe_with_buffer(SUBSCRIBER_BUFFER)
+        }
+
+        /// Every sentence from now on, buffering up to buffer sentences.
+        pub fn subscribe_with_buffer(&self, buffer: usize) -> Receiver<GpsSentence> {
+            let (sender, receiver) = sync_channel(buffer);
+            self.subscribers.lock().unwrap().push(sender);
+            receiver
+        }
+
+        /// Runs f on the reader thread, between updates, and gives what it returns. Waits for at
+        /// most the update in progress, up to a second, before f is run.
+        ///
+        /// None if the service has stopped.
+        pub fn with_gps<T: Send + 'static>(&self, f: impl FnOnce(&mut Gps) -> T + Send + 'static) -> Option<T> {
+            let (result, result_rx) = channel();
+            let command: Command = Box::new(move |gps| {
+                let _ = result.send(f(gps));
+            });
+            self.commands.send(command).ok()?;
+            result_rx.recv().ok()
+        }
+
+        /// gps.send_with_ack on the reader thread. NoPacket if the service has stopped.
+        pub fn send_with_ack(&self, cmd: &str) -> PmtkAck {
+            let number = command_number(cmd);
+            let cmd = cmd.to_string();
+            self.with_gps(move |gps| gps.send_with_ack(cmd.as_str()))
+                .unwrap_or_else(|| PmtkAck::new(number, Pmtk001Ack::NoPacket))
+        }
+
+        /// gps.send_query on the reader thread. NoReply if the service has stopped.
+        pub fn send_query(&self, cmd: &str, reply: u16) -> Result<String, QueryError> {
+            let cmd = cmd.to_string();
+            self.with_gps(move |gps| gps.send_query(cmd.as_str(), reply)).unwrap_or(Err(QueryError::NoReply))
+        }
+
+        /// True until stop is called, or the reader thread panics.
+        pub fn is_running(&self) -> bool {
+            self.running.load(Ordering::SeqCst) && self.thread.as_ref().is_some_and(|t| !t.is_finished())
+        }
+
+        /// Stops the reader thread after the update in progress and gives back the gps. Subscribers
+        /// see their channel close. None if the reader thread panicked.
+        pub fn stop(mut self) -> Option<Gps> {
+            self.stop_thread()
+        }
+
+        fn stop_thread(&mut self) -> Option<Gps> {
+            self.running.store(false, Ordering::SeqCst);
+            let gps = self.thread.take()?.join().ok();
+            self.subscribers.lock().unwrap().clear();
+            gps
+        }
+    }
+
+    impl Drop for GpsService {
+        fn drop(&mut self) {
+            self.stop_thread();
+        }
+    }
+}
+
+#[cfg(test)]
+mod gps_service_test {
+    use std::sync::mpsc::Receiver;
+    use std::time::Duration;
+
+    use crate::open_gps::gps::GpsSentence;
+    use crate::open_gps::mock_port::{mock_gps, mock_gps_with_replies, GGA};
+    use crate::pmtk::send_pmtk::{add_checksum, Pmtk001Ack};
+
+    use super::gps_service::GpsService;
+
+    const VTG: &str = "$GNVTG,0.00,T,,M,0.00,N,0.00,K,A*23\r\n";
+
+    /// The next sentence that isn't NoConnection, which the empty mock port gives between lines.
+    fn next_sentence(subscriber: &Receiver<GpsSentence>) -> GpsSentence {
+        loop {
+            match subscriber.recv_timeout(Duration::from_secs(3)).unwrap() {
+                GpsSentence::NoConnection => continue,
+                sentence => return sentence,
+            }
+        }
+    }
+
+    #[test]
+    fn every_subscriber_gets_every_sentence() {
+        let (gps, _written) = mock_gps(&[]);
+        let service = GpsService::start(gps);
+        let first = service.subscribe();
+        let second = service.subscribe();
+        // Only give the gps its lines once both have subscribed.
+        service.with_gps(|gps| gps.pending.extend(vec![GGA.to_string(), VTG.to_string()])).unwrap();
+
+        for subscriber in [first, second].iter() {
+            assert!(matches!(next_sentence(subscriber), GpsSentence::GGA(_)));
+            assert!(matches!(next_sentence(subscriber), GpsSentence::VTG(_)));
+        }
+        assert!(service.stop().is_some());
+    }
+
+    #[test]
+    fn commands_run_between_updates() {
+        let ack = add_checksum("PMTK001,220,3".to_string());
+        let (gps, written) = mock_gps_with_replies(&[], &[ack.as_str()]);
+        let service = GpsService::start(gps);
+
+        let ack = service.send_with_ack("PMTK220,1000");
+        assert_eq!(ack, Pmtk001Ack::Success);
+        assert_eq!(String::from_utf8(written.lock().unwrap().clone()).unwrap(), add_checksum("PMTK220,1000".to_string()));
+        assert!(service.is_running());
+    }
+
+    #[test]
+    fn stopping_closes_subscriptions() {
+        let (gps, _written) = mock_gps(&[]);
+        let service = GpsService::start(gps);
+        let subscriber = service.subscribe_with_buffer(1);
+        let gps = service.stop().unwrap();
+        assert!(gps.pending.is_empty());
+        while subscriber.recv().is_ok() {}
+    }
+}
@@ -0,0 +1,273 @@
+//! # Reconnect supervisor
+//! A USB gps can be unplugged and plugged back in. Once it is gone, the old port only gives
+//! errors, so gps.update() gives NoConnection forever, even after the gps is back.
+//!
+//! Supervisor owns the Gps and watches for the port going away: an error from the port (see
+//! gps.port_error) or the device path no longer existing. It then reopens the path, waiting longer
+//! between each failed attempt, and applies the stored ReceiverConfig again, as an unplugged gps
+//! without a battery loses its settings.
+//!
+//! Use a path that stays the same when the gps is plugged back in, such as /dev/serial/by-id/...
+//! rather than /dev/ttyUSB0.
+//! ```ignore
+//! let mut gps = Supervisor::new("/dev/serial/by-id/usb-u-blox_GNSS-if00", 9600).with_config(config);
+//! let events = gps.subscribe();
+//! loop {
+//!     let sentence = gps.update(); // NoConnection while unplugged.
+//!     for event in events.try_iter() {
+//!         println!("{:?}", event);
+//!     }
+//! }
+//! ```
+
+pub mod gps_supervisor {
+    //! Supervisor, its events and backoff.
+    use std::cmp::min;
+    use std::path::Path;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    use crate::config::receiver_config::ReceiverConfig;
+    use crate::open_gps::gps::{open_port, Gps, GpsSentence};
+    use crate::pmtk::send_pmtk::Pmtk001Ack;
+
+    /// What the supervisor did.
+    /// - Connected: The port was opened.
+    /// - Disconnected: The port went away. Reconnecting starts on the next update.
+    /// - Reconfigured: The config was applied after connecting. failed lists the settings the gps
+    ///   didn't ack, empty if it took everything.
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum SupervisorEvent {
+        Connected,
+        Disconnected,
+        Reconfigured { failed: Vec<&'static str> },
+    }
+
+    /// Wait before reconnecting. Starts at initial and doubles after each failed attempt, up to max.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub struct Backoff {
+        pub initial: Duration,
+        pub max: Duration,
+    }
+
+    impl Default for Backoff {
+        fn default() -> Backoff {
+            Backoff { initial: Duration::from_millis(250), max: Duration::from_secs(30) }
+        }
+    }
+
+    /// Keeps a Gps connected. See the [module docs](index.html).
+    ///
+    /// The Gps is kept across reconnects, with only the port replaced, so its talker, chip, trace
+    /// and command policy stay set.
+    pub struct Supervisor {
+        pub port_name: String,
+        pub baud_rate: u32,
+        /// Applied each time the port is opened. None applies nothing.
+        pub config: Option<ReceiverConfig>,
+        pub backoff: Backoff,
+        gps: Option<Gps>,
+        connected: bool,
+        delay: Duration,
+        next_attempt: Instant,
+        subscribers: Vec<Sender<SupervisorEvent>>,
+    }
+
+    impl Supervisor {
+        /// Supervisor for the gps at port_name. The port is opened on the first update.
+        pub fn new(port_name: &str, baud_rate: u32) -> Supervisor {
+            let backoff = Backoff::default();
+            Supervisor {
+                port_name: port_name.to_string(),
+                baud_rate,
+                config: None,
+                backoff,
+                gps: None,
+                connected: false,
+                delay: backoff.initial,
+                next_attempt: Instant::now(),
+                subscribers: Vec::new(),
+            }
+        }
+
+        /// Applies config every time the port is opened.
+        pub fn with_config(mut self, config: ReceiverConfig) -> Supervisor {
+            self.config = Some(config);
+            self
+        }
+
+        pub fn with_backoff(mut self, backoff: Backoff) -> Supervisor {
+            self.backoff = backoff;
+            self.delay = backoff.initial;
+            self
+        }
+
+        /// Every event from now on.
+        pub fn subscribe(&mut self) -> Receiver<SupervisorEvent> {
+            let (sender, receiver) = channel();
+            self.subscribers.push(sender);
+            receiver
+        }
+
+        pub fn is_connected(&self) -> bool {
+            self.connected
+        }
+
+        /// The gps, if the port has been opened at least once. Its port is dead while disconnected.
+        pub fn gps(&mut self) -> Option<&mut Gps> {
+            self.gps.as_mut()
+        }
+
+        /// gps.update(), reconnecting first if the port has gone away. NoConnection while
+        /// disconnected, after waiting up to a second for the next attempt, as gps.update() does.
+        pub fn update(&mut self) -> GpsSentence {
+            if !self.connected && !self.reconnect() {
+                return GpsSentence::NoConnection;
+            }
+            let gps = self.gps.as_mut().expect("connected without a gps");
+            let sentence = gps.update();
+            if sentence == GpsSentence::NoConnection
+                && (gps.port_error.is_some() || !Path::new(&self.port_name).exists()) {
+                self.connected = false;
+                self.next_attempt = Instant::now();
+                self.emit(SupervisorEvent::Disconnected);
+            }
+            sentence
+        }
+
+        /// Tries to open the port if it is time to. True if it was opened.
+        fn reconnect(&mut self) -> bool {
+            let now = Instant::now();
+            if now < self.next_attempt {
+                sleep(min(self.next_attempt - now, Duration::from_secs(1)));
+                if Instant::now() < self.next_attempt {
+                    return false;
+                }
+            }
+            let port = match open_port(self.port_name.as_str(), self.baud_rate) {
+                Ok(port) => port,
+                Err(_) => {
+                    self.next_attempt = Instant::now() + self.delay;
+                    self.delay = min(self.delay * 2, self.backoff.max);
+                    return false;
+                }
+            };
+            self.delay = self.backoff.initial;
+            self.connected = true;
+            let gps = match self.gps.as_mut() {
+                Some(gps) => {
+                    gps.port = port;
+                    gps.port_error = None;
+                    gps.framer.clear();
+                    gps
+                }
+                None => self.gps.insert(Gps::from_port(port)),
+            };
+            let failed = self.config.as_ref().map(|config| {
+                gps.apply_config(config).into_iter()
+                    .filter(|(_, ack)| *ack != Pmtk001Ack::Success)
+                    .map(|(setting, _)| setting)
+                    .collect()
+            });
+            self.emit(SupervisorEvent::Connected);
+            if let Some(failed) = failed {
+                self.emit(SupervisorEvent::Reconfigured { failed });
+            }
+            true
+        }
+
+        fn emit(&mut self, event: SupervisorEvent) {
+            self.subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod gps_supervisor_test {
+    use std::fs::remove_file;
+    use std::io::{Read, Write};
+    use std::os::unix::fs::symlink;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::Receiver;
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::{Duration, Instant};
+
+    use serialport::posix::TTYPort;
+    use serialport::SerialPort;
+
+    use crate::config::receiver_config::ReceiverConfig;
+    use crate::open_gps::gps::GpsSentence;
+    use crate::open_gps::mock_port::GGA;
+    use crate::pmtk::send_pmtk::add_checksum;
+
+    use super::gps_supervisor::{Backoff, Supervisor, SupervisorEvent};
+
+    const LINK: &str = "supervisor_test_gps";
+
+    /// Plugs in a gps: a new pty, linked from LINK, whose master gives a GGA every 50ms and acks
+    /// every PMTK220. Setting the flag to false unplugs it.
+    fn plug_in() -> (Arc<AtomicBool>, JoinHandle<()>) {
+        let (mut master, slave) = TTYPort::pair().unwrap();
+        let _ = remove_file(LINK);
+        symlink(slave.name().unwrap(), LINK).unwrap();
+        drop(slave);
+        master.set_timeout(Duration::from_millis(50)).unwrap();
+
+        let plugged = Arc::new(AtomicBool::new(true));
+        let running = plugged.clone();
+        let thread = thread::spawn(move || {
+            let mut buf = [0u8; 256];
+            while running.load(Ordering::SeqCst) {
+                if let Ok(read) = master.read(&mut buf) {
+                    if String::from_utf8_lossy(&buf[..read]).contains("PMTK220") {
+                        let _ = master.write_all(add_checksum("PMTK001,220,3".to_string()).as_bytes());
+                    }
+                }
+                let _ = master.write_all(GGA.as_bytes());
+            }
+            let _ = remove_file(LINK);
+        });
+        (plugged, thread)
+    }
+
+    /// Updates until the event and a GGA have both been seen.
+    fn update_until(gps: &mut Supervisor, events: &Receiver<SupervisorEvent>, event: SupervisorEvent) {
+        let start = Instant::now();
+        let (mut seen_event, mut seen_gga) = (false, false);
+        while !(seen_event && seen_gga) {
+            assert!(start.elapsed() < Duration::from_secs(10), "no {:?}", event);
+            seen_gga |= matches!(gps.update(), GpsSentence::GGA(_));
+            seen_event |= events.try_iter().any(|e| e == event);
+        }
+    }
+
+    #[test]
+    fn reconnects_and_reconfigures() {
+        let config = ReceiverConfig { update_rate: Some(1000), ..ReceiverConfig::default() };
+        let mut gps = Supervisor::new(LINK, 9600)
+            .with_config(config)
+            .with_backoff(Backoff { initial: Duration::from_millis(20), max: Duration::from_millis(100) });
+        let events = gps.subscribe();
+
+        let (plugged, thread) = plug_in();
+        update_until(&mut gps, &events, SupervisorEvent::Reconfigured { failed: vec![] });
+        assert!(gps.is_connected());
+
+        plugged.store(false, Ordering::SeqCst);
+        thread.join().unwrap();
+        let start = Instant::now();
+        while gps.is_connected() {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            gps.update();
+        }
+        assert_eq!(events.try_iter().collect::<Vec<_>>(), vec![SupervisorEvent::Disconnected]);
+        assert_eq!(gps.update(), GpsSentence::NoConnection);
+
+        let (plugged, thread) = plug_in();
+        update_until(&mut gps, &events, SupervisorEvent::Connected);
+        plugged.store(false, Ordering::SeqCst);
+        thread.join().unwrap();
+    }
+}
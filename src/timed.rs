@@ -0,0 +1,210 @@
+//! # Receive timestamps
+//! GpsSentence only has the gps's own UTC. gps.update_timed() also gives when the sentence arrived
+//! at the host, to measure latency, line the gps up with other sensors and spot buffering stalls.
+//!
+//! - received: Wall clock time, from SystemTime. Can jump if the clock is set.
+//! - monotonic: Time since the first Gps was made in this process, from Instant. Never jumps, so
+//!   use this for latency and gaps.
+//! - bytes: Bytes read for the sentence, all the GSV messages for a GSV.
+//!
+//! The times are when the read that finished the line returned, so lines read together share a
+//! time. Sentences kept while waiting for a command reply keep the time they were read.
+//!
+//! TimedSentence::append_to() writes to the same log as GpsSentence::append_to(), as a
+//! GpsSentence::Timed entry, so timed and plain sentences can share a file. TimedSentence::read_from()
+//! gives only the timed entries.
+//! ```ignore
+//! let timed = gps.update_timed();
+//! let latency = timed.received.duration_since(gps_utc_as_system_time);
+//! timed.append_to("gps_log");
+//! let log: Vec<TimedSentence> = TimedSentence::try_read_from("gps_log")?;
+//! ```
+
+pub mod timed_sentence {
+    //! TimedSentence and gps.update_timed().
+    use std::io;
+    use std::sync::OnceLock;
+    use std::time::{Duration, Instant, SystemTime};
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::open_gps::gps::{Gps, GpsSentence};
+
+    static MONOTONIC_START: OnceLock<Instant> = OnceLock::new();
+
+    /// The instant monotonic times are measured from.
+    pub(crate) fn monotonic_start() -> Instant {
+        *MONOTONIC_START.get_or_init(Instant::now)
+    }
+
+    /// When a line arrived and how long it was.
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct Receipt {
+        pub received: SystemTime,
+        pub monotonic: Instant,
+        pub bytes: usize,
+    }
+
+    /// A sentence with when it arrived at the host. See the [module docs](index.html).
+    ///
+    /// NoConnection and Sleeping have the time update_timed returned and 0 bytes.
+    #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+    pub struct TimedSentence {
+        pub sentence: GpsSentence,
+        pub received: SystemTime,
+        pub monotonic: Duration,
+        pub bytes: usize,
+    }
+
+    impl TimedSentence {
+        /// Appends to a GpsSentence log file as a GpsSentence::Timed, see GpsSentence::append_to().
+        pub fn append_to(self, file: &str) {
+            GpsSentence::Timed(Box::new(self)).append_to(file)
+        }
+
+        /// The timed entries in a log, see GpsSentence::read_from().
+        pub fn read_from(file: &str) -> Vec<TimedSentence> {
+            timed_entries(GpsSentence::read_from(file))
+        }
+
+        /// The timed entries in a log, see GpsSentence::try_read_from().
+        pub fn try_read_from(file: &str) -> io::Result<Vec<TimedSentence>> {
+            GpsSentence::try_read_from(file).map(timed_entries)
+        }
+    }
+
+    fn timed_entries(log: Vec<GpsSentence>) -> Vec<TimedSentence> {
+        log.into_iter()
+            .filter_map(|sentence| match sentence {
+                GpsSentence::Timed(timed) => Some(*timed),
+                _ => None,
+            })
+            .collect()
+    }
+
+    impl Gps {
+        /// gps.update() with when the sentence arrived. See the [module docs](index.html).
+        pub fn update_timed(&mut self) -> TimedSentence {
+            let sentence = self.update();
+            let receipt = self.update_receipt.take().unwrap_or(Receipt {
+                received: SystemTime::now(),
+                monotonic: Instant::now(),
+                bytes: 0,
+            });
+            TimedSentence {
+                sentence,
+                received: receipt.received,
+                monotonic: receipt.monotonic.saturating_duration_since(monotonic_start()),
+                bytes: receipt.bytes,
+            }
+        }
+
+        /// Adds the receipt of the line just given by next_line to this update's.
+        pub(crate) fn add_to_update_receipt(&mut self, line: Option<Receipt>) {
+            if let Some(line) = line {
+                let bytes = self.update_receipt.map_or(0, |r| r.bytes) + line.bytes;
+                self.update_receipt = Some(Receipt { bytes, ..line });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod timed_sentence_test {
+    use std::fs::remove_file;
+    use std::io::ErrorKind;
+    use std::thread::sleep;
+    use std::time::{Duration, SystemTime};
+
+    use crate::open_gps::gps::GpsSentence;
+    use crate::open_gps::mock_port::{mock_gps, mock_gps_with_replies, GGA};
+    use crate::pmtk::send_pmtk::add_checksum;
+
+    use super::timed_sentence::TimedSentence;
+
+    const GSV_1: &str = "$GPGSV,2,1,08,12,12,100,,04,11,331,,16,06,282,,05,05,074,22*7C\r\n";
+    const GSV_2: &str = "$GPGSV,2,2,08,32,01,215,,41,,,*42\r\n";
+
+    #[test]
+    fn stamps_each_sentence() {
+        let before = SystemTime::now();
+        let (mut gps, _written) = mock_gps(&[GGA, GSV_1, GSV_2]);
+        let gga = gps.update_timed();
+        assert!(matches!(gga.sentence, GpsSentence::GGA(_)));
+        assert_eq!(gga.bytes, GGA.len());
+        assert!(gga.received >= before && gga.received <= SystemTime::now());
+
+        let gsv = gps.update_timed();
+        assert!(matches!(&gsv.sentence, GpsSentence::GSV(sats) if sats.len() == 6));
+        assert_eq!(gsv.bytes, GSV_1.len() + GSV_2.len());
+        assert!(gsv.monotonic >= gga.monotonic);
+
+        let none = gps.update_timed();
+        assert_eq!(none.sentence, GpsSentence::NoConnection);
+        assert_eq!(none.bytes, 0);
+        assert!(none.monotonic >= gsv.monotonic + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn lost_gsv_message() {
+        let (mut gps, _written) = mock_gps(&[GSV_1, GGA]);
+        let gsv = gps.update_timed();
+        assert!(matches!(&gsv.sentence, GpsSentence::GSV(sats) if sats.len() == 4));
+        assert_eq!(gsv.bytes, GSV_1.len());
+        let gga = gps.update_timed();
+        assert!(matches!(gga.sentence, GpsSentence::GGA(_)));
+        assert_eq!(gga.bytes, GGA.len());
+    }
+
+    #[test]
+    fn kept_sentences_keep_their_time() {
+        let ack = add_checksum("PMTK001,220,3".to_string());
+        let reply = format!("{}{}", GGA, ack);
+        let (mut gps, _written) = mock_gps_with_replies(&[], &[reply.as_str()]);
+        gps.send_with_ack("PMTK220,1000");
+        let read = SystemTime::now();
+        sleep(Duration::from_millis(20));
+        let gga = gps.update_timed();
+        assert!(matches!(gga.sentence, GpsSentence::GGA(_)));
+        assert!(gga.received <= read);
+        assert_eq!(gga.bytes, GGA.len());
+    }
+
+    #[test]
+    fn log_round_trip() {
+        let file = "timed_log_test";
+        let _ = remove_file(file);
+        let (mut gps, _written) = mock_gps(&[GGA, GSV_1, GSV_2]);
+        let written = vec![gps.update_timed(), gps.update_timed()];
+        for timed in written.iter() {
+            timed.clone().append_to(file);
+        }
+        GpsSentence::NoConnection.append_to(file);
+        assert_eq!(TimedSentence::try_read_from(file).unwrap(), written);
+        assert_eq!(GpsSentence::read_from(file).len(), 3);
+        let _ = remove_file(file);
+    }
+
+    #[test]
+    fn corrupt_log() {
+        let file = "timed_corrupt_test";
+        let _ = remove_file(file);
+        let (mut gps, _written) = mock_gps(&[GGA, GGA]);
+        let first = gps.update_timed();
+        first.clone().append_to(file);
+        gps.update_timed().append_to(file);
+        let mut bytes = std::fs::read(file).unwrap();
+
+        // Cut short at the end, as by a power cut mid write.
+        std::fs::write(file, &bytes[..bytes.len() - 5]).unwrap();
+        assert_eq!(TimedSentence::try_read_from(file).unwrap(), vec![first.clone()]);
+
+        // A bad sentence type in the second entry.
+        let second = bincode::serialized_size(&GpsSentence::Timed(Box::new(first.clone()))).unwrap() as usize + 1;
+        bytes[second + 4] = 0xFF;
+        std::fs::write(file, &bytes).unwrap();
+        assert_eq!(TimedSentence::try_read_from(file).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(TimedSentence::read_from(file), vec![first]);
+        let _ = remove_file(file);
+    }
+}
@@ -0,0 +1,342 @@
+//! # Timing
+//! Helpers for using the gps as a time source.
+//!
+//! The utc module turns the gps time and date into a full date time, and the pps module pairs
+//! the 1PPS pulse, as seen by the linux kernel, with the UTC given in the sentences after it.
+//!
+//! See pmtk_285_set_pps_config and pmtk_255_set_sync_pps_nmea for setting up the pulse.
+
+pub mod utc {
+    //! Full UTC date times from the gps sentences.
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use crate::nmea::rmc::RmcData;
+    use crate::nmea::zda::ZdaData;
+
+    /// A UTC date and time. second includes the fraction of the second.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct UtcDateTime {
+        pub year: u16,
+        pub month: u8,
+        pub day: u8,
+        pub hour: u8,
+        pub minute: u8,
+        pub second: f64,
+    }
+
+    /// Days since 1970-01-01 for a date. See http://howardhinnant.github.io/date_algorithms.html
+    fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = if year >= 0 { year } else { year - 399 } / 400;
+        let yoe = year - era * 400;
+        let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    /// (year, month, day) for a number of days since 1970-01-01.
+    fn civil_from_days(days: i64) -> (i64, i64, i64) {
+        let z = days + 719_468;
+        let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        (year, month, day)
+    }
+
+    /// Splits the gps hhmmss.sss time into hours, minutes and seconds.
+    pub(crate) fn split_utc(utc: f64) -> (u8, u8, f64) {
+        let hour = (utc / 10_000.0) as u8;
+        let minute = ((utc / 100.0) as u32 % 100) as u8;
+        let second = utc - (utc / 100.0).floor() * 100.0;
+        (hour, minute, second)
+    }
+
+    impl UtcDateTime {
+        /// Date time from an RMC sentence. None if there is no date given.
+        ///
+        /// RMC only gives a two digit year, which is taken to be 20yy.
+        pub fn from_rmc(rmc: &RmcData) -> Option<UtcDateTime> {
+            if rmc.date.len() != 6 {
+                return None;
+            }
+            let day: u8 = rmc.date[0..2].parse().ok()?;
+            let month: u8 = rmc.date[2..4].parse().ok()?;
+            let year: u16 = rmc.date[4..6].parse().ok()?;
+            let (hour, minute, second) = split_utc(rmc.utc);
+            Some(UtcDateTime { year: 2000 + year, month, day, hour, minute, second })
+        }
+
+        /// Date time from a ZDA sentence. None if the date is not given.
+        pub fn from_zda(zda: &ZdaData) -> Option<UtcDateTime> {
+            let (hour, minute, second) = split_utc(zda.utc);
+            Some(UtcDateTime { year: zda.year?, month: zda.month?, day: zda.day?, hour, minute, second })
+        }
+
+        /// Date time from seconds since the unix epoch.
+        pub fn from_unix(seconds: f64) -> UtcDateTime {
+            let days = (seconds / 86_400.0).floor();
+            let (year, month, day) = civil_from_days(days as i64);
+            let second_of_day = seconds - days * 86_400.0;
+            let hour = (second_of_day / 3600.0) as u8;
+            let minute = ((second_of_day % 3600.0) / 60.0) as u8;
+            UtcDateTime {
+                year: year as u16,
+                month: month as u8,
+                day: day as u8,
+                hour,
+                minute,
+                second: second_of_day % 60.0,
+            }
+        }
+
+        /// The current time of the host clock.
+        pub fn now() -> UtcDateTime {
+            let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            UtcDateTime::from_unix(since_epoch.as_secs_f64())
+        }
+
+        /// ISO 8601 to the millisecond, such as 2020-05-20T11:09:42.000Z.
+        pub fn to_iso8601(&self) -> String {
+            let millis = ((self.second * 1000.0).round() as u32).min(59_999);
+            format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+                    self.year, self.month, self.day, self.hour, self.minute, millis / 1000, millis % 1000)
+        }
+
+        /// Seconds since the unix epoch.
+        pub fn to_unix(&self) -> f64 {
+            let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+            (days * 86_400 + self.hour as i64 * 3600 + self.minute as i64 * 60) as f64 + self.second
+        }
+    }
+}
+
+pub mod pps {
+    //! # 1PPS pairing
+    //! The linux pps driver gives the host clock time of each pulse in
+    //! /sys/class/pps/ppsN/assert as seconds.nanoseconds#sequence.
+    //!
+    //! The pulse marks the start of a UTC second, and the RMC or ZDA sentence after it gives which
+    //! second it was. Pairing the two gives the offset of the host clock from UTC.
+    //!
+    //! Files of assert lines and of timestamped sentences (host seconds, a space, then the raw
+    //! sentence) can be paired after the fact with pair_pps, which is handy for testing.
+    use std::fs::File;
+    use std::io::Read;
+
+    use crate::open_gps::gps::{parse_line, GpsSentence};
+    use crate::timing::utc::UtcDateTime;
+
+    /// A single pulse as seen by the kernel. seconds and nanos are the host clock time.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct PpsTimestamp {
+        pub seconds: u64,
+        pub nanos: u32,
+        pub sequence: u64,
+    }
+
+    impl PpsTimestamp {
+        /// Host time of the pulse as seconds since the unix epoch.
+        pub fn as_secs_f64(&self) -> f64 {
+            self.seconds as f64 + self.nanos as f64 / 1_000_000_000.0
+        }
+    }
+
+    /// A pulse paired with the UTC it marks.
+    /// - pulse: the kernel timestamp.
+    /// - utc: the UTC from the sentence after the pulse.
+    /// - offset: UTC - host time of the pulse, in seconds. Positive means the host clock is behind.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct PpsPair {
+        pub pulse: PpsTimestamp,
+        pub utc: UtcDateTime,
+        pub offset: f64,
+    }
+
+    /// Parses a kernel assert line: 1590000000.123456789#42
+    pub fn parse_pps_assert(line: &str) -> Option<PpsTimestamp> {
+        let line = line.trim();
+        let hash = line.find('#')?;
+        let (time, sequence) = (&line[..hash], &line[hash + 1..]);
+        let mut time = time.split('.');
+        let seconds: u64 = time.next()?.parse().ok()?;
+        let nanos: u32 = time.next()?.parse().ok()?;
+        Some(PpsTimestamp { seconds, nanos, sequence: sequence.parse().ok()? })
+    }
+
+    /// The sysfs assert file for a pps device, /dev/pps0 -> /sys/class/pps/pps0/assert.
+    /// Any other path is taken to already be an assert file.
+    pub fn assert_path(pps_device: &str) -> String {
+        match pps_device.strip_prefix("/dev/") {
+            Some(name) => format!("/sys/class/pps/{}/assert", name),
+            None => pps_device.to_string(),
+        }
+    }
+
+    /// Reads the latest pulse of a pps device. None if the device does not exist or has not
+    /// pulsed yet.
+    pub fn read_pps_assert(pps_device: &str) -> Option<PpsTimestamp> {
+        let mut contents = String::new();
+        File::open(assert_path(pps_device)).ok()?.read_to_string(&mut contents).ok()?;
+        parse_pps_assert(contents.as_str())
+    }
+
+    /// Reads a file of assert lines, one pulse per line. Lines that don't parse are skipped.
+    pub fn read_pps_file(file: &str) -> Vec<PpsTimestamp> {
+        let mut contents = String::new();
+        let _ = File::open(file).expect("No file found").read_to_string(&mut contents);
+        contents.lines().filter_map(parse_pps_assert).collect()
+    }
+
+    /// Reads a file of timestamped sentences: host seconds, a space, then the raw sentence.
+    /// Lines that don't parse are skipped.
+    pub fn read_timestamped_sentences(file: &str) -> Vec<(f64, GpsSentence)> {
+        let mut contents = String::new();
+        let _ = File::open(file).expect("No file found").read_to_string(&mut contents);
+        let mut sentences = Vec::new();
+        for line in contents.lines() {
+            let mut split = line.splitn(2, ' ');
+            let host_time = split.next().and_then(|t| t.parse::<f64>().ok());
+            if let (Some(host_time), Some(sentence)) = (host_time, split.next()) {
+                sentences.push((host_time, parse_line(sentence)));
+            }
+        }
+        sentences
+    }
+
+    /// The UTC of a sentence, if it is an RMC or ZDA with a date.
+    pub fn sentence_utc(sentence: &GpsSentence) -> Option<UtcDateTime> {
+        match sentence {
+            GpsSentence::RMC(rmc) => UtcDateTime::from_rmc(rmc),
+            GpsSentence::ZDA(zda) => UtcDateTime::from_zda(zda),
+            _ => None,
+        }
+    }
+
+    /// Pairs each pulse with the first RMC or ZDA received within max_delay seconds after it.
+    ///
+    /// sentences are (host receive time, sentence) in the order they were received. Pulses with
+    /// no sentence in time are dropped.
+    pub fn pair_pps(pulses: &[PpsTimestamp], sentences: &[(f64, GpsSentence)], max_delay: f64) -> Vec<PpsPair> {
+        let mut pairs = Vec::new();
+        for pulse in pulses.iter() {
+            let pulse_time = pulse.as_secs_f64();
+            let next = sentences.iter()
+                .filter(|(host_time, _)| *host_time > pulse_time && *host_time - pulse_time < max_delay)
+                .find_map(|(_, sentence)| sentence_utc(sentence));
+            if let Some(utc) = next {
+                pairs.push(PpsPair { offset: utc.to_unix() - pulse_time, pulse: pulse.clone(), utc });
+            }
+        }
+        pairs
+    }
+
+    /// Pairs pulses with sentences as they are read from the gps.
+    ///
+    /// ```ignore
+    /// let mut pairer = PpsPairer::new("/dev/pps0");
+    /// loop {
+    ///     let sentence = gps.update();
+    ///     if let Some(pair) = pairer.pair(&sentence) {
+    ///         println!("Host clock offset: {}", pair.offset);
+    ///     }
+    /// }
+    /// ```
+    pub struct PpsPairer {
+        pub pps_device: String,
+        /// The longest time, in seconds, between a pulse and the sentence for it. Default 0.9
+        pub max_delay: f64,
+        last_sequence: Option<u64>,
+    }
+
+    impl PpsPairer {
+        pub fn new(pps_device: &str) -> PpsPairer {
+            PpsPairer { pps_device: pps_device.to_string(), max_delay: 0.9, last_sequence: None }
+        }
+
+        /// Give each sentence read from the gps. Returns a pair for the first RMC or ZDA after each
+        /// new pulse.
+        pub fn pair(&mut self, sentence: &GpsSentence) -> Option<PpsPair> {
+            let utc = sentence_utc(sentence)?;
+            let pulse = read_pps_assert(self.pps_device.as_str())?;
+            if self.last_sequence == Some(pulse.sequence) {
+                return None;
+            }
+            let now = UtcDateTime::now().to_unix();
+            if now - pulse.as_secs_f64() > self.max_delay {
+                return None;
+            }
+            self.last_sequence = Some(pulse.sequence);
+            Some(PpsPair { offset: utc.to_unix() - pulse.as_secs_f64(), pulse, utc })
+        }
+    }
+}
+
+#[cfg(test)]
+mod utc_test {
+    use crate::nmea::rmc::RmcData;
+    use crate::timing::utc::UtcDateTime;
+
+    #[test]
+    fn unix_round_trip() {
+        let date = UtcDateTime::from_unix(1_590_000_000.25);
+        assert_eq!(date, UtcDateTime { year: 2020, month: 5, day: 20, hour: 18, minute: 40, second: 0.25 });
+        assert_eq!(date.to_unix(), 1_590_000_000.25);
+    }
+
+    #[test]
+    fn iso8601() {
+        assert_eq!(UtcDateTime::from_unix(1_590_000_000.25).to_iso8601(), "2020-05-20T18:40:00.250Z");
+        let date = UtcDateTime { year: 2020, month: 5, day: 20, hour: 11, minute: 9, second: 59.9999 };
+        assert_eq!(date.to_iso8601(), "2020-05-20T11:09:59.999Z");
+    }
+
+    #[test]
+    fn from_rmc() {
+        let rmc = RmcData { utc: 184000.0, date: "200520".to_string(), ..RmcData::default() };
+        assert_eq!(UtcDateTime::from_rmc(&rmc).unwrap().to_unix(), 1_590_000_000.0);
+        assert_eq!(UtcDateTime::from_rmc(&RmcData::default()), None);
+    }
+}
+
+#[cfg(test)]
+mod pps_test {
+    use std::fs::{remove_file, write};
+
+    use crate::timing::pps::{pair_pps, parse_pps_assert, read_pps_file, read_timestamped_sentences, PpsTimestamp};
+
+    #[test]
+    fn parse_assert() {
+        assert_eq!(
+            parse_pps_assert("1590000000.000012345#42\n"),
+            Some(PpsTimestamp { seconds: 1_590_000_000, nanos: 12345, sequence: 42 })
+        );
+        assert_eq!(parse_pps_assert("0.000000000#0"), Some(PpsTimestamp { seconds: 0, nanos: 0, sequence: 0 }));
+        assert_eq!(parse_pps_assert(""), None);
+    }
+
+    #[test]
+    fn pair_synthetic_files() {
+        // Host clock is 0.2 s behind UTC. The later pulses have no RMC in time.
+        write("pps_test_assert", "1589999999.800000000#1\n1590000000.800000000#2\n1590000005.800000000#3\n").unwrap();
+        write("pps_test_nmea", "\
+1589999999.900000000 $GPGGA,184000.000,5132.7314,N,00005.9099,W,1,9,1.17,42.4,M,47.0,M,,*74
+1589999999.950000000 $GPRMC,184000.000,A,5132.7314,N,00005.9099,W,0.12,0.00,200520,,,A*7F
+1590005000.000000000 $GPRMC,184500.000,A,5132.7314,N,00005.9099,W,0.12,0.00,200520,,,A*7A
+").unwrap();
+        let pulses = read_pps_file("pps_test_assert");
+        let sentences = read_timestamped_sentences("pps_test_nmea");
+        let _ = remove_file("pps_test_assert");
+        let _ = remove_file("pps_test_nmea");
+
+        let pairs = pair_pps(&pulses, &sentences, 0.9);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].pulse.sequence, 1);
+        assert_eq!(pairs[0].utc.to_unix(), 1_590_000_000.0);
+        assert!((pairs[0].offset - 0.2).abs() < 1e-6);
+    }
+}
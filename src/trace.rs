@@ -0,0 +1,408 @@
+//! # Wire trace
+//! Every command sent to the gps and every line read from it, with the time and whether the
+//! checksum is right. Saves running `cat /dev/serial0` in another terminal to see what the gps is
+//! doing.
+//!
+//! Give the gps a TraceSink with gps.set_trace(sink). The built in sinks are:
+//! - FileSink -> One line per event in a file. read_trace reads it back, and ReplayPort gives the
+//!   received lines to Gps::from_port so a session can be run again without the gps.
+//! - LogSink -> The log crate, with the `log` feature.
+//! - TracingSink -> The tracing crate, with the `tracing` feature.
+//! ```ignore
+//! gps.set_trace(FileSink::create("wire.trace")?);
+//! gps.pmtk_220_set_nmea_updaterate("1000");
+//!
+//! let mut replay = Gps::from_port(Box::new(ReplayPort::from_file("wire.trace")?));
+//! let sentence = replay.update();
+//! ```
+
+pub mod wire_trace {
+    //! TraceEvent, the TraceSink trait and the built in sinks.
+    use std::collections::VecDeque;
+    use std::fmt;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use serialport::prelude::*;
+    use serialport::{ClearBuffer, Error, ErrorKind};
+
+    use crate::open_gps::gps::{is_valid_checksum, Gps};
+
+    /// Sent is a command written to the gps, Received a line read from it.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum Direction {
+        Sent,
+        Received,
+    }
+
+    /// Missing is a line with no *checksum, such as a line cut short or bytes at the wrong baud rate.
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub enum ChecksumStatus {
+        Valid,
+        Invalid,
+        Missing,
+    }
+
+    impl ChecksumStatus {
+        /// The checksum status of a line, with or without the \r\n.
+        pub fn of(line: &str) -> ChecksumStatus {
+            let trimmed = line.trim();
+            if !trimmed.contains('*') {
+                ChecksumStatus::Missing
+            } else if trimmed.len() > 3 && trimmed.is_char_boundary(trimmed.len() - 3)
+                && is_valid_checksum(trimmed) {
+                ChecksumStatus::Valid
+            } else {
+                ChecksumStatus::Invalid
+            }
+        }
+    }
+
+    /// One line on the wire.
+    ///
+    /// - time: When it was sent or read.
+    /// - line: Exactly as sent or read, including the \r\n. Bytes that aren't utf8 are replaced
+    ///   with U+FFFD.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct TraceEvent {
+        pub time: SystemTime,
+        pub direction: Direction,
+        pub line: String,
+        pub checksum: ChecksumStatus,
+    }
+
+    impl TraceEvent {
+        /// An event for a line sent or read now.
+        pub fn new(direction: Direction, line: &str) -> TraceEvent {
+            TraceEvent {
+                time: SystemTime::now(),
+                direction,
+                line: line.to_string(),
+                checksum: ChecksumStatus::of(line),
+            }
+        }
+
+        /// The event as one line of a trace file, without the newline:
+        ///
+        /// {seconds since 1970}.{microseconds} {tx|rx} {ok|bad|none} {line}
+        ///
+        /// Backslashes, \r and \n in the line are escaped so each event is one line.
+        pub fn to_trace_line(&self) -> String {
+            let since_epoch = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let direction = match self.direction {
+                Direction::Sent => "tx",
+                Direction::Received => "rx",
+            };
+            let checksum = match self.checksum {
+                ChecksumStatus::Valid => "ok",
+                ChecksumStatus::Invalid => "bad",
+                ChecksumStatus::Missing => "none",
+            };
+            let line = self.line.replace('\\', "\\\\").replace('\r', "\\r").replace('\n', "\\n");
+            format!("{}.{:06} {} {} {}", since_epoch.as_secs(), since_epoch.subsec_micros(), direction, checksum,
+                    line)
+        }
+
+        /// Reads a line given by to_trace_line. None if it isn't one.
+        pub fn from_trace_line(trace_line: &str) -> Option<TraceEvent> {
+            let mut fields = trace_line.splitn(4, ' ');
+            let (secs, micros) = fields.next()?.split_once('.')?;
+            let time = UNIX_EPOCH + Duration::from_secs(secs.parse().ok()?)
+                + Duration::from_micros(micros.parse().ok()?);
+            let direction = match fields.next()? {
+                "tx" => Direction::Sent,
+                "rx" => Direction::Received,
+                _ => return None,
+            };
+            let checksum = match fields.next()? {
+                "ok" => ChecksumStatus::Valid,
+                "bad" => ChecksumStatus::Invalid,
+                "none" => ChecksumStatus::Missing,
+                _ => return None,
+            };
+            let mut line = String::new();
+            let mut chars = fields.next()?.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    line.push(c);
+                    continue;
+                }
+                match chars.next()? {
+                    'r' => line.push('\r'),
+                    'n' => line.push('\n'),
+                    '\\' => line.push('\\'),
+                    _ => return None,
+                }
+            }
+            Some(TraceEvent { time, direction, line, checksum })
+        }
+    }
+
+    impl fmt::Display for TraceEvent {
+        /// {tx|rx} {line}, with the checksum status if it isn't valid.
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let direction = match self.direction {
+                Direction::Sent => "tx",
+                Direction::Received => "rx",
+            };
+            match self.checksum {
+                ChecksumStatus::Valid => write!(f, "{} {}", direction, self.line.trim_end()),
+                ChecksumStatus::Invalid => write!(f, "{} {} (bad checksum)", direction, self.line.trim_end()),
+                ChecksumStatus::Missing => write!(f, "{} {} (no checksum)", direction, self.line.trim_end()),
+            }
+        }
+    }
+
+    /// Something that is given every line sent to and read from the gps.
+    pub trait TraceSink: Send {
+        fn trace(&mut self, event: &TraceEvent);
+    }
+
+    /// Writes each event to a file as a line, see TraceEvent::to_trace_line. Write errors are ignored
+    /// so a full disk doesn't stop the gps.
+    pub struct FileSink {
+        file: File,
+    }
+
+    impl FileSink {
+        /// Starts a new trace file, replacing any file already there.
+        pub fn create(path: &str) -> io::Result<FileSink> {
+            Ok(FileSink { file: File::create(path)? })
+        }
+
+        /// Adds to the end of a trace file.
+        pub fn append(path: &str) -> io::Result<FileSink> {
+            Ok(FileSink { file: OpenOptions::new().create(true).append(true).open(path)? })
+        }
+    }
+
+    impl TraceSink for FileSink {
+        fn trace(&mut self, event: &TraceEvent) {
+            let _ = writeln!(self.file, "{}", event.to_trace_line());
+        }
+    }
+
+    /// Reads a file written by FileSink. Lines that can't be read are skipped.
+    pub fn read_trace(path: &str) -> io::Result<Vec<TraceEvent>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut events = Vec::new();
+        for line in reader.lines() {
+            if let Some(event) = TraceEvent::from_trace_line(line?.as_str()) {
+                events.push(event);
+            }
+        }
+        Ok(events)
+    }
+
+    /// Logs each event to the log crate, under the target adafruit_gps::wire. Debug level by default.
+    #[cfg(feature = "log")]
+    pub struct LogSink {
+        pub level: log::Level,
+    }
+
+    #[cfg(feature = "log")]
+    impl Default for LogSink {
+        fn default() -> LogSink {
+            LogSink { level: log::Level::Debug }
+        }
+    }
+
+    #[cfg(feature = "log")]
+    impl TraceSink for LogSink {
+        fn trace(&mut self, event: &TraceEvent) {
+            log::log!(target: "adafruit_gps::wire", self.level, "{}", event);
+        }
+    }
+
+    /// Gives each event as a tracing debug event, under the target adafruit_gps::wire, with the
+    /// direction and checksum status as fields.
+    #[cfg(feature = "tracing")]
+    #[derive(Default)]
+    pub struct TracingSink;
+
+    #[cfg(feature = "tracing")]
+    impl TraceSink for TracingSink {
+        fn trace(&mut self, event: &TraceEvent) {
+            tracing::debug!(target: "adafruit_gps::wire", direction = ?event.direction,
+                            checksum = ?event.checksum, "{}", event.line.trim_end());
+        }
+    }
+
+    /// A port that gives the received lines of a trace, in order, then times out. Anything written
+    /// to it is dropped.
+    pub struct ReplayPort {
+        input: VecDeque<u8>,
+    }
+
+    impl ReplayPort {
+        /// A port that gives the received lines of the events.
+        pub fn new(events: &[TraceEvent]) -> ReplayPort {
+            let input = events.iter()
+                .filter(|e| e.direction == Direction::Received)
+                .flat_map(|e| e.line.as_bytes().to_vec())
+                .collect();
+            ReplayPort { input }
+        }
+
+        /// A port that gives the received lines of a file written by FileSink.
+        pub fn from_file(path: &str) -> io::Result<ReplayPort> {
+            Ok(ReplayPort::new(&read_trace(path)?))
+        }
+    }
+
+    impl Read for ReplayPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.input.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "End of trace"));
+            }
+            let mut read = 0;
+            while read < buf.len() {
+                match self.input.pop_front() {
+                    Some(byte) => buf[read] = byte,
+                    None => break,
+                }
+                read += 1;
+            }
+            Ok(read)
+        }
+    }
+
+    impl Write for ReplayPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SerialPort for ReplayPort {
+        fn name(&self) -> Option<String> { Some("replay".to_string()) }
+        fn settings(&self) -> SerialPortSettings { SerialPortSettings::default() }
+        fn baud_rate(&self) -> serialport::Result<u32> { Ok(9600) }
+        fn data_bits(&self) -> serialport::Result<DataBits> { Ok(DataBits::Eight) }
+        fn flow_control(&self) -> serialport::Result<FlowControl> { Ok(FlowControl::None) }
+        fn parity(&self) -> serialport::Result<Parity> { Ok(Parity::None) }
+        fn stop_bits(&self) -> serialport::Result<StopBits> { Ok(StopBits::One) }
+        fn timeout(&self) -> Duration { Duration::from_millis(1) }
+        fn set_all(&mut self, _: &SerialPortSettings) -> serialport::Result<()> { Ok(()) }
+        fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> { Ok(()) }
+        fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> { Ok(()) }
+        fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> { Ok(()) }
+        fn set_parity(&mut self, _: Parity) -> serialport::Result<()> { Ok(()) }
+        fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> { Ok(()) }
+        fn set_timeout(&mut self, _: Duration) -> serialport::Result<()> { Ok(()) }
+        fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> { Ok(()) }
+        fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> { Ok(()) }
+        fn read_clear_to_send(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_data_set_ready(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_ring_indicator(&mut self) -> serialport::Result<bool> { Ok(false) }
+        fn read_carrier_detect(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn bytes_to_read(&self) -> serialport::Result<u32> { Ok(self.input.len() as u32) }
+        fn bytes_to_write(&self) -> serialport::Result<u32> { Ok(0) }
+        fn clear(&self, _: ClearBuffer) -> serialport::Result<()> { Ok(()) }
+        fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+            Err(Error::new(ErrorKind::Unknown, "Replay port can't be cloned"))
+        }
+    }
+
+    impl Gps {
+        /// Gives every line sent and read to sink, replacing any sink already set.
+        pub fn set_trace(&mut self, sink: impl TraceSink + 'static) {
+            self.trace = Some(Box::new(sink));
+        }
+
+        /// Stops tracing, giving back the sink.
+        pub fn take_trace(&mut self) -> Option<Box<dyn TraceSink>> {
+            self.trace.take()
+        }
+
+        /// Gives the line to the trace sink, if there is one.
+        pub(crate) fn trace_line(&mut self, direction: Direction, line: &str) {
+            if let Some(sink) = self.trace.as_mut() {
+                sink.trace(&TraceEvent::new(direction, line));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod trace_test {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::open_gps::gps::{Gps, GpsSentence};
+    use crate::open_gps::mock_port::{mock_gps, GGA};
+    use crate::pmtk::send_pmtk::add_checksum;
+
+    use super::wire_trace::{read_trace, ChecksumStatus, Direction, FileSink, ReplayPort, TraceEvent, TraceSink};
+
+    struct Collect(Arc<Mutex<Vec<TraceEvent>>>);
+
+    impl TraceSink for Collect {
+        fn trace(&mut self, event: &TraceEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn checksum_status() {
+        assert_eq!(ChecksumStatus::of(GGA), ChecksumStatus::Valid);
+        assert_eq!(ChecksumStatus::of("$GNGGA,131613.000*61\r\n"), ChecksumStatus::Invalid);
+        assert_eq!(ChecksumStatus::of("$GNGGA,1316"), ChecksumStatus::Missing);
+        assert_eq!(ChecksumStatus::of("*"), ChecksumStatus::Invalid);
+    }
+
+    #[test]
+    fn trace_line_round_trip() {
+        let event = TraceEvent {
+            time: UNIX_EPOCH + Duration::from_micros(1_590_000_000_123_456),
+            direction: Direction::Received,
+            line: "$GN\\GGA*00\r\n".to_string(),
+            checksum: ChecksumStatus::Invalid,
+        };
+        assert_eq!(event.to_trace_line(), "1590000000.123456 rx bad $GN\\\\GGA*00\\r\\n");
+        assert_eq!(TraceEvent::from_trace_line(event.to_trace_line().as_str()), Some(event));
+        assert_eq!(TraceEvent::from_trace_line("1590000000.123456 up ok $GNGGA"), None);
+    }
+
+    #[test]
+    fn commands_and_lines_are_traced() {
+        let ack = add_checksum("PMTK001,220,3".to_string());
+        let (mut gps, _written) = mock_gps(&[GGA, ack.as_str()]);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        gps.set_trace(Collect(events.clone()));
+
+        gps.pmtk_220_set_nmea_updaterate("1000");
+        let events = events.lock().unwrap();
+        let sent = add_checksum("PMTK220,1000".to_string());
+        let seen: Vec<(Direction, &str, ChecksumStatus)> =
+            events.iter().map(|e| (e.direction, e.line.as_str(), e.checksum)).collect();
+        assert_eq!(seen, vec![
+            (Direction::Sent, sent.as_str(), ChecksumStatus::Valid),
+            (Direction::Received, GGA, ChecksumStatus::Valid),
+            (Direction::Received, ack.as_str(), ChecksumStatus::Valid),
+        ]);
+    }
+
+    #[test]
+    fn file_trace_replays() {
+        let (mut gps, _written) = mock_gps(&[GGA]);
+        gps.set_trace(FileSink::create("trace_test").unwrap());
+        gps.send_command("PMTK220,1000").unwrap();
+        let sentence = gps.update();
+        assert!(gps.take_trace().is_some());
+
+        let events = read_trace("trace_test").unwrap();
+        let port = ReplayPort::from_file("trace_test").unwrap();
+        let _ = std::fs::remove_file("trace_test");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].direction, Direction::Sent);
+
+        let mut replay = Gps::from_port(Box::new(port));
+        assert_eq!(replay.update(), sentence);
+        assert!(matches!(replay.update(), GpsSentence::NoConnection));
+    }
+}